@@ -106,7 +106,7 @@ fn main() {
 
         #[cfg(feature = "renderdoc")]
         rd.end_frame_capture(std::ptr::null(), std::ptr::null());
-        gfx_select!(device => global.device_poll(device, true)).unwrap();
+        gfx_select!(device => global.device_poll(device, wgc::device::Maintain::Wait)).unwrap();
     }
     #[cfg(feature = "winit")]
     {
@@ -162,7 +162,7 @@ fn main() {
                 },
                 Event::LoopDestroyed => {
                     log::info!("Closing");
-                    gfx_select!(device => global.device_poll(device, true));
+                    gfx_select!(device => global.device_poll(device, wgc::device::Maintain::Wait));
                 }
                 _ => {}
             }
@@ -109,16 +109,47 @@ impl GlobalPlay for wgc::hub::Global<IdentityPassThroughFactory> {
                 } => {
                     self.command_encoder_run_render_pass_impl::<B>(
                         encoder,
-                        base.as_ref(),
+                        &[base.as_ref()],
                         &target_colors,
                         target_depth_stencil.as_ref(),
+                        &wgc::command::run_sub_passes_inline,
                     )
                     .unwrap();
                 }
+                trace::Command::RunParallelRenderPass {
+                    bases,
+                    target_colors,
+                    target_depth_stencil,
+                } => {
+                    let bases = bases.iter().map(|base| base.as_ref()).collect::<Vec<_>>();
+                    self.command_encoder_run_render_pass_impl::<B>(
+                        encoder,
+                        &bases,
+                        &target_colors,
+                        target_depth_stencil.as_ref(),
+                        &wgc::command::run_sub_passes_inline,
+                    )
+                    .unwrap();
+                }
+                trace::Command::PushDebugGroup(label) => self
+                    .command_encoder_push_debug_group::<B>(encoder, &label)
+                    .unwrap(),
+                trace::Command::PopDebugGroup => {
+                    self.command_encoder_pop_debug_group::<B>(encoder).unwrap()
+                }
+                trace::Command::InsertDebugMarker(label) => self
+                    .command_encoder_insert_debug_marker::<B>(encoder, &label)
+                    .unwrap(),
             }
         }
-        self.command_encoder_finish::<B>(encoder, &wgt::CommandBufferDescriptor { label: None })
-            .unwrap()
+        self.command_encoder_finish::<B>(
+            encoder,
+            &wgt::CommandBufferDescriptor {
+                label: None,
+                reusable: false,
+            },
+        )
+        .unwrap()
     }
 
     fn process<B: wgc::hub::GfxBackend>(
@@ -198,12 +229,24 @@ impl GlobalPlay for wgc::hub::Global<IdentityPassThroughFactory> {
             A::DestroyBindGroup(id) => {
                 self.bind_group_drop::<B>(id);
             }
-            A::CreateShaderModule { id, data } => {
+            A::CreateShaderModule { id, data, glsl } => {
+                let path = dir.join(&data);
                 let source = if data.ends_with(".wgsl") {
-                    let code = fs::read_to_string(dir.join(data)).unwrap();
+                    let code = fs::read_to_string(path).unwrap();
                     wgc::pipeline::ShaderModuleSource::Wgsl(Cow::Owned(code))
+                } else if data.ends_with(".glsl") {
+                    let code = fs::read_to_string(path).unwrap();
+                    let options = glsl.expect("GLSL shader module without options");
+                    wgc::pipeline::ShaderModuleSource::Glsl {
+                        source: Cow::Owned(code),
+                        stage: options.stage,
+                        defines: options.defines,
+                    }
+                } else if data.ends_with(".ron") {
+                    let string = fs::read_to_string(path).unwrap();
+                    wgc::pipeline::ShaderModuleSource::Naga(ron::de::from_str(&string).unwrap())
                 } else {
-                    let byte_vec = fs::read(dir.join(data)).unwrap();
+                    let byte_vec = fs::read(path).unwrap();
                     let spv = byte_vec
                         .chunks(4)
                         .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
@@ -245,6 +288,18 @@ impl GlobalPlay for wgc::hub::Global<IdentityPassThroughFactory> {
             A::DestroyRenderBundle(id) => {
                 self.render_bundle_drop::<B>(id);
             }
+            A::CreateComputeBundle { id, desc, base } => {
+                let bundle = wgc::command::ComputeBundleEncoder::new(&desc, device, Some(base));
+                self.compute_bundle_encoder_finish::<B>(
+                    bundle,
+                    &wgt::ComputeBundleDescriptor { label: desc.label },
+                    id,
+                )
+                .unwrap();
+            }
+            A::DestroyComputeBundle(id) => {
+                self.compute_bundle_drop::<B>(id);
+            }
             A::WriteBuffer {
                 id,
                 data,
@@ -276,7 +331,10 @@ impl GlobalPlay for wgc::hub::Global<IdentityPassThroughFactory> {
                 let encoder = self
                     .device_create_command_encoder::<B>(
                         device,
-                        &wgt::CommandEncoderDescriptor { label: None },
+                        &wgt::CommandEncoderDescriptor {
+                            label: None,
+                            reusable: false,
+                        },
                         comb_manager.alloc(device.backend()),
                     )
                     .unwrap();
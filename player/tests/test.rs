@@ -74,7 +74,8 @@ impl Test<'_> {
             &wgt::DeviceDescriptor {
                 features: self.features | wgt::Features::MAPPABLE_PRIMARY_BUFFERS,
                 limits: wgt::Limits::default(),
-                shader_validation: true,
+                shader_validation: wgt::ShaderValidation::Strict,
+                max_submissions_in_flight: None,
             },
             None,
             wgc::id::TypedId::zip(1, 0, backend)
@@ -94,15 +95,17 @@ impl Test<'_> {
                 expect.offset .. expect.offset+expect.data.len() as wgt::BufferAddress,
                 wgc::resource::BufferMapOperation {
                     host: wgc::device::HostMap::Read,
-                    callback: map_callback,
-                    user_data: ptr::null_mut(),
+                    callback: wgc::resource::BufferMapClosure::C {
+                        callback: map_callback,
+                        user_data: ptr::null_mut(),
+                    },
                 }
             ))
             .unwrap();
         }
 
         println!("\t\t\tWaiting...");
-        gfx_select!(device => global.device_poll(device, true)).unwrap();
+        gfx_select!(device => global.device_poll(device, wgc::device::Maintain::Wait)).unwrap();
 
         for expect in self.expectations {
             println!("\t\t\tChecking {}", expect.name);
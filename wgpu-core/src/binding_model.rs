@@ -74,10 +74,15 @@ pub enum CreateBindGroupError {
     SingleBindingExpected,
     #[error("unable to create a bind group with a swap chain image")]
     SwapChainImage,
-    #[error("buffer offset {0} does not respect `BIND_BUFFER_ALIGNMENT`")]
-    UnalignedBufferOffset(wgt::BufferAddress),
+    #[error("buffer offset {offset} is not a multiple of the required alignment {alignment}")]
+    UnalignedBufferOffset {
+        offset: wgt::BufferAddress,
+        alignment: u32,
+    },
     #[error("uniform buffer binding range exceeds `max_uniform_buffer_binding_size` limit")]
     UniformBufferRangeTooLarge,
+    #[error("storage buffer binding range exceeds `max_storage_buffer_binding_size` limit")]
+    StorageBufferRangeTooLarge,
     #[error("binding {binding} has a different type ({actual:?}) than the one in the layout ({expected:?})")]
     WrongBindingType {
         // Index of the binding
@@ -314,6 +319,7 @@ pub struct BindGroupLayout<B: hal::Backend> {
     pub(crate) desc_counts: DescriptorCounts,
     pub(crate) dynamic_count: usize,
     pub(crate) count_validator: BindingTypeMaxCountValidator,
+    pub(crate) label: String,
 }
 
 #[derive(Clone, Debug, Error)]
@@ -512,10 +518,12 @@ pub enum BindingResource<'a> {
 pub enum BindError {
     #[error("number of dynamic offsets ({actual}) doesn't match the number of dynamic bindings in the bind group layout ({expected})")]
     MismatchedDynamicOffsetCount { actual: usize, expected: usize },
-    #[error(
-        "dynamic binding at index {idx}: offset {offset} does not respect `BIND_BUFFER_ALIGNMENT`"
-    )]
-    UnalignedDynamicBinding { idx: usize, offset: u32 },
+    #[error("dynamic binding at index {idx}: offset {offset} is not a multiple of the required alignment {alignment}")]
+    UnalignedDynamicBinding {
+        idx: usize,
+        offset: u32,
+        alignment: u32,
+    },
     #[error("dynamic binding at index {idx} with offset {offset} would overrun the buffer (limit: {max})")]
     DynamicBindingOutOfBounds { idx: usize, offset: u32, max: u64 },
 }
@@ -524,6 +532,8 @@ pub enum BindError {
 pub struct BindGroupDynamicBindingData {
     /// The maximum value the dynamic offset can have before running off the end of the buffer.
     pub(crate) maximum_dynamic_offset: wgt::BufferAddress,
+    /// The alignment required by the device for offsets of this kind of binding.
+    pub(crate) alignment: u32,
 }
 
 #[derive(Debug)]
@@ -554,8 +564,12 @@ impl<B: hal::Backend> BindGroup<B> {
             .zip(offsets.iter())
             .enumerate()
         {
-            if offset as wgt::BufferAddress % wgt::BIND_BUFFER_ALIGNMENT != 0 {
-                return Err(BindError::UnalignedDynamicBinding { idx, offset });
+            if offset % info.alignment != 0 {
+                return Err(BindError::UnalignedDynamicBinding {
+                    idx,
+                    offset,
+                    alignment: info.alignment,
+                });
             }
 
             if offset as wgt::BufferAddress > info.maximum_dynamic_offset {
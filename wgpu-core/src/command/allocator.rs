@@ -21,49 +21,67 @@ struct CommandPool<B: hal::Backend> {
     raw: B::CommandPool,
     total: usize,
     available: Vec<B::CommandBuffer>,
-    pending: Vec<(B::CommandBuffer, SubmissionIndex)>,
+    available_secondary: Vec<B::CommandBuffer>,
+    pending: Vec<(B::CommandBuffer, hal::command::Level, SubmissionIndex)>,
 }
 
 impl<B: hal::Backend> CommandPool<B> {
     fn maintain(&mut self, last_done_index: SubmissionIndex) {
         for i in (0..self.pending.len()).rev() {
-            if self.pending[i].1 <= last_done_index {
-                let (cmd_buf, index) = self.pending.swap_remove(i);
+            if self.pending[i].2 <= last_done_index {
+                let (cmd_buf, level, index) = self.pending.swap_remove(i);
                 tracing::trace!(
                     "recycling cmdbuf submitted in {} when {} is last done",
                     index,
                     last_done_index,
                 );
-                self.recycle(cmd_buf);
+                self.recycle(cmd_buf, level);
             }
         }
     }
 
-    fn recycle(&mut self, mut raw: B::CommandBuffer) {
+    fn recycle(&mut self, mut raw: B::CommandBuffer, level: hal::command::Level) {
         unsafe {
             raw.reset(false);
         }
-        self.available.push(raw);
+        match level {
+            hal::command::Level::Primary => self.available.push(raw),
+            hal::command::Level::Secondary => self.available_secondary.push(raw),
+        }
     }
 
-    fn allocate(&mut self) -> B::CommandBuffer {
-        if self.available.is_empty() {
+    fn allocate(&mut self, level: hal::command::Level) -> B::CommandBuffer {
+        let available = match level {
+            hal::command::Level::Primary => &mut self.available,
+            hal::command::Level::Secondary => &mut self.available_secondary,
+        };
+        if available.is_empty() {
             self.total += GROW_AMOUNT;
-            unsafe {
-                self.raw.allocate(
-                    GROW_AMOUNT,
-                    hal::command::Level::Primary,
-                    &mut self.available,
-                )
-            };
+            unsafe { self.raw.allocate(GROW_AMOUNT, level, available) };
         }
-        self.available.pop().unwrap()
+        available.pop().unwrap()
+    }
+
+    fn is_idle(&self) -> bool {
+        self.total == self.available.len() + self.available_secondary.len()
+    }
+
+    unsafe fn free(mut self, device: &B::Device) {
+        self.raw.free(self.available);
+        self.raw.free(self.available_secondary);
+        device.destroy_command_pool(self.raw);
     }
 }
 
 #[derive(Debug)]
 struct Inner<B: hal::Backend> {
     pools: FastHashMap<thread::ThreadId, CommandPool<B>>,
+    /// Pools of the secondary command buffers, which may be recorded on any thread.
+    /// A pool is checked out for the duration of the recording, so that it's
+    /// never used by two threads at once, and reused afterwards.
+    secondary_pools: Vec<CommandPool<B>>,
+    /// Indices of the secondary pools that aren't checked out.
+    idle_secondary_pools: Vec<usize>,
 }
 
 #[derive(Debug)]
@@ -73,6 +91,45 @@ pub struct CommandAllocator<B: hal::Backend> {
     inner: Mutex<Inner<B>>,
 }
 
+fn create_pool<B: hal::Backend>(
+    queue_family: hal::queue::QueueFamilyId,
+    device: &B::Device,
+) -> Result<CommandPool<B>, CommandAllocatorError> {
+    let raw = unsafe {
+        device
+            .create_command_pool(
+                queue_family,
+                hal::pool::CommandPoolCreateFlags::RESET_INDIVIDUAL,
+            )
+            .or(Err(DeviceError::OutOfMemory))?
+    };
+    Ok(CommandPool {
+        raw,
+        total: 0,
+        available: Vec::new(),
+        available_secondary: Vec::new(),
+        pending: Vec::new(),
+    })
+}
+
+impl<B: hal::Backend> Inner<B> {
+    fn pool_for_thread(
+        &mut self,
+        thread_id: thread::ThreadId,
+        queue_family: hal::queue::QueueFamilyId,
+        device: &B::Device,
+    ) -> Result<&mut CommandPool<B>, CommandAllocatorError> {
+        use std::collections::hash_map::Entry;
+        Ok(match self.pools.entry(thread_id) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                tracing::info!("Starting on thread {:?}", thread_id);
+                e.insert(create_pool(queue_family, device)?)
+            }
+        })
+    }
+}
+
 impl<B: GfxBackend> CommandAllocator<B> {
     pub(crate) fn allocate(
         &self,
@@ -85,35 +142,16 @@ impl<B: GfxBackend> CommandAllocator<B> {
         //debug_assert_eq!(device_id.backend(), B::VARIANT);
         let thread_id = thread::current().id();
         let mut inner = self.inner.lock();
-
-        use std::collections::hash_map::Entry;
-        let pool = match inner.pools.entry(thread_id) {
-            Entry::Occupied(e) => e.into_mut(),
-            Entry::Vacant(e) => {
-                tracing::info!("Starting on thread {:?}", thread_id);
-                let raw = unsafe {
-                    device
-                        .create_command_pool(
-                            self.queue_family,
-                            hal::pool::CommandPoolCreateFlags::RESET_INDIVIDUAL,
-                        )
-                        .or(Err(DeviceError::OutOfMemory))?
-                };
-                let pool = CommandPool {
-                    raw,
-                    total: 0,
-                    available: Vec::new(),
-                    pending: Vec::new(),
-                };
-                e.insert(pool)
-            }
-        };
-
-        let init = pool.allocate();
+        let pool = inner.pool_for_thread(thread_id, self.queue_family, device)?;
+        let init = pool.allocate(hal::command::Level::Primary);
 
         Ok(CommandBuffer {
             raw: vec![init],
+            secondary: Vec::new(),
             is_recording: true,
+            reusable: false,
+            submission_index: None,
+            debug_scope_depth: 0,
             recorded_thread_id: thread_id,
             device_id,
             trackers: TrackerSet::new(B::VARIANT),
@@ -138,40 +176,69 @@ impl<B: hal::Backend> CommandAllocator<B> {
         let internal_thread_id = thread::current().id();
         tracing::info!("Starting on (internal) thread {:?}", internal_thread_id);
         let mut pools = FastHashMap::default();
-        pools.insert(
-            internal_thread_id,
-            CommandPool {
-                raw: unsafe {
-                    device
-                        .create_command_pool(
-                            queue_family,
-                            hal::pool::CommandPoolCreateFlags::RESET_INDIVIDUAL,
-                        )
-                        .or(Err(DeviceError::OutOfMemory))?
-                },
-                total: 0,
-                available: Vec::new(),
-                pending: Vec::new(),
-            },
-        );
+        pools.insert(internal_thread_id, create_pool(queue_family, device)?);
         Ok(CommandAllocator {
             queue_family,
             internal_thread_id,
-            inner: Mutex::new(Inner { pools }),
+            inner: Mutex::new(Inner {
+                pools,
+                secondary_pools: Vec::new(),
+                idle_secondary_pools: Vec::new(),
+            }),
         })
     }
 
-    fn allocate_for_thread_id(&self, thread_id: thread::ThreadId) -> B::CommandBuffer {
+    fn allocate_for_thread_id(
+        &self,
+        thread_id: thread::ThreadId,
+        level: hal::command::Level,
+    ) -> B::CommandBuffer {
         let mut inner = self.inner.lock();
-        inner.pools.get_mut(&thread_id).unwrap().allocate()
+        inner.pools.get_mut(&thread_id).unwrap().allocate(level)
     }
 
     pub fn allocate_internal(&self) -> B::CommandBuffer {
-        self.allocate_for_thread_id(self.internal_thread_id)
+        self.allocate_for_thread_id(self.internal_thread_id, hal::command::Level::Primary)
     }
 
     pub fn extend(&self, cmd_buf: &CommandBuffer<B>) -> B::CommandBuffer {
-        self.allocate_for_thread_id(cmd_buf.recorded_thread_id)
+        self.allocate_for_thread_id(cmd_buf.recorded_thread_id, hal::command::Level::Primary)
+    }
+
+    /// Checks out an idle secondary pool, creating one if there is none,
+    /// and allocates a secondary command buffer from it.
+    ///
+    /// The command buffer can be recorded on any thread, in parallel with the others.
+    /// The pool has to be returned with `release_secondary_pool` once the recording is done.
+    pub(crate) fn allocate_secondary(
+        &self,
+        device: &B::Device,
+    ) -> Result<(usize, B::CommandBuffer), CommandAllocatorError> {
+        let mut inner = self.inner.lock();
+        let index = match inner.idle_secondary_pools.pop() {
+            Some(index) => index,
+            None => {
+                let pool = create_pool(self.queue_family, device)?;
+                inner.secondary_pools.push(pool);
+                inner.secondary_pools.len() - 1
+            }
+        };
+        let raw = inner.secondary_pools[index].allocate(hal::command::Level::Secondary);
+        Ok((index, raw))
+    }
+
+    pub(crate) fn release_secondary_pool(&self, index: usize) {
+        self.inner.lock().idle_secondary_pools.push(index);
+    }
+
+    /// Returns a secondary command buffer that will not be submitted.
+    pub(crate) fn discard_secondary(&self, index: usize, raw: B::CommandBuffer) {
+        let mut inner = self.inner.lock();
+        // The pool may be checked out by another thread, so the command buffer
+        // is only reset on the next `maintain`, like a completed submission.
+        inner.secondary_pools[index]
+            .pending
+            .push((raw, hal::command::Level::Secondary, 0));
     }
 
     pub fn discard_internal(&self, raw: B::CommandBuffer) {
@@ -180,7 +247,7 @@ impl<B: hal::Backend> CommandAllocator<B> {
             .pools
             .get_mut(&self.internal_thread_id)
             .unwrap()
-            .recycle(raw);
+            .recycle(raw, hal::command::Level::Primary);
     }
 
     pub fn discard(&self, mut cmd_buf: CommandBuffer<B>) {
@@ -188,7 +255,12 @@ impl<B: hal::Backend> CommandAllocator<B> {
         let mut inner = self.inner.lock();
         let pool = inner.pools.get_mut(&cmd_buf.recorded_thread_id).unwrap();
         for raw in cmd_buf.raw {
-            pool.recycle(raw);
+            pool.recycle(raw, hal::command::Level::Primary);
+        }
+        for (index, raw) in cmd_buf.secondary {
+            inner.secondary_pools[index]
+                .pending
+                .push((raw, hal::command::Level::Secondary, 0));
         }
     }
 
@@ -199,18 +271,26 @@ impl<B: hal::Backend> CommandAllocator<B> {
             .get_mut(&self.internal_thread_id)
             .unwrap()
             .pending
-            .push((raw, submit_index));
+            .push((raw, hal::command::Level::Primary, submit_index));
     }
 
     pub fn after_submit(&self, cmd_buf: CommandBuffer<B>, submit_index: SubmissionIndex) {
         // Record this command buffer as pending
         let mut inner = self.inner.lock();
-        inner
-            .pools
-            .get_mut(&cmd_buf.recorded_thread_id)
-            .unwrap()
-            .pending
-            .extend(cmd_buf.raw.into_iter().map(|raw| (raw, submit_index)));
+        let pool = inner.pools.get_mut(&cmd_buf.recorded_thread_id).unwrap();
+        pool.pending.extend(
+            cmd_buf
+                .raw
+                .into_iter()
+                .map(|raw| (raw, hal::command::Level::Primary, submit_index)),
+        );
+        for (index, raw) in cmd_buf.secondary {
+            inner.secondary_pools[index].pending.push((
+                raw,
+                hal::command::Level::Secondary,
+                submit_index,
+            ));
+        }
     }
 
     pub fn maintain(&self, device: &B::Device, last_done_index: SubmissionIndex) {
@@ -218,37 +298,53 @@ impl<B: hal::Backend> CommandAllocator<B> {
         let mut remove_threads = Vec::new();
         for (&thread_id, pool) in inner.pools.iter_mut() {
             pool.maintain(last_done_index);
-            if pool.total == pool.available.len() && thread_id != self.internal_thread_id {
+            if pool.is_idle() && thread_id != self.internal_thread_id {
                 assert!(pool.pending.is_empty());
                 remove_threads.push(thread_id);
             }
         }
         for thread_id in remove_threads {
             tracing::info!("Removing from thread {:?}", thread_id);
-            let mut pool = inner.pools.remove(&thread_id).unwrap();
+            let pool = inner.pools.remove(&thread_id).unwrap();
             unsafe {
-                pool.raw.free(pool.available);
-                device.destroy_command_pool(pool.raw);
+                pool.free(device);
             }
         }
+        // The checked out secondary pools are being recorded into.
+        let Inner {
+            ref mut secondary_pools,
+            ref idle_secondary_pools,
+            ..
+        } = *inner;
+        for &index in idle_secondary_pools {
+            secondary_pools[index].maintain(last_done_index);
+        }
     }
 
     pub fn destroy(self, device: &B::Device) {
         let mut inner = self.inner.lock();
-        for (_, mut pool) in inner.pools.drain() {
-            while let Some((raw, _)) = pool.pending.pop() {
-                pool.recycle(raw);
+        let Inner {
+            ref mut pools,
+            ref mut secondary_pools,
+            ..
+        } = *inner;
+        for mut pool in pools
+            .drain()
+            .map(|(_, pool)| pool)
+            .chain(secondary_pools.drain(..))
+        {
+            while let Some((raw, level, _)) = pool.pending.pop() {
+                pool.recycle(raw, level);
             }
-            if pool.total != pool.available.len() {
+            if !pool.is_idle() {
                 tracing::error!(
                     "Some command buffers are still recorded, only tracking {} / {}",
-                    pool.available.len(),
+                    pool.available.len() + pool.available_secondary.len(),
                     pool.total
                 );
             }
             unsafe {
-                pool.raw.free(pool.available);
-                device.destroy_command_pool(pool.raw);
+                pool.free(device);
             }
         }
     }
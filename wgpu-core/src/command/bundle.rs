@@ -38,7 +38,10 @@
 #![allow(clippy::reversed_empty_ranges)]
 
 use crate::{
-    command::{BasePass, DrawError, RenderCommand, RenderCommandError},
+    command::{
+        check_device_features, check_indirect_count_range, check_indirect_range, BasePass,
+        DrawError, RenderCommand, RenderCommandError,
+    },
     conv,
     device::{
         AttachmentData, DeviceError, RenderPassContext, MAX_VERTEX_BUFFERS, SHADER_STAGE_COUNT,
@@ -57,6 +60,7 @@ use std::{
     iter,
     marker::PhantomData,
     ops::Range,
+    str,
 };
 use thiserror::Error;
 
@@ -167,6 +171,7 @@ impl RenderBundle {
         use hal::command::CommandBuffer as _;
 
         let mut offsets = self.base.dynamic_offsets.as_slice();
+        let mut string_data = self.base.string_data.as_slice();
         let mut index_type = hal::IndexType::U16;
         let mut pipeline_layout_id = None::<id::Valid<id::PipelineLayoutId>>;
 
@@ -284,26 +289,60 @@ impl RenderBundle {
                 RenderCommand::MultiDrawIndirect {
                     buffer_id,
                     offset,
-                    count: None,
-                    indexed: false,
+                    count,
+                    indexed,
                 } => {
                     let buffer = buffer_guard.get(buffer_id).unwrap();
-                    cmd_buf.draw_indirect(&buffer.raw, offset, 1, 0);
+                    let stride = indirect_stride(indexed) as u32;
+                    let count = count.map_or(1, |c| c.get());
+                    match indexed {
+                        false => cmd_buf.draw_indirect(&buffer.raw, offset, count, stride),
+                        true => cmd_buf.draw_indexed_indirect(&buffer.raw, offset, count, stride),
+                    }
                 }
-                RenderCommand::MultiDrawIndirect {
+                RenderCommand::MultiDrawIndirectCount {
                     buffer_id,
                     offset,
-                    count: None,
-                    indexed: true,
+                    count_buffer_id,
+                    count_buffer_offset,
+                    max_count,
+                    indexed,
                 } => {
                     let buffer = buffer_guard.get(buffer_id).unwrap();
-                    cmd_buf.draw_indexed_indirect(&buffer.raw, offset, 1, 0);
+                    let count_buffer = buffer_guard.get(count_buffer_id).unwrap();
+                    let stride = indirect_stride(indexed) as u32;
+                    match indexed {
+                        false => cmd_buf.draw_indirect_count(
+                            &buffer.raw,
+                            offset,
+                            &count_buffer.raw,
+                            count_buffer_offset,
+                            max_count,
+                            stride,
+                        ),
+                        true => cmd_buf.draw_indexed_indirect_count(
+                            &buffer.raw,
+                            offset,
+                            &count_buffer.raw,
+                            count_buffer_offset,
+                            max_count,
+                            stride,
+                        ),
+                    }
+                }
+                RenderCommand::PushDebugGroup { color, len } => {
+                    let label = str::from_utf8(&string_data[..len]).unwrap();
+                    cmd_buf.begin_debug_marker(label, color);
+                    string_data = &string_data[len..];
+                }
+                RenderCommand::PopDebugGroup => {
+                    cmd_buf.end_debug_marker();
+                }
+                RenderCommand::InsertDebugMarker { color, len } => {
+                    let label = str::from_utf8(&string_data[..len]).unwrap();
+                    cmd_buf.insert_debug_marker(label, color);
+                    string_data = &string_data[len..];
                 }
-                RenderCommand::MultiDrawIndirect { .. }
-                | RenderCommand::MultiDrawIndirectCount { .. } => unimplemented!(),
-                RenderCommand::PushDebugGroup { color: _, len: _ } => unimplemented!(),
-                RenderCommand::InsertDebugMarker { color: _, len: _ } => unimplemented!(),
-                RenderCommand::PopDebugGroup => unimplemented!(),
                 RenderCommand::ExecuteBundle(_)
                 | RenderCommand::SetBlendColor(_)
                 | RenderCommand::SetStencilReference(_)
@@ -320,6 +359,14 @@ impl Borrow<RefCount> for RenderBundle {
     }
 }
 
+/// Size of a single indirect draw argument structure, in bytes.
+fn indirect_stride(indexed: bool) -> wgt::BufferAddress {
+    match indexed {
+        false => 16,
+        true => 20,
+    }
+}
+
 #[derive(Debug)]
 struct IndexState {
     buffer: Option<id::BufferId>,
@@ -484,9 +531,43 @@ struct State {
     raw_dynamic_offsets: Vec<wgt::DynamicOffset>,
     flat_dynamic_offsets: Vec<wgt::DynamicOffset>,
     used_bind_groups: usize,
+    debug_scope_depth: u32,
 }
 
 impl State {
+    fn new(backend: wgt::Backend) -> Self {
+        State {
+            trackers: TrackerSet::new(backend),
+            index: IndexState::new(),
+            vertex: (0..MAX_VERTEX_BUFFERS)
+                .map(|_| VertexState::new())
+                .collect(),
+            bind: (0..MAX_BIND_GROUPS).map(|_| BindState::new()).collect(),
+            push_constant_ranges: PushConstantState::new(),
+            raw_dynamic_offsets: Vec::new(),
+            flat_dynamic_offsets: Vec::new(),
+            used_bind_groups: 0,
+            debug_scope_depth: 0,
+        }
+    }
+
+    fn pop_debug_group(&mut self) -> Result<(), RenderBundleError> {
+        match self.debug_scope_depth.checked_sub(1) {
+            Some(depth) => {
+                self.debug_scope_depth = depth;
+                Ok(())
+            }
+            None => Err(RenderBundleError::InvalidPopDebugGroup),
+        }
+    }
+
+    fn check_debug_groups_closed(&self) -> Result<(), RenderBundleError> {
+        match self.debug_scope_depth {
+            0 => Ok(()),
+            depth => Err(RenderBundleError::UnclosedDebugGroups(depth)),
+        }
+    }
+
     fn vertex_limits(&self) -> (u32, u32) {
         let mut vertex_limit = !0;
         let mut instance_limit = !0;
@@ -631,6 +712,10 @@ pub enum RenderBundleError {
     RenderCommand(#[from] RenderCommandError),
     #[error(transparent)]
     Draw(#[from] DrawError),
+    #[error("cannot pop debug group, because number of pushed debug groups is zero")]
+    InvalidPopDebugGroup,
+    #[error("render bundle has {0} unclosed debug groups")]
+    UnclosedDebugGroups(u32),
 }
 
 impl<G: GlobalIdentityHandlerFactory> Global<G> {
@@ -654,18 +739,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             let (pipeline_guard, mut token) = hub.render_pipelines.read(&mut token);
             let (buffer_guard, _) = hub.buffers.read(&mut token);
 
-            let mut state = State {
-                trackers: TrackerSet::new(bundle_encoder.parent_id.backend()),
-                index: IndexState::new(),
-                vertex: (0..MAX_VERTEX_BUFFERS)
-                    .map(|_| VertexState::new())
-                    .collect(),
-                bind: (0..MAX_BIND_GROUPS).map(|_| BindState::new()).collect(),
-                push_constant_ranges: PushConstantState::new(),
-                raw_dynamic_offsets: Vec::new(),
-                flat_dynamic_offsets: Vec::new(),
-                used_bind_groups: 0,
-            };
+            let mut state = State::new(bundle_encoder.parent_id.backend());
             let mut commands = Vec::new();
             let mut base = bundle_encoder.base.as_ref();
             let mut pipeline_layout_id = None::<id::Valid<id::PipelineLayoutId>>;
@@ -688,14 +762,6 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         let offsets = &base.dynamic_offsets[..num_dynamic_offsets as usize];
                         base.dynamic_offsets =
                             &base.dynamic_offsets[num_dynamic_offsets as usize..];
-                        // Check for misaligned offsets.
-                        if let Some(offset) = offsets
-                            .iter()
-                            .map(|offset| *offset as wgt::BufferAddress)
-                            .find(|offset| offset % wgt::BIND_BUFFER_ALIGNMENT != 0)
-                        {
-                            Err(RenderCommandError::UnalignedBufferOffset(offset))?
-                        }
 
                         let bind_group = state
                             .trackers
@@ -708,6 +774,15 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                                 expected: bind_group.dynamic_binding_info.len(),
                             })?
                         }
+                        // Check for misaligned offsets.
+                        for (info, &offset) in bind_group.dynamic_binding_info.iter().zip(offsets) {
+                            if offset % info.alignment != 0 {
+                                Err(RenderCommandError::UnalignedBufferOffset {
+                                    offset,
+                                    alignment: info.alignment,
+                                })?
+                            }
+                        }
 
                         state.set_bind_group(index, bind_group_id, bind_group.layout_id, offsets);
                         state.trackers.merge_extend(&bind_group.used);
@@ -851,28 +926,54 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     }
                     RenderCommand::MultiDrawIndirect {
                         buffer_id,
-                        offset: _,
-                        count: None,
-                        indexed: false,
+                        offset,
+                        count,
+                        indexed,
                     } => {
+                        if count.is_some() {
+                            check_device_features(
+                                device.features,
+                                wgt::Features::MULTI_DRAW_INDIRECT,
+                            )?;
+                        }
+
                         let buffer = state
                             .trackers
                             .buffers
                             .use_extend(&*buffer_guard, buffer_id, (), BufferUse::INDIRECT)
-                            .unwrap();
+                            .map_err(|err| RenderCommandError::Buffer(buffer_id, err))?;
                         check_buffer_usage(buffer.usage, wgt::BufferUsage::INDIRECT)
                             .map_err(RenderCommandError::from)?;
 
+                        let actual_count = count.map_or(1, |c| c.get());
+                        check_indirect_range(
+                            offset,
+                            count,
+                            indirect_stride(indexed),
+                            actual_count,
+                            buffer.size,
+                        )?;
+
+                        if indexed {
+                            commands.extend(state.index.flush());
+                        }
                         commands.extend(state.flush_vertices());
                         commands.extend(state.flush_binds());
                         commands.push(command);
                     }
-                    RenderCommand::MultiDrawIndirect {
+                    RenderCommand::MultiDrawIndirectCount {
                         buffer_id,
-                        offset: _,
-                        count: None,
-                        indexed: true,
+                        offset,
+                        count_buffer_id,
+                        count_buffer_offset,
+                        max_count,
+                        indexed,
                     } => {
+                        check_device_features(
+                            device.features,
+                            wgt::Features::MULTI_DRAW_INDIRECT_COUNT,
+                        )?;
+
                         let buffer = state
                             .trackers
                             .buffers
@@ -880,17 +981,44 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             .map_err(|err| RenderCommandError::Buffer(buffer_id, err))?;
                         check_buffer_usage(buffer.usage, wgt::BufferUsage::INDIRECT)
                             .map_err(RenderCommandError::from)?;
+                        let buffer_size = buffer.size;
+                        let count_buffer = state
+                            .trackers
+                            .buffers
+                            .use_extend(&*buffer_guard, count_buffer_id, (), BufferUse::INDIRECT)
+                            .map_err(|err| RenderCommandError::Buffer(count_buffer_id, err))?;
+                        check_buffer_usage(count_buffer.usage, wgt::BufferUsage::INDIRECT)
+                            .map_err(RenderCommandError::from)?;
 
-                        commands.extend(state.index.flush());
+                        check_indirect_range(
+                            offset,
+                            None,
+                            indirect_stride(indexed),
+                            max_count,
+                            buffer_size,
+                        )?;
+                        check_indirect_count_range(count_buffer_offset, count_buffer.size)?;
+
+                        if indexed {
+                            commands.extend(state.index.flush());
+                        }
                         commands.extend(state.flush_vertices());
                         commands.extend(state.flush_binds());
                         commands.push(command);
                     }
-                    RenderCommand::MultiDrawIndirect { .. }
-                    | RenderCommand::MultiDrawIndirectCount { .. } => unimplemented!(),
-                    RenderCommand::PushDebugGroup { color: _, len: _ } => unimplemented!(),
-                    RenderCommand::InsertDebugMarker { color: _, len: _ } => unimplemented!(),
-                    RenderCommand::PopDebugGroup => unimplemented!(),
+                    RenderCommand::PushDebugGroup { color: _, len } => {
+                        state.debug_scope_depth += 1;
+                        base.string_data = &base.string_data[len..];
+                        commands.push(command);
+                    }
+                    RenderCommand::PopDebugGroup => {
+                        state.pop_debug_group()?;
+                        commands.push(command);
+                    }
+                    RenderCommand::InsertDebugMarker { color: _, len } => {
+                        base.string_data = &base.string_data[len..];
+                        commands.push(command);
+                    }
                     RenderCommand::ExecuteBundle(_)
                     | RenderCommand::SetBlendColor(_)
                     | RenderCommand::SetStencilReference(_)
@@ -901,14 +1029,16 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 }
             }
 
+            state.check_debug_groups_closed()?;
+
             tracing::debug!("Render bundle {:?} = {:#?}", id_in, state.trackers);
-            let _ = desc.label; //TODO: actually use
-                                //TODO: check if the device is still alive
+            //TODO: check if the device is still alive
             RenderBundle {
                 base: BasePass {
                     commands,
                     dynamic_offsets: state.flat_dynamic_offsets,
-                    string_data: Vec::new(),
+                    // Debug labels are passed through verbatim, in the same order.
+                    string_data: bundle_encoder.base.string_data,
                     push_constant_data: Vec::new(),
                 },
                 device_id: Stored {
@@ -917,7 +1047,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 },
                 used: state.trackers,
                 context: bundle_encoder.context,
-                life_guard: LifeGuard::new(),
+                life_guard: LifeGuard::new(device.resource_label(desc.label.as_deref())),
             }
         };
 
@@ -955,7 +1085,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 pub mod bundle_ffi {
     use super::{RenderBundleEncoder, RenderCommand};
     use crate::{id, span, RawString};
-    use std::{convert::TryInto, slice};
+    use std::{convert::TryInto, ffi, num::NonZeroU32, slice};
     use wgt::{BufferAddress, BufferSize, DynamicOffset};
 
     /// # Safety
@@ -1116,27 +1246,197 @@ pub mod bundle_ffi {
         });
     }
 
+    #[no_mangle]
+    pub extern "C" fn wgpu_render_bundle_multi_draw_indirect(
+        bundle: &mut RenderBundleEncoder,
+        buffer_id: id::BufferId,
+        offset: BufferAddress,
+        count: u32,
+    ) {
+        span!(_guard, DEBUG, "RenderBundle::multi_draw_indirect");
+        bundle.base.commands.push(RenderCommand::MultiDrawIndirect {
+            buffer_id,
+            offset,
+            count: NonZeroU32::new(count),
+            indexed: false,
+        });
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wgpu_render_bundle_multi_draw_indexed_indirect(
+        bundle: &mut RenderBundleEncoder,
+        buffer_id: id::BufferId,
+        offset: BufferAddress,
+        count: u32,
+    ) {
+        span!(_guard, DEBUG, "RenderBundle::multi_draw_indexed_indirect");
+        bundle.base.commands.push(RenderCommand::MultiDrawIndirect {
+            buffer_id,
+            offset,
+            count: NonZeroU32::new(count),
+            indexed: true,
+        });
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wgpu_render_bundle_multi_draw_indirect_count(
+        bundle: &mut RenderBundleEncoder,
+        buffer_id: id::BufferId,
+        offset: BufferAddress,
+        count_buffer_id: id::BufferId,
+        count_buffer_offset: BufferAddress,
+        max_count: u32,
+    ) {
+        span!(_guard, DEBUG, "RenderBundle::multi_draw_indirect_count");
+        bundle
+            .base
+            .commands
+            .push(RenderCommand::MultiDrawIndirectCount {
+                buffer_id,
+                offset,
+                count_buffer_id,
+                count_buffer_offset,
+                max_count,
+                indexed: false,
+            });
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wgpu_render_bundle_multi_draw_indexed_indirect_count(
+        bundle: &mut RenderBundleEncoder,
+        buffer_id: id::BufferId,
+        offset: BufferAddress,
+        count_buffer_id: id::BufferId,
+        count_buffer_offset: BufferAddress,
+        max_count: u32,
+    ) {
+        span!(
+            _guard,
+            DEBUG,
+            "RenderBundle::multi_draw_indexed_indirect_count"
+        );
+        bundle
+            .base
+            .commands
+            .push(RenderCommand::MultiDrawIndirectCount {
+                buffer_id,
+                offset,
+                count_buffer_id,
+                count_buffer_offset,
+                max_count,
+                indexed: true,
+            });
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn wgpu_render_bundle_push_debug_group(
-        _bundle: &mut RenderBundleEncoder,
-        _label: RawString,
+        bundle: &mut RenderBundleEncoder,
+        label: RawString,
     ) {
         span!(_guard, DEBUG, "RenderBundle::push_debug_group");
-        //TODO
+        let bytes = ffi::CStr::from_ptr(label).to_bytes();
+        bundle.base.string_data.extend_from_slice(bytes);
+
+        bundle.base.commands.push(RenderCommand::PushDebugGroup {
+            color: 0,
+            len: bytes.len(),
+        });
     }
 
     #[no_mangle]
-    pub unsafe extern "C" fn wgpu_render_bundle_pop_debug_group(_bundle: &mut RenderBundleEncoder) {
+    pub unsafe extern "C" fn wgpu_render_bundle_pop_debug_group(bundle: &mut RenderBundleEncoder) {
         span!(_guard, DEBUG, "RenderBundle::pop_debug_group");
-        //TODO
+        bundle.base.commands.push(RenderCommand::PopDebugGroup);
     }
 
     #[no_mangle]
     pub unsafe extern "C" fn wgpu_render_bundle_insert_debug_marker(
-        _bundle: &mut RenderBundleEncoder,
-        _label: RawString,
+        bundle: &mut RenderBundleEncoder,
+        label: RawString,
     ) {
         span!(_guard, DEBUG, "RenderBundle::insert_debug_marker");
-        //TODO
+        let bytes = ffi::CStr::from_ptr(label).to_bytes();
+        bundle.base.string_data.extend_from_slice(bytes);
+
+        bundle.base.commands.push(RenderCommand::InsertDebugMarker {
+            color: 0,
+            len: bytes.len(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::TypedId as _;
+    use std::{ffi, num::NonZeroU32};
+
+    #[test]
+    fn indirect_overrun() {
+        let buffer_size = 3 * indirect_stride(true);
+        let count = NonZeroU32::new(3);
+        assert!(check_indirect_range(0, count, indirect_stride(true), 3, buffer_size).is_ok());
+        match check_indirect_range(4, count, indirect_stride(true), 3, buffer_size) {
+            Err(RenderCommandError::IndirectBufferOverrun { end_offset, .. }) => {
+                assert_eq!(end_offset, 64)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        // A huge count must not wrap around the address space and pass.
+        match check_indirect_range(
+            wgt::BufferAddress::MAX - 8,
+            None,
+            indirect_stride(false),
+            !0,
+            buffer_size,
+        ) {
+            Err(RenderCommandError::IndirectBufferOverrun { end_offset, .. }) => {
+                assert_eq!(end_offset, wgt::BufferAddress::MAX)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        assert!(check_indirect_count_range(4, 8).is_ok());
+        assert!(check_indirect_count_range(8, 8).is_err());
+        assert!(check_indirect_count_range(wgt::BufferAddress::MAX - 2, 8).is_err());
+    }
+
+    #[test]
+    fn debug_markers() {
+        let desc = RenderBundleEncoderDescriptor {
+            label: None,
+            color_formats: Cow::Borrowed(&[wgt::TextureFormat::Rgba8Unorm]),
+            depth_stencil_format: None,
+            sample_count: 1,
+        };
+        let device_id = id::DeviceId::zip(1, 0, wgt::Backend::Empty);
+        let mut encoder = RenderBundleEncoder::new(&desc, device_id, None).unwrap();
+        let group = ffi::CString::new("group").unwrap();
+        let marker = ffi::CString::new("marker").unwrap();
+        unsafe {
+            bundle_ffi::wgpu_render_bundle_push_debug_group(&mut encoder, group.as_ptr());
+            bundle_ffi::wgpu_render_bundle_insert_debug_marker(&mut encoder, marker.as_ptr());
+            bundle_ffi::wgpu_render_bundle_pop_debug_group(&mut encoder);
+        }
+        assert_eq!(encoder.base.string_data, b"groupmarker");
+        match encoder.base.commands[..] {
+            [RenderCommand::PushDebugGroup { len: 5, .. }, RenderCommand::InsertDebugMarker { len: 6, .. }, RenderCommand::PopDebugGroup] =>
+                {}
+            ref other => panic!("unexpected commands {:?}", other),
+        }
+
+        let mut state = State::new(wgt::Backend::Empty);
+        match state.pop_debug_group() {
+            Err(RenderBundleError::InvalidPopDebugGroup) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        state.debug_scope_depth = 2;
+        state.pop_debug_group().unwrap();
+        match state.check_debug_groups_closed() {
+            Err(RenderBundleError::UnclosedDebugGroups(1)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        state.pop_debug_group().unwrap();
+        state.check_debug_groups_closed().unwrap();
     }
 }
@@ -60,6 +60,7 @@ pub enum ComputeCommand {
         color: u32,
         len: usize,
     },
+    ExecuteBundle(id::ComputeBundleId),
 }
 
 #[cfg_attr(feature = "serial-pass", derive(serde::Deserialize, serde::Serialize))]
@@ -109,6 +110,46 @@ pub enum DispatchError {
         //expected: BindGroupLayoutId,
         //provided: Option<(BindGroupLayoutId, BindGroupId)>,
     },
+    #[error("dispatch of {current:?} workgroups exceeds the limit of {limit} per dimension")]
+    InvalidGroupSize { current: [u32; 3], limit: u32 },
+    #[error("indirect dispatch with offset {offset} uses bytes {offset}..{end_offset} which overruns indirect buffer of size {buffer_size}")]
+    IndirectBufferOverrun {
+        offset: u64,
+        end_offset: u64,
+        buffer_size: u64,
+    },
+}
+
+/// Checks the number of workgroups of a direct dispatch against the device limits.
+pub(crate) fn check_dispatch_size(
+    groups: [u32; 3],
+    limits: &wgt::Limits,
+) -> Result<(), DispatchError> {
+    let limit = limits.max_compute_workgroups_per_dimension;
+    if groups.iter().any(|&count| count > limit) {
+        return Err(DispatchError::InvalidGroupSize {
+            current: groups,
+            limit,
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the 12-byte indirect dispatch arguments at `offset`
+/// fit into an indirect buffer of `buffer_size` bytes.
+pub(crate) fn check_dispatch_indirect_range(
+    offset: BufferAddress,
+    buffer_size: BufferAddress,
+) -> Result<(), DispatchError> {
+    let end_offset = offset.checked_add(12);
+    match end_offset {
+        Some(end_offset) if end_offset <= buffer_size => Ok(()),
+        _ => Err(DispatchError::IndirectBufferOverrun {
+            offset,
+            end_offset: end_offset.unwrap_or(BufferAddress::MAX),
+            buffer_size,
+        }),
+    }
 }
 
 #[derive(Clone, Debug, Error)]
@@ -123,6 +164,10 @@ pub enum ComputePassError {
     InvalidPipeline(id::ComputePipelineId),
     #[error("indirect buffer {0:?} is invalid")]
     InvalidIndirectBuffer(id::BufferId),
+    #[error("compute bundle {0:?} is invalid")]
+    InvalidBundle(id::ComputeBundleId),
+    #[error("compute bundle {0:?} belongs to a different device than the compute pass")]
+    BundleDeviceMismatch(id::ComputeBundleId),
     #[error(transparent)]
     ResourceUsageConflict(UsageConflict),
     #[error(transparent)]
@@ -165,6 +210,12 @@ impl State {
         }
         Ok(())
     }
+
+    /// Reset the `ComputeBundle`-related states.
+    fn reset_bundle(&mut self) {
+        self.binder.reset();
+        self.pipeline = PipelineState::Required;
+    }
 }
 
 // Common routines between render/compute
@@ -202,7 +253,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             None => {}
         }
 
-        let (_, mut token) = hub.render_bundles.read(&mut token);
+        let (bundle_guard, mut token) = hub.compute_bundles.read(&mut token);
         let (pipeline_layout_guard, mut token) = hub.pipeline_layouts.read(&mut token);
         let (bind_group_guard, mut token) = hub.bind_groups.read(&mut token);
         let (pipeline_guard, mut token) = hub.compute_pipelines.read(&mut token);
@@ -379,6 +430,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 }
                 ComputeCommand::Dispatch(groups) => {
                     state.is_ready()?;
+                    check_dispatch_size(groups, &cmd_buf.limits)?;
                     unsafe {
                         raw.dispatch(groups);
                     }
@@ -392,6 +444,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         .use_replace(&*buffer_guard, buffer_id, (), BufferUse::INDIRECT)
                         .map_err(ComputePassError::InvalidIndirectBuffer)?;
                     check_buffer_usage(src_buffer.usage, BufferUsage::INDIRECT)?;
+                    check_dispatch_indirect_range(offset, src_buffer.size)?;
 
                     let barriers = src_pending.map(|pending| pending.into_hal(src_buffer));
 
@@ -427,6 +480,42 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     unsafe { raw.insert_debug_marker(label, color) }
                     base.string_data = &base.string_data[len..];
                 }
+                ComputeCommand::ExecuteBundle(bundle_id) => {
+                    let bundle = cmd_buf
+                        .trackers
+                        .compute_bundles
+                        .use_extend(&*bundle_guard, bundle_id, (), ())
+                        .map_err(|_| ComputePassError::InvalidBundle(bundle_id))?;
+                    if bundle.device_id.value != cmd_buf.device_id.value {
+                        return Err(ComputePassError::BundleDeviceMismatch(bundle_id));
+                    }
+
+                    tracing::trace!(
+                        "Encoding barriers on execution of {:?} in {:?}",
+                        bundle_id,
+                        encoder_id
+                    );
+                    CommandBuffer::insert_barriers(
+                        raw,
+                        &mut cmd_buf.trackers,
+                        &bundle.used,
+                        &*buffer_guard,
+                        &*texture_guard,
+                    );
+
+                    unsafe {
+                        bundle.execute(
+                            raw,
+                            &mut cmd_buf.trackers,
+                            &*pipeline_layout_guard,
+                            &*bind_group_guard,
+                            &*pipeline_guard,
+                            &*buffer_guard,
+                            &*texture_guard,
+                        )
+                    }
+                    state.reset_bundle();
+                }
             }
         }
 
@@ -558,4 +647,62 @@ pub mod compute_ffi {
             len: bytes.len(),
         });
     }
+
+    /// # Safety
+    ///
+    /// This function is unsafe as there is no guarantee that the given pointer is
+    /// valid for `compute_bundle_ids_length` elements.
+    #[no_mangle]
+    pub unsafe extern "C" fn wgpu_compute_pass_execute_bundles(
+        pass: &mut ComputePass,
+        compute_bundle_ids: *const id::ComputeBundleId,
+        compute_bundle_ids_length: usize,
+    ) {
+        span!(_guard, DEBUG, "ComputePass::execute_bundles");
+        for &bundle_id in slice::from_raw_parts(compute_bundle_ids, compute_bundle_ids_length) {
+            pass.base
+                .commands
+                .push(ComputeCommand::ExecuteBundle(bundle_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_dispatch_indirect_range, check_dispatch_size, DispatchError};
+
+    #[test]
+    fn dispatch_size() {
+        let limits = wgt::Limits::default();
+        let limit = limits.max_compute_workgroups_per_dimension;
+        assert_eq!(check_dispatch_size([limit, limit, limit], &limits), Ok(()));
+        assert_eq!(check_dispatch_size([0, 0, 0], &limits), Ok(()));
+        assert_eq!(
+            check_dispatch_size([1, limit + 1, 1], &limits),
+            Err(DispatchError::InvalidGroupSize {
+                current: [1, limit + 1, 1],
+                limit,
+            })
+        );
+    }
+    #[test]
+    fn dispatch_indirect_range() {
+        assert_eq!(check_dispatch_indirect_range(4, 16), Ok(()));
+        assert_eq!(
+            check_dispatch_indirect_range(8, 16),
+            Err(DispatchError::IndirectBufferOverrun {
+                offset: 8,
+                end_offset: 20,
+                buffer_size: 16,
+            })
+        );
+        assert_eq!(
+            check_dispatch_indirect_range(!0 - 4, 16),
+            Err(DispatchError::IndirectBufferOverrun {
+                offset: !0 - 4,
+                end_offset: !0,
+                buffer_size: 16,
+            })
+        );
+    }
 }
@@ -0,0 +1,752 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Compute Bundles
+//!
+//! Compute bundles follow the same 3 phases as render bundles.
+//!
+//! ### Initial command encoding
+//!
+//! User creates a `ComputeBundleEncoder` and populates it by issuing commands
+//! from `compute_bundle_ffi` module, just like with `ComputePass`.
+//!
+//! ### Bundle baking
+//!
+//! When `compute_bundle_encoder_finish` is called, the commands are validated
+//! and the command stream is "normalized": every bind group required by a dispatch
+//! is set explicitly between the dispatch and the last pipeline change.
+//! The buffers and textures used by each dispatch are gathered into a separate
+//! usage scope, which has to be free of conflicting usages.
+//!
+//! ### Execution
+//!
+//! When the bundle is used in an actual compute pass, `ComputeBundle::execute`
+//! issues the commands verbatim into the native command buffer, transitioning
+//! the resources of each dispatch scope right before the dispatch. This way,
+//! a dispatch can write a resource that a following dispatch reads.
+
+use crate::{
+    binding_model::{BindError, BindGroup, PushConstantUploadError},
+    command::{
+        compute::{check_dispatch_indirect_range, check_dispatch_size},
+        BasePass, CommandBuffer, ComputeCommand, DispatchError,
+    },
+    device::DeviceError,
+    hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Input, Storage, Token},
+    id,
+    resource::{BufferUse, TextureUse},
+    span,
+    track::{TrackerSet, UseExtendError},
+    validation::{check_buffer_usage, MissingBufferUsageError},
+    Label, LifeGuard, RefCount, Stored, MAX_BIND_GROUPS,
+};
+use arrayvec::ArrayVec;
+use std::{borrow::Borrow, iter, marker::PhantomData, ops::Range, str};
+use thiserror::Error;
+use wgt::ShaderStage;
+
+/// Describes a [`ComputeBundleEncoder`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize))]
+#[cfg_attr(feature = "replay", derive(serde::Deserialize))]
+pub struct ComputeBundleEncoderDescriptor<'a> {
+    /// Debug label of the compute bundle encoder. This will show up in graphics debuggers for easy identification.
+    pub label: Label<'a>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serial-pass", derive(serde::Deserialize, serde::Serialize))]
+pub struct ComputeBundleEncoder {
+    base: BasePass<ComputeCommand>,
+    parent_id: id::DeviceId,
+}
+
+impl ComputeBundleEncoder {
+    pub fn new(
+        _desc: &ComputeBundleEncoderDescriptor,
+        parent_id: id::DeviceId,
+        base: Option<BasePass<ComputeCommand>>,
+    ) -> Self {
+        span!(_guard, INFO, "ComputeBundleEncoder::new");
+        ComputeBundleEncoder {
+            base: base.unwrap_or_else(BasePass::new),
+            parent_id,
+        }
+    }
+
+    pub fn parent(&self) -> id::DeviceId {
+        self.parent_id
+    }
+}
+
+pub type ComputeBundleDescriptor<'a> = wgt::ComputeBundleDescriptor<Label<'a>>;
+
+#[derive(Debug)]
+pub struct ComputeBundle {
+    // Normalized command stream. It can be executed verbatim,
+    // without re-binding anything on the pipeline change.
+    base: BasePass<ComputeCommand>,
+    pub(crate) device_id: Stored<id::DeviceId>,
+    /// Bind groups and pipelines used by the bundle.
+    pub(crate) used: TrackerSet,
+    /// Buffers and textures used by each dispatch, in order.
+    pub(crate) scopes: Vec<TrackerSet>,
+    pub(crate) life_guard: LifeGuard,
+}
+
+unsafe impl Send for ComputeBundle {}
+unsafe impl Sync for ComputeBundle {}
+
+impl ComputeBundle {
+    /// Actually encode the contents into a native command buffer.
+    ///
+    /// Like `RenderBundle::execute`, this isn't expected to fail:
+    /// all the validation has been done in `compute_bundle_encoder_finish`.
+    /// The usage scope of each dispatch is merged into `trackers`,
+    /// with the barriers recorded right before the dispatch.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) unsafe fn execute<B: GfxBackend>(
+        &self,
+        cmd_buf: &mut B::CommandBuffer,
+        trackers: &mut TrackerSet,
+        pipeline_layout_guard: &Storage<
+            crate::binding_model::PipelineLayout<B>,
+            id::PipelineLayoutId,
+        >,
+        bind_group_guard: &Storage<BindGroup<B>, id::BindGroupId>,
+        pipeline_guard: &Storage<crate::pipeline::ComputePipeline<B>, id::ComputePipelineId>,
+        buffer_guard: &Storage<crate::resource::Buffer<B>, id::BufferId>,
+        texture_guard: &Storage<crate::resource::Texture<B>, id::TextureId>,
+    ) {
+        use hal::command::CommandBuffer as _;
+
+        let mut offsets = self.base.dynamic_offsets.as_slice();
+        let mut string_data = self.base.string_data.as_slice();
+        let mut pipeline_layout_id = None::<id::Valid<id::PipelineLayoutId>>;
+        let mut scopes = self.scopes.iter();
+
+        for command in self.base.commands.iter() {
+            match *command {
+                ComputeCommand::SetBindGroup {
+                    index,
+                    num_dynamic_offsets,
+                    bind_group_id,
+                } => {
+                    let bind_group = bind_group_guard.get(bind_group_id).unwrap();
+                    cmd_buf.bind_compute_descriptor_sets(
+                        &pipeline_layout_guard[pipeline_layout_id.unwrap()].raw,
+                        index as usize,
+                        iter::once(bind_group.raw.raw()),
+                        &offsets[..num_dynamic_offsets as usize],
+                    );
+                    offsets = &offsets[num_dynamic_offsets as usize..];
+                }
+                ComputeCommand::SetPipeline(pipeline_id) => {
+                    let pipeline = pipeline_guard.get(pipeline_id).unwrap();
+                    cmd_buf.bind_compute_pipeline(&pipeline.raw);
+
+                    if pipeline_layout_id != Some(pipeline.layout_id.value) {
+                        let pipeline_layout = &pipeline_layout_guard[pipeline.layout_id.value];
+                        pipeline_layout_id = Some(pipeline.layout_id.value);
+
+                        let non_overlapping = super::bind::compute_nonoverlapping_ranges(
+                            &pipeline_layout.push_constant_ranges,
+                        );
+                        for range in non_overlapping {
+                            let offset = range.range.start;
+                            let size_bytes = range.range.end - offset;
+                            super::push_constant_clear(
+                                offset,
+                                size_bytes,
+                                |clear_offset, clear_data| {
+                                    cmd_buf.push_compute_constants(
+                                        &pipeline_layout.raw,
+                                        clear_offset,
+                                        clear_data,
+                                    );
+                                },
+                            );
+                        }
+                    }
+                }
+                ComputeCommand::SetPushConstant {
+                    offset,
+                    size_bytes,
+                    values_offset,
+                } => {
+                    let pipeline_layout = &pipeline_layout_guard[pipeline_layout_id.unwrap()];
+                    let values_end_offset = (values_offset + size_bytes / 4) as usize;
+                    let data_slice =
+                        &self.base.push_constant_data[(values_offset as usize)..values_end_offset];
+                    cmd_buf.push_compute_constants(&pipeline_layout.raw, offset, data_slice);
+                }
+                ComputeCommand::Dispatch(groups) => {
+                    CommandBuffer::insert_barriers(
+                        cmd_buf,
+                        trackers,
+                        scopes.next().unwrap(),
+                        buffer_guard,
+                        texture_guard,
+                    );
+                    cmd_buf.dispatch(groups);
+                }
+                ComputeCommand::DispatchIndirect { buffer_id, offset } => {
+                    CommandBuffer::insert_barriers(
+                        cmd_buf,
+                        trackers,
+                        scopes.next().unwrap(),
+                        buffer_guard,
+                        texture_guard,
+                    );
+                    let buffer = buffer_guard.get(buffer_id).unwrap();
+                    cmd_buf.dispatch_indirect(&buffer.raw, offset);
+                }
+                ComputeCommand::PushDebugGroup { color, len } => {
+                    let label = str::from_utf8(&string_data[..len]).unwrap();
+                    cmd_buf.begin_debug_marker(label, color);
+                    string_data = &string_data[len..];
+                }
+                ComputeCommand::PopDebugGroup => {
+                    cmd_buf.end_debug_marker();
+                }
+                ComputeCommand::InsertDebugMarker { color, len } => {
+                    let label = str::from_utf8(&string_data[..len]).unwrap();
+                    cmd_buf.insert_debug_marker(label, color);
+                    string_data = &string_data[len..];
+                }
+                ComputeCommand::ExecuteBundle(_) => unreachable!(),
+            }
+        }
+    }
+}
+
+impl Borrow<RefCount> for ComputeBundle {
+    fn borrow(&self) -> &RefCount {
+        self.life_guard.ref_count.as_ref().unwrap()
+    }
+}
+
+#[derive(Debug)]
+struct BindState {
+    bind_group: Option<(id::BindGroupId, id::Valid<id::BindGroupLayoutId>)>,
+    dynamic_offsets: Range<usize>,
+    is_dirty: bool,
+}
+
+impl BindState {
+    fn new() -> Self {
+        BindState {
+            bind_group: None,
+            dynamic_offsets: 0..0,
+            is_dirty: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    trackers: TrackerSet,
+    bind: ArrayVec<[BindState; MAX_BIND_GROUPS]>,
+    pipeline_layout_id: Option<id::Valid<id::PipelineLayoutId>>,
+    raw_dynamic_offsets: Vec<wgt::DynamicOffset>,
+    flat_dynamic_offsets: Vec<wgt::DynamicOffset>,
+    debug_scope_depth: u32,
+}
+
+impl State {
+    fn set_bind_group(
+        &mut self,
+        slot: u8,
+        bind_group_id: id::BindGroupId,
+        layout_id: id::Valid<id::BindGroupLayoutId>,
+        offsets: &[wgt::DynamicOffset],
+    ) {
+        let start = self.raw_dynamic_offsets.len();
+        self.raw_dynamic_offsets.extend_from_slice(offsets);
+        self.bind[slot as usize] = BindState {
+            bind_group: Some((bind_group_id, layout_id)),
+            dynamic_offsets: start..self.raw_dynamic_offsets.len(),
+            is_dirty: true,
+        };
+    }
+
+    /// Gather the buffers and textures used by the bind groups of a dispatch.
+    fn dispatch_scope<B: GfxBackend>(
+        &self,
+        bind_group_guard: &Storage<BindGroup<B>, id::BindGroupId>,
+        bind_group_count: usize,
+    ) -> Result<TrackerSet, ComputeBundleError> {
+        let mut scope = TrackerSet::new(B::VARIANT);
+        for bs in self.bind[..bind_group_count].iter() {
+            if let Some((bind_group_id, _)) = bs.bind_group {
+                extend_scope(&mut scope, &bind_group_guard[id::Valid(bind_group_id)].used)?;
+            }
+        }
+        Ok(scope)
+    }
+
+    fn invalidate_binds(&mut self) {
+        for bs in self.bind.iter_mut() {
+            if bs.bind_group.is_some() {
+                bs.is_dirty = true;
+            }
+        }
+    }
+
+    /// Check that the bind groups match the layout of the current pipeline,
+    /// and produce the commands that (re-)bind the dirty ones.
+    fn flush_binds(
+        &mut self,
+        layout_ids: &[id::Valid<id::BindGroupLayoutId>],
+    ) -> Result<impl Iterator<Item = ComputeCommand> + '_, DispatchError> {
+        for (index, (bs, &layout_id)) in self.bind.iter().zip(layout_ids).enumerate() {
+            match bs.bind_group {
+                Some((_, bgl_id)) if bgl_id == layout_id => {}
+                _ => {
+                    return Err(DispatchError::IncompatibleBindGroup {
+                        index: index as u32,
+                    })
+                }
+            }
+        }
+
+        for bs in self.bind[..layout_ids.len()].iter() {
+            if bs.is_dirty {
+                self.flat_dynamic_offsets
+                    .extend_from_slice(&self.raw_dynamic_offsets[bs.dynamic_offsets.clone()]);
+            }
+        }
+        Ok(self
+            .bind
+            .iter_mut()
+            .take(layout_ids.len())
+            .enumerate()
+            .flat_map(|(i, bs)| {
+                if bs.is_dirty {
+                    bs.is_dirty = false;
+                    Some(ComputeCommand::SetBindGroup {
+                        index: i as u8,
+                        bind_group_id: bs.bind_group.unwrap().0,
+                        num_dynamic_offsets: (bs.dynamic_offsets.end - bs.dynamic_offsets.start)
+                            as u8,
+                    })
+                } else {
+                    None
+                }
+            }))
+    }
+}
+
+/// Extend the usage scope of a dispatch with the buffers and textures of a bind group.
+fn extend_scope(scope: &mut TrackerSet, used: &TrackerSet) -> Result<(), ComputeBundleError> {
+    scope
+        .buffers
+        .merge_extend(&used.buffers)
+        .map_err(|pending| ComputeBundleError::BufferUsageConflict(pending.id.0, pending.usage))?;
+    scope
+        .textures
+        .merge_extend(&used.textures)
+        .map_err(|pending| ComputeBundleError::TextureUsageConflict(pending.id.0, pending.usage))?;
+    Ok(())
+}
+
+/// Error encountered when finishing recording a compute bundle.
+#[derive(Clone, Debug, Error)]
+pub enum ComputeBundleError {
+    #[error(transparent)]
+    Device(#[from] DeviceError),
+    #[error("bind group {0:?} is invalid")]
+    InvalidBindGroup(id::BindGroupId),
+    #[error("bind group index {index} is greater than the device's requested `max_bind_group` limit {max}")]
+    BindGroupIndexOutOfRange { index: u8, max: u32 },
+    #[error("compute pipeline {0:?} is invalid")]
+    InvalidPipeline(id::ComputePipelineId),
+    #[error("indirect buffer {0:?} is invalid")]
+    InvalidIndirectBuffer(id::BufferId),
+    #[error(transparent)]
+    MissingBufferUsage(#[from] MissingBufferUsageError),
+    #[error("buffer {0:?} is used with conflicting usages {1:?} by a single dispatch")]
+    BufferUsageConflict(id::BufferId, Range<BufferUse>),
+    #[error("texture {0:?} is used with conflicting usages {1:?} by a single dispatch")]
+    TextureUsageConflict(id::TextureId, Range<TextureUse>),
+    #[error("compute bundles can't execute other compute bundles")]
+    NestedBundle,
+    #[error("cannot pop debug group, because number of pushed debug groups is zero")]
+    InvalidPopDebugGroup,
+    #[error("compute bundle has {0} unclosed debug groups")]
+    UnclosedDebugGroups(u32),
+    #[error(transparent)]
+    Dispatch(#[from] DispatchError),
+    #[error(transparent)]
+    Bind(#[from] BindError),
+    #[error(transparent)]
+    PushConstants(#[from] PushConstantUploadError),
+}
+
+impl<G: GlobalIdentityHandlerFactory> Global<G> {
+    pub fn compute_bundle_encoder_finish<B: GfxBackend>(
+        &self,
+        bundle_encoder: ComputeBundleEncoder,
+        desc: &ComputeBundleDescriptor,
+        id_in: Input<G, id::ComputeBundleId>,
+    ) -> Result<id::ComputeBundleId, ComputeBundleError> {
+        span!(_guard, INFO, "ComputeBundleEncoder::finish");
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, mut token) = hub.devices.read(&mut token);
+
+        let device = device_guard
+            .get(bundle_encoder.parent_id)
+            .map_err(|_| DeviceError::Invalid)?;
+        let compute_bundle = {
+            let (pipeline_layout_guard, mut token) = hub.pipeline_layouts.read(&mut token);
+            let (bind_group_guard, mut token) = hub.bind_groups.read(&mut token);
+            let (pipeline_guard, mut token) = hub.compute_pipelines.read(&mut token);
+            let (buffer_guard, _) = hub.buffers.read(&mut token);
+
+            let mut state = State {
+                trackers: TrackerSet::new(bundle_encoder.parent_id.backend()),
+                bind: (0..MAX_BIND_GROUPS).map(|_| BindState::new()).collect(),
+                pipeline_layout_id: None,
+                raw_dynamic_offsets: Vec::new(),
+                flat_dynamic_offsets: Vec::new(),
+                debug_scope_depth: 0,
+            };
+            let mut commands = Vec::new();
+            let mut scopes = Vec::new();
+            let mut push_constant_data = Vec::new();
+            let mut base = bundle_encoder.base.as_ref();
+
+            for &command in base.commands {
+                match command {
+                    ComputeCommand::SetBindGroup {
+                        index,
+                        num_dynamic_offsets,
+                        bind_group_id,
+                    } => {
+                        let max_bind_groups = device.limits.max_bind_groups;
+                        if (index as u32) >= max_bind_groups {
+                            return Err(ComputeBundleError::BindGroupIndexOutOfRange {
+                                index,
+                                max: max_bind_groups,
+                            });
+                        }
+
+                        let offsets = &base.dynamic_offsets[..num_dynamic_offsets as usize];
+                        base.dynamic_offsets =
+                            &base.dynamic_offsets[num_dynamic_offsets as usize..];
+
+                        let bind_group = state
+                            .trackers
+                            .bind_groups
+                            .use_extend(&*bind_group_guard, bind_group_id, (), ())
+                            .map_err(|_| ComputeBundleError::InvalidBindGroup(bind_group_id))?;
+                        bind_group.validate_dynamic_bindings(offsets)?;
+
+                        state.set_bind_group(index, bind_group_id, bind_group.layout_id, offsets);
+                    }
+                    ComputeCommand::SetPipeline(pipeline_id) => {
+                        let pipeline = state
+                            .trackers
+                            .compute_pipes
+                            .use_extend(&*pipeline_guard, pipeline_id, (), ())
+                            .map_err(|_| ComputeBundleError::InvalidPipeline(pipeline_id))?;
+
+                        if state.pipeline_layout_id != Some(pipeline.layout_id.value) {
+                            state.pipeline_layout_id = Some(pipeline.layout_id.value);
+                            state.invalidate_binds();
+                        }
+                        commands.push(command);
+                    }
+                    ComputeCommand::SetPushConstant {
+                        offset,
+                        size_bytes,
+                        values_offset,
+                    } => {
+                        let end_offset_bytes = offset + size_bytes;
+                        let values_end_offset = (values_offset + size_bytes / 4) as usize;
+                        let data_slice =
+                            &base.push_constant_data[(values_offset as usize)..values_end_offset];
+
+                        let pipeline_layout_id = state
+                            .pipeline_layout_id
+                            .ok_or(DispatchError::MissingPipeline)?;
+                        pipeline_layout_guard[pipeline_layout_id].validate_push_constant_ranges(
+                            ShaderStage::COMPUTE,
+                            offset,
+                            end_offset_bytes,
+                        )?;
+
+                        commands.push(ComputeCommand::SetPushConstant {
+                            offset,
+                            size_bytes,
+                            values_offset: push_constant_data.len() as u32,
+                        });
+                        push_constant_data.extend_from_slice(data_slice);
+                    }
+                    ComputeCommand::Dispatch(groups) => {
+                        let pipeline_layout_id = state
+                            .pipeline_layout_id
+                            .ok_or(DispatchError::MissingPipeline)?;
+                        check_dispatch_size(groups, &device.limits)?;
+                        let layout = &pipeline_layout_guard[pipeline_layout_id];
+                        commands.extend(state.flush_binds(&layout.bind_group_layout_ids)?);
+                        scopes.push(state.dispatch_scope(
+                            &*bind_group_guard,
+                            layout.bind_group_layout_ids.len(),
+                        )?);
+                        commands.push(command);
+                    }
+                    ComputeCommand::DispatchIndirect { buffer_id, offset } => {
+                        let pipeline_layout_id = state
+                            .pipeline_layout_id
+                            .ok_or(DispatchError::MissingPipeline)?;
+
+                        let layout = &pipeline_layout_guard[pipeline_layout_id];
+                        commands.extend(state.flush_binds(&layout.bind_group_layout_ids)?);
+                        let mut scope = state.dispatch_scope(
+                            &*bind_group_guard,
+                            layout.bind_group_layout_ids.len(),
+                        )?;
+
+                        let buffer = scope
+                            .buffers
+                            .use_extend(&*buffer_guard, buffer_id, (), BufferUse::INDIRECT)
+                            .map_err(|err| match err {
+                                UseExtendError::InvalidResource => {
+                                    ComputeBundleError::InvalidIndirectBuffer(buffer_id)
+                                }
+                                UseExtendError::Conflict(old) => {
+                                    ComputeBundleError::BufferUsageConflict(
+                                        buffer_id,
+                                        old..BufferUse::INDIRECT,
+                                    )
+                                }
+                            })?;
+                        check_buffer_usage(buffer.usage, wgt::BufferUsage::INDIRECT)?;
+                        check_dispatch_indirect_range(offset, buffer.size)?;
+
+                        scopes.push(scope);
+                        commands.push(command);
+                    }
+                    ComputeCommand::PushDebugGroup { color: _, len } => {
+                        state.debug_scope_depth += 1;
+                        base.string_data = &base.string_data[len..];
+                        commands.push(command);
+                    }
+                    ComputeCommand::PopDebugGroup => {
+                        if state.debug_scope_depth == 0 {
+                            return Err(ComputeBundleError::InvalidPopDebugGroup);
+                        }
+                        state.debug_scope_depth -= 1;
+                        commands.push(command);
+                    }
+                    ComputeCommand::InsertDebugMarker { color: _, len } => {
+                        base.string_data = &base.string_data[len..];
+                        commands.push(command);
+                    }
+                    ComputeCommand::ExecuteBundle(_) => {
+                        return Err(ComputeBundleError::NestedBundle);
+                    }
+                }
+            }
+
+            if state.debug_scope_depth != 0 {
+                return Err(ComputeBundleError::UnclosedDebugGroups(
+                    state.debug_scope_depth,
+                ));
+            }
+
+            tracing::debug!("Compute bundle {:?} = {:#?}", id_in, state.trackers);
+            ComputeBundle {
+                base: BasePass {
+                    commands,
+                    dynamic_offsets: state.flat_dynamic_offsets,
+                    string_data: bundle_encoder.base.string_data,
+                    push_constant_data,
+                },
+                device_id: Stored {
+                    value: id::Valid(bundle_encoder.parent_id),
+                    ref_count: device.life_guard.add_ref(),
+                },
+                used: state.trackers,
+                scopes,
+                life_guard: LifeGuard::new(device.resource_label(desc.label.as_deref())),
+            }
+        };
+
+        let ref_count = compute_bundle.life_guard.add_ref();
+        let id = hub
+            .compute_bundles
+            .register_identity(id_in, compute_bundle, &mut token);
+
+        #[cfg(feature = "trace")]
+        match device.trace {
+            Some(ref trace) => {
+                use crate::device::trace;
+                let (bundle_guard, _) = hub.compute_bundles.read(&mut token);
+                let bundle = &bundle_guard[id];
+                trace.lock().add(trace::Action::CreateComputeBundle {
+                    id: id.0,
+                    desc: ComputeBundleEncoderDescriptor {
+                        label: desc.label.as_ref().map(|l| l.as_ref().into()),
+                    },
+                    base: BasePass::from_ref(bundle.base.as_ref()),
+                });
+            }
+            None => {}
+        }
+
+        device
+            .trackers
+            .lock()
+            .compute_bundles
+            .init(id, ref_count, PhantomData)
+            .unwrap();
+        Ok(id.0)
+    }
+}
+
+pub mod compute_bundle_ffi {
+    use super::{ComputeBundleEncoder, ComputeCommand};
+    use crate::{id, span, RawString};
+    use std::{convert::TryInto, ffi, slice};
+    use wgt::{BufferAddress, DynamicOffset};
+
+    /// # Safety
+    ///
+    /// This function is unsafe as there is no guarantee that the given pointer is
+    /// valid for `offset_length` elements.
+    #[no_mangle]
+    pub unsafe extern "C" fn wgpu_compute_bundle_set_bind_group(
+        bundle: &mut ComputeBundleEncoder,
+        index: u32,
+        bind_group_id: id::BindGroupId,
+        offsets: *const DynamicOffset,
+        offset_length: usize,
+    ) {
+        span!(_guard, DEBUG, "ComputeBundle::set_bind_group");
+        bundle.base.commands.push(ComputeCommand::SetBindGroup {
+            index: index.try_into().unwrap(),
+            num_dynamic_offsets: offset_length.try_into().unwrap(),
+            bind_group_id,
+        });
+        bundle
+            .base
+            .dynamic_offsets
+            .extend_from_slice(slice::from_raw_parts(offsets, offset_length));
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wgpu_compute_bundle_set_pipeline(
+        bundle: &mut ComputeBundleEncoder,
+        pipeline_id: id::ComputePipelineId,
+    ) {
+        span!(_guard, DEBUG, "ComputeBundle::set_pipeline");
+        bundle
+            .base
+            .commands
+            .push(ComputeCommand::SetPipeline(pipeline_id));
+    }
+
+    /// # Safety
+    ///
+    /// This function is unsafe as there is no guarantee that the given pointer is
+    /// valid for `size_bytes` bytes.
+    #[no_mangle]
+    pub unsafe extern "C" fn wgpu_compute_bundle_set_push_constant(
+        bundle: &mut ComputeBundleEncoder,
+        offset: u32,
+        size_bytes: u32,
+        data: *const u32,
+    ) {
+        span!(_guard, DEBUG, "ComputeBundle::set_push_constant");
+        let data_slice = slice::from_raw_parts(data, (size_bytes / 4) as usize);
+        let value_offset = bundle.base.push_constant_data.len().try_into().expect(
+            "Ran out of push constant space. Don't set 4gb of push constants per ComputeBundle.",
+        );
+        bundle.base.push_constant_data.extend_from_slice(data_slice);
+        bundle.base.commands.push(ComputeCommand::SetPushConstant {
+            offset,
+            size_bytes,
+            values_offset: value_offset,
+        });
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wgpu_compute_bundle_dispatch(
+        bundle: &mut ComputeBundleEncoder,
+        groups_x: u32,
+        groups_y: u32,
+        groups_z: u32,
+    ) {
+        span!(_guard, DEBUG, "ComputeBundle::dispatch");
+        bundle
+            .base
+            .commands
+            .push(ComputeCommand::Dispatch([groups_x, groups_y, groups_z]));
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wgpu_compute_bundle_dispatch_indirect(
+        bundle: &mut ComputeBundleEncoder,
+        buffer_id: id::BufferId,
+        offset: BufferAddress,
+    ) {
+        span!(_guard, DEBUG, "ComputeBundle::dispatch_indirect");
+        bundle
+            .base
+            .commands
+            .push(ComputeCommand::DispatchIndirect { buffer_id, offset });
+    }
+
+    /// # Safety
+    ///
+    /// This function is unsafe as there is no guarantee that the given `label`
+    /// is a valid null-terminated string.
+    #[no_mangle]
+    pub unsafe extern "C" fn wgpu_compute_bundle_push_debug_group(
+        bundle: &mut ComputeBundleEncoder,
+        label: RawString,
+        color: u32,
+    ) {
+        span!(_guard, DEBUG, "ComputeBundle::push_debug_group");
+        let bytes = ffi::CStr::from_ptr(label).to_bytes();
+        bundle.base.string_data.extend_from_slice(bytes);
+
+        bundle.base.commands.push(ComputeCommand::PushDebugGroup {
+            color,
+            len: bytes.len(),
+        });
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wgpu_compute_bundle_pop_debug_group(bundle: &mut ComputeBundleEncoder) {
+        span!(_guard, DEBUG, "ComputeBundle::pop_debug_group");
+        bundle.base.commands.push(ComputeCommand::PopDebugGroup);
+    }
+
+    /// # Safety
+    ///
+    /// This function is unsafe as there is no guarantee that the given `label`
+    /// is a valid null-terminated string.
+    #[no_mangle]
+    pub unsafe extern "C" fn wgpu_compute_bundle_insert_debug_marker(
+        bundle: &mut ComputeBundleEncoder,
+        label: RawString,
+        color: u32,
+    ) {
+        span!(_guard, DEBUG, "ComputeBundle::insert_debug_marker");
+        let bytes = ffi::CStr::from_ptr(label).to_bytes();
+        bundle.base.string_data.extend_from_slice(bytes);
+
+        bundle
+            .base
+            .commands
+            .push(ComputeCommand::InsertDebugMarker {
+                color,
+                len: bytes.len(),
+            });
+    }
+}
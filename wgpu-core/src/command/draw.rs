@@ -53,8 +53,13 @@ pub enum RenderCommandError {
     InvalidBindGroup(id::BindGroupId),
     #[error("bind group index {index} is greater than the device's requested `max_bind_group` limit {max}")]
     BindGroupIndexOutOfRange { index: u8, max: u32 },
-    #[error("dynamic buffer offset {0} does not respect `BIND_BUFFER_ALIGNMENT`")]
-    UnalignedBufferOffset(u64),
+    #[error(
+        "dynamic buffer offset {offset} is not a multiple of the required alignment {alignment}"
+    )]
+    UnalignedBufferOffset {
+        offset: wgt::DynamicOffset,
+        alignment: u32,
+    },
     #[error("number of buffer offsets ({actual}) does not match the number of dynamic bindings ({expected})")]
     InvalidDynamicOffsetCount { actual: usize, expected: usize },
     #[error("render pipeline {0:?} is invalid")]
@@ -71,6 +76,74 @@ pub enum RenderCommandError {
     MissingTextureUsage(#[from] MissingTextureUsageError),
     #[error(transparent)]
     PushConstants(#[from] PushConstantUploadError),
+    #[error("required device features not enabled: {0:?}")]
+    MissingDeviceFeatures(wgt::Features),
+    #[error("indirect draw with offset {offset}{} uses bytes {begin_offset}..{end_offset} which overruns indirect buffer of size {buffer_size}", count.map_or_else(String::new, |v| format!(" and count {}", v)))]
+    IndirectBufferOverrun {
+        offset: u64,
+        count: Option<NonZeroU32>,
+        begin_offset: u64,
+        end_offset: u64,
+        buffer_size: u64,
+    },
+    #[error("indirect draw uses bytes {begin_count_offset}..{end_count_offset} which overruns indirect buffer of size {count_buffer_size}")]
+    IndirectCountBufferOverrun {
+        begin_count_offset: u64,
+        end_count_offset: u64,
+        count_buffer_size: u64,
+    },
+}
+
+pub(super) fn check_device_features(
+    actual: wgt::Features,
+    expected: wgt::Features,
+) -> Result<(), RenderCommandError> {
+    if !actual.contains(expected) {
+        Err(RenderCommandError::MissingDeviceFeatures(expected))
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks that `count` indirect argument structures of `stride` bytes each,
+/// starting at `offset`, fit into an indirect buffer of `buffer_size` bytes.
+pub(super) fn check_indirect_range(
+    offset: BufferAddress,
+    count: Option<NonZeroU32>,
+    stride: BufferAddress,
+    actual_count: u32,
+    buffer_size: BufferAddress,
+) -> Result<(), RenderCommandError> {
+    let end_offset = stride
+        .checked_mul(actual_count as BufferAddress)
+        .and_then(|size| offset.checked_add(size));
+    match end_offset {
+        Some(end_offset) if end_offset <= buffer_size => Ok(()),
+        _ => Err(RenderCommandError::IndirectBufferOverrun {
+            offset,
+            count,
+            begin_offset: offset,
+            end_offset: end_offset.unwrap_or(BufferAddress::MAX),
+            buffer_size,
+        }),
+    }
+}
+
+/// Checks that the 4-byte draw count at `count_buffer_offset` fits into
+/// a count buffer of `count_buffer_size` bytes.
+pub(super) fn check_indirect_count_range(
+    count_buffer_offset: BufferAddress,
+    count_buffer_size: BufferAddress,
+) -> Result<(), RenderCommandError> {
+    let end_count_offset = count_buffer_offset.checked_add(4);
+    match end_count_offset {
+        Some(end_count_offset) if end_count_offset <= count_buffer_size => Ok(()),
+        _ => Err(RenderCommandError::IndirectCountBufferOverrun {
+            begin_count_offset: count_buffer_offset,
+            end_count_offset: end_count_offset.unwrap_or(BufferAddress::MAX),
+            count_buffer_size,
+        }),
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
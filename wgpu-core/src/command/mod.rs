@@ -6,6 +6,7 @@ mod allocator;
 mod bind;
 mod bundle;
 mod compute;
+mod compute_bundle;
 mod draw;
 mod render;
 mod transfer;
@@ -14,6 +15,7 @@ pub(crate) use self::allocator::CommandAllocator;
 pub use self::allocator::CommandAllocatorError;
 pub use self::bundle::*;
 pub use self::compute::*;
+pub use self::compute_bundle::*;
 pub use self::draw::*;
 pub use self::render::*;
 pub use self::transfer::*;
@@ -22,23 +24,32 @@ use crate::{
     device::{all_buffer_stages, all_image_stages},
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Storage, Token},
     id,
-    resource::{Buffer, Texture},
+    resource::{Buffer, BufferUse, Texture, TextureUse},
     span,
     track::TrackerSet,
-    Label, PrivateFeatures, Stored,
+    Label, PrivateFeatures, Stored, SubmissionIndex,
 };
 
 use hal::command::CommandBuffer as _;
 use thiserror::Error;
 
-use std::thread::ThreadId;
+use std::{ops::Range, thread::ThreadId};
 
 const PUSH_CONSTANT_CLEAR_ARRAY: &[u32] = &[0_u32; 64];
 
 #[derive(Debug)]
 pub struct CommandBuffer<B: hal::Backend> {
     pub(crate) raw: Vec<B::CommandBuffer>,
+    /// Secondary command buffers executed by `raw`, kept alive until the submission is done,
+    /// along with the indices of the secondary pools owning them.
+    secondary: Vec<(usize, B::CommandBuffer)>,
     is_recording: bool,
+    /// If the command buffer can be submitted more than once. While recording,
+    /// tells if the encoder was created as reusable.
+    pub(crate) reusable: bool,
+    /// Index of the last submission of this command buffer, if any.
+    pub(crate) submission_index: Option<SubmissionIndex>,
+    debug_scope_depth: u32,
     recorded_thread_id: ThreadId,
     pub(crate) device_id: Stored<id::DeviceId>,
     pub(crate) trackers: TrackerSet,
@@ -61,6 +72,15 @@ impl<B: GfxBackend> CommandBuffer<B> {
         }
     }
 
+    /// Flags for beginning the raw command buffers of this encoder.
+    pub(crate) fn begin_flags(&self) -> hal::command::CommandBufferFlags {
+        if self.reusable {
+            hal::command::CommandBufferFlags::empty()
+        } else {
+            hal::command::CommandBufferFlags::ONE_TIME_SUBMIT
+        }
+    }
+
     pub(crate) fn insert_barriers(
         raw: &mut B::CommandBuffer,
         base: &mut TrackerSet,
@@ -89,6 +109,9 @@ impl<B: GfxBackend> CommandBuffer<B> {
             .unwrap();
         base.render_pipes.merge_extend(&head.render_pipes).unwrap();
         base.bundles.merge_extend(&head.bundles).unwrap();
+        base.compute_bundles
+            .merge_extend(&head.compute_bundles)
+            .unwrap();
 
         let stages = all_buffer_stages() | all_image_stages();
         unsafe {
@@ -110,7 +133,7 @@ pub struct BasePassRef<'a, C> {
 }
 
 #[doc(hidden)]
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[cfg_attr(
     any(feature = "serial-pass", feature = "trace"),
     derive(serde::Serialize)
@@ -162,13 +185,38 @@ pub enum CommandEncoderError {
     Invalid,
     #[error("command encoder must be active")]
     NotRecording,
+    #[error("cannot pop debug group, because number of pushed debug groups is zero")]
+    InvalidPopDebugGroup,
+    #[error("command encoder has {0} unclosed debug groups")]
+    UnclosedDebugGroups(u32),
+    #[error("reusable command buffers can't use swap chain images")]
+    ReusableWithSwapChain,
+    #[error("command buffer can't be reusable, because its encoder wasn't created as reusable")]
+    EncoderNotReusable,
+}
+
+fn pop_debug_group(depth: &mut u32) -> Result<(), CommandEncoderError> {
+    match depth.checked_sub(1) {
+        Some(new_depth) => {
+            *depth = new_depth;
+            Ok(())
+        }
+        None => Err(CommandEncoderError::InvalidPopDebugGroup),
+    }
+}
+
+fn check_debug_groups_closed(depth: u32) -> Result<(), CommandEncoderError> {
+    match depth {
+        0 => Ok(()),
+        _ => Err(CommandEncoderError::UnclosedDebugGroups(depth)),
+    }
 }
 
 impl<G: GlobalIdentityHandlerFactory> Global<G> {
     pub fn command_encoder_finish<B: GfxBackend>(
         &self,
         encoder_id: id::CommandEncoderId,
-        _desc: &wgt::CommandBufferDescriptor<Label>,
+        desc: &wgt::CommandBufferDescriptor<Label>,
     ) -> Result<id::CommandBufferId, CommandEncoderError> {
         span!(_guard, INFO, "CommandEncoder::finish");
 
@@ -178,7 +226,16 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         //TODO: actually close the last recorded command buffer
         let (mut cmd_buf_guard, _) = hub.command_buffers.write(&mut token);
         let cmd_buf = CommandBuffer::get_encoder(&mut *cmd_buf_guard, encoder_id)?;
+        check_debug_groups_closed(cmd_buf.debug_scope_depth)?;
+        if desc.reusable && !cmd_buf.reusable {
+            return Err(CommandEncoderError::EncoderNotReusable);
+        }
+        // The swap chain framebuffer is released at presentation.
+        if desc.reusable && cmd_buf.used_swap_chain.is_some() {
+            return Err(CommandEncoderError::ReusableWithSwapChain);
+        }
         cmd_buf.is_recording = false;
+        cmd_buf.reusable = desc.reusable;
         // stop tracking the swapchain image, if used
         if let Some((ref sc_id, _)) = cmd_buf.used_swap_chain {
             let view_id = swap_chain_guard[sc_id.value]
@@ -203,6 +260,18 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let (mut cmd_buf_guard, _) = hub.command_buffers.write(&mut token);
         let cmd_buf = CommandBuffer::get_encoder(&mut *cmd_buf_guard, encoder_id)?;
+
+        #[cfg(feature = "trace")]
+        match cmd_buf.commands {
+            Some(ref mut list) => {
+                list.push(crate::device::trace::Command::PushDebugGroup(
+                    label.to_string(),
+                ));
+            }
+            None => {}
+        }
+
+        cmd_buf.debug_scope_depth += 1;
         let cmb_raw = cmd_buf.raw.last_mut().unwrap();
 
         unsafe {
@@ -223,6 +292,17 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let (mut cmd_buf_guard, _) = hub.command_buffers.write(&mut token);
         let cmd_buf = CommandBuffer::get_encoder(&mut *cmd_buf_guard, encoder_id)?;
+
+        #[cfg(feature = "trace")]
+        match cmd_buf.commands {
+            Some(ref mut list) => {
+                list.push(crate::device::trace::Command::InsertDebugMarker(
+                    label.to_string(),
+                ));
+            }
+            None => {}
+        }
+
         let cmb_raw = cmd_buf.raw.last_mut().unwrap();
 
         unsafe {
@@ -242,6 +322,17 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let (mut cmd_buf_guard, _) = hub.command_buffers.write(&mut token);
         let cmd_buf = CommandBuffer::get_encoder(&mut *cmd_buf_guard, encoder_id)?;
+        // Invalid pops are not recorded, since they would fail on replay.
+        pop_debug_group(&mut cmd_buf.debug_scope_depth)?;
+
+        #[cfg(feature = "trace")]
+        match cmd_buf.commands {
+            Some(ref mut list) => {
+                list.push(crate::device::trace::Command::PopDebugGroup);
+            }
+            None => {}
+        }
+
         let cmb_raw = cmd_buf.raw.last_mut().unwrap();
 
         unsafe {
@@ -253,10 +344,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
 #[derive(Clone, Debug, Error)]
 pub enum UsageConflict {
-    #[error("buffer {0:?} combined usage is {1:?}")]
-    Buffer(id::BufferId, wgt::BufferUsage),
-    #[error("texture {0:?} combined usage is {1:?}")]
-    Texture(id::TextureId, wgt::TextureUsage),
+    #[error("buffer {0:?} is used with conflicting usages {1:?}")]
+    Buffer(id::BufferId, Range<BufferUse>),
+    #[error("texture {0:?} is used with conflicting usages {1:?}")]
+    Texture(id::TextureId, Range<TextureUse>),
 }
 
 fn push_constant_clear<PushFn>(offset: u32, size_bytes: u32, mut push_fn: PushFn)
@@ -278,3 +369,26 @@ where
         count_words += size_to_write_words;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{check_debug_groups_closed, pop_debug_group, CommandEncoderError};
+
+    #[test]
+    fn debug_groups() {
+        let mut depth = 2;
+        assert!(check_debug_groups_closed(0).is_ok());
+        match check_debug_groups_closed(depth) {
+            Err(CommandEncoderError::UnclosedDebugGroups(2)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        pop_debug_group(&mut depth).unwrap();
+        pop_debug_group(&mut depth).unwrap();
+        assert_eq!(depth, 0);
+        match pop_debug_group(&mut depth) {
+            Err(CommandEncoderError::InvalidPopDebugGroup) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(depth, 0);
+    }
+}
@@ -3,21 +3,22 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::{
-    binding_model::BindError,
+    binding_model::{BindError, BindGroup, PipelineLayout},
     command::{
         bind::{Binder, LayoutChange},
-        BasePass, BasePassRef, CommandBuffer, CommandEncoderError, DrawError, RenderCommand,
-        RenderCommandError,
+        check_device_features, check_indirect_count_range, check_indirect_range, BasePass,
+        BasePassRef, CommandAllocator, CommandBuffer, CommandEncoderError, DrawError, RenderBundle,
+        RenderCommand, RenderCommandError, UsageConflict,
     },
     conv,
     device::{
         AttachmentData, AttachmentDataVec, FramebufferKey, RenderPassContext, RenderPassKey,
         MAX_COLOR_TARGETS, MAX_VERTEX_BUFFERS,
     },
-    hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Token},
+    hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Storage, Token},
     id,
-    pipeline::PipelineFlags,
-    resource::{BufferUse, TextureUse, TextureView, TextureViewInner},
+    pipeline::{PipelineFlags, RenderPipeline},
+    resource::{Buffer, BufferUse, TextureUse, TextureView, TextureViewInner},
     span,
     track::{TextureSelector, TrackerSet},
     validation::{
@@ -28,6 +29,7 @@ use crate::{
 
 use arrayvec::ArrayVec;
 use hal::command::CommandBuffer as _;
+use parking_lot::Mutex;
 use thiserror::Error;
 use wgt::{BufferAddress, BufferUsage, Color, IndexFormat, InputStepMode, TextureUsage};
 
@@ -40,7 +42,6 @@ use std::{
     borrow::{Borrow, Cow},
     collections::hash_map::Entry,
     fmt, iter,
-    num::NonZeroU32,
     ops::Range,
     str,
 };
@@ -163,6 +164,36 @@ impl RenderPass {
     pub fn parent_id(&self) -> id::CommandEncoderId {
         self.parent_id
     }
+
+    /// Creates an empty pass with the same parent and attachments.
+    ///
+    /// Sub-passes can be recorded independently, e.g. on different threads,
+    /// and then executed as a single render pass with
+    /// `Global::command_encoder_run_parallel_render_pass`.
+    pub fn sub_pass(&self) -> Self {
+        RenderPass {
+            base: BasePass::new(),
+            parent_id: self.parent_id,
+            color_targets: self.color_targets.clone(),
+            depth_stencil_target: self.depth_stencil_target.clone(),
+        }
+    }
+}
+
+/// Encoding job of a single sub-pass of a parallel render pass.
+pub type SubPassJob<'a> = dyn Fn() + Sync + 'a;
+
+/// Runs the encoding jobs of a parallel render pass, e.g. on a thread pool of the caller.
+///
+/// The jobs borrow the state of the pass, so they have to be finished by the time
+/// the executor returns. Any job that isn't run by then is run inline afterwards.
+pub type SubPassExecutor<'a> = dyn Fn(&[&SubPassJob]) + 'a;
+
+/// Runs the sub-pass jobs one after another, on the calling thread.
+pub fn run_sub_passes_inline(jobs: &[&SubPassJob]) {
+    for job in jobs {
+        job();
+    }
 }
 
 impl fmt::Debug for RenderPass {
@@ -341,26 +372,16 @@ pub enum RenderPassError {
     SwapChainMismatch,
     #[error("setting `values_offset` to be `None` is only for internal use in render bundles")]
     InvalidValuesOffset,
-    #[error("required device features not enabled: {0:?}")]
-    MissingDeviceFeatures(wgt::Features),
-    #[error("indirect draw with offset {offset}{} uses bytes {begin_offset}..{end_offset} which overruns indirect buffer of size {buffer_size}", count.map_or_else(String::new, |v| format!(" and count {}", v)))]
-    IndirectBufferOverrun {
-        offset: u64,
-        count: Option<NonZeroU32>,
-        begin_offset: u64,
-        end_offset: u64,
-        buffer_size: u64,
-    },
-    #[error("indirect draw uses bytes {begin_count_offset}..{end_count_offset} which overruns indirect buffer of size {count_buffer_size}")]
-    IndirectCountBufferOverrun {
-        begin_count_offset: u64,
-        end_count_offset: u64,
-        count_buffer_size: u64,
-    },
     #[error("cannot pop debug group, because number of pushed debug groups is zero")]
     InvalidPopDebugGroup,
     #[error("render bundle output formats do not match render pass attachment formats")]
     IncompatibleRenderBundle,
+    #[error("parallel render pass has no sub-passes")]
+    NoSubPasses,
+    #[error("render sub-pass {0} does not match the parent or attachments of the first sub-pass")]
+    SubPassMismatch(usize),
+    #[error(transparent)]
+    ResourceUsageConflict(UsageConflict),
     #[error(transparent)]
     RenderCommand(#[from] RenderCommandError),
     #[error(transparent)]
@@ -381,1128 +402,1305 @@ impl From<MissingTextureUsageError> for RenderPassError {
     }
 }
 
-fn check_device_features(
-    actual: wgt::Features,
-    expected: wgt::Features,
-) -> Result<(), RenderPassError> {
-    if !actual.contains(expected) {
-        Err(RenderPassError::MissingDeviceFeatures(expected))
-    } else {
-        Ok(())
-    }
+/// Parameters and resources shared by the sub-passes of a render pass.
+struct SubPassEnv<'a, B: GfxBackend> {
+    features: wgt::Features,
+    max_bind_groups: u32,
+    context: &'a RenderPassContext,
+    rect: hal::pso::Rect,
+    is_ds_read_only: bool,
+    bundle_guard: &'a Storage<RenderBundle, id::RenderBundleId>,
+    pipeline_layout_guard: &'a Storage<PipelineLayout<B>, id::PipelineLayoutId>,
+    bind_group_guard: &'a Storage<BindGroup<B>, id::BindGroupId>,
+    pipeline_guard: &'a Storage<RenderPipeline<B>, id::RenderPipelineId>,
+    buffer_guard: &'a Storage<Buffer<B>, id::BufferId>,
 }
 
-// Common routines between render/compute
-
-impl<G: GlobalIdentityHandlerFactory> Global<G> {
-    pub fn command_encoder_run_render_pass<B: GfxBackend>(
-        &self,
-        encoder_id: id::CommandEncoderId,
-        pass: &RenderPass,
-    ) -> Result<(), RenderPassError> {
-        self.command_encoder_run_render_pass_impl::<B>(
-            encoder_id,
-            pass.base.as_ref(),
-            &pass.color_targets,
-            pass.depth_stencil_target.as_ref(),
-        )
-    }
-
-    #[doc(hidden)]
-    pub fn command_encoder_run_render_pass_impl<B: GfxBackend>(
+impl<B: GfxBackend> SubPassEnv<'_, B> {
+    /// Validate the commands of a sub-pass, and encode them into `raw`.
+    ///
+    /// The used resources are gathered into `trackers`.
+    fn encode(
         &self,
-        encoder_id: id::CommandEncoderId,
+        raw: &mut B::CommandBuffer,
         mut base: BasePassRef<RenderCommand>,
-        color_attachments: &[ColorAttachmentDescriptor],
-        depth_stencil_attachment: Option<&DepthStencilAttachmentDescriptor>,
+        trackers: &mut TrackerSet,
     ) -> Result<(), RenderPassError> {
-        span!(_guard, INFO, "CommandEncoder::run_render_pass");
-
-        let hub = B::hub(self);
-        let mut token = Token::root();
-
-        let (device_guard, mut token) = hub.devices.read(&mut token);
-        let (mut cmb_guard, mut token) = hub.command_buffers.write(&mut token);
-
-        let mut trackers = TrackerSet::new(B::VARIANT);
-        let cmd_buf = CommandBuffer::get_encoder(&mut *cmb_guard, encoder_id)?;
-        let device = &device_guard[cmd_buf.device_id.value];
-        let mut raw = device.cmd_allocator.extend(cmd_buf);
-
-        #[cfg(feature = "trace")]
-        match cmd_buf.commands {
-            Some(ref mut list) => {
-                list.push(crate::device::trace::Command::RunRenderPass {
-                    base: BasePass::from_ref(base),
-                    target_colors: color_attachments.iter().cloned().collect(),
-                    target_depth_stencil: depth_stencil_attachment.cloned(),
-                });
-            }
-            None => {}
-        }
+        let SubPassEnv {
+            features,
+            max_bind_groups,
+            context,
+            rect,
+            is_ds_read_only,
+            bundle_guard,
+            pipeline_layout_guard,
+            bind_group_guard,
+            pipeline_guard,
+            buffer_guard,
+        } = *self;
 
         unsafe {
-            raw.begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
+            raw.set_scissors(0, iter::once(&rect));
+            raw.set_viewports(
+                0,
+                iter::once(hal::pso::Viewport {
+                    rect,
+                    depth: 0.0..1.0,
+                }),
+            );
         }
 
-        let (bundle_guard, mut token) = hub.render_bundles.read(&mut token);
-        let (pipeline_layout_guard, mut token) = hub.pipeline_layouts.read(&mut token);
-        let (bind_group_guard, mut token) = hub.bind_groups.read(&mut token);
-        let (pipeline_guard, mut token) = hub.render_pipelines.read(&mut token);
-        let (buffer_guard, mut token) = hub.buffers.read(&mut token);
-        let (texture_guard, mut token) = hub.textures.read(&mut token);
-        let (view_guard, _) = hub.texture_views.read(&mut token);
-
-        // We default to false intentionally, even if depth-stencil isn't used at all.
-        // This allows us to use the primary raw pipeline in `RenderPipeline`,
-        // instead of the special read-only one, which would be `None`.
-        let mut is_ds_read_only = false;
+        let mut temp_offsets = Vec::new();
+        let mut state = State {
+            binder: Binder::new(max_bind_groups),
+            blend_color: OptionalState::Unused,
+            stencil_reference: OptionalState::Unused,
+            pipeline: OptionalState::Required,
+            index: IndexState::default(),
+            vertex: VertexState::default(),
+            debug_scope_depth: 0,
+        };
 
-        struct OutputAttachment<'a> {
-            texture_id: &'a Stored<id::TextureId>,
-            selector: &'a TextureSelector,
-            previous_use: Option<TextureUse>,
-            new_use: TextureUse,
-        }
-        let mut output_attachments = AttachmentDataVec::<OutputAttachment>::new();
+        for command in base.commands {
+            match *command {
+                RenderCommand::SetBindGroup {
+                    index,
+                    num_dynamic_offsets,
+                    bind_group_id,
+                } => {
+                    if (index as u32) >= max_bind_groups {
+                        return Err(RenderCommandError::BindGroupIndexOutOfRange {
+                            index,
+                            max: max_bind_groups,
+                        }
+                        .into());
+                    }
 
-        let context = {
-            use hal::device::Device as _;
+                    temp_offsets.clear();
+                    temp_offsets
+                        .extend_from_slice(&base.dynamic_offsets[..num_dynamic_offsets as usize]);
+                    base.dynamic_offsets = &base.dynamic_offsets[num_dynamic_offsets as usize..];
 
-            let sample_count_limit = device.hal_limits.framebuffer_color_sample_counts;
-            let base_trackers = &cmd_buf.trackers;
+                    let bind_group = trackers
+                        .bind_groups
+                        .use_extend(bind_group_guard, bind_group_id, (), ())
+                        .unwrap();
+                    bind_group
+                        .validate_dynamic_bindings(&temp_offsets)
+                        .map_err(RenderPassError::from)?;
 
-            let mut extent = None;
-            let mut sample_count = 0;
-            let mut depth_stencil_aspects = hal::format::Aspects::empty();
-            let mut used_swap_chain = None::<Stored<id::SwapChainId>>;
+                    trackers.merge_extend(&bind_group.used);
 
-            let mut add_view = |view: &TextureView<B>| {
-                if let Some(ex) = extent {
-                    if ex != view.extent {
-                        return Err(RenderPassError::ExtentStateMismatch {
-                            state_extent: ex,
-                            view_extent: view.extent,
-                        });
-                    }
-                } else {
-                    extent = Some(view.extent);
-                }
-                if sample_count == 0 {
-                    sample_count = view.samples;
-                } else if sample_count != view.samples {
-                    return Err(RenderPassError::SampleCountMismatch {
-                        actual: view.samples,
-                        expected: sample_count,
-                    });
+                    if let Some((pipeline_layout_id, follow_ups)) = state.binder.provide_entry(
+                        index as usize,
+                        id::Valid(bind_group_id),
+                        bind_group,
+                        &temp_offsets,
+                    ) {
+                        let bind_groups = iter::once(bind_group.raw.raw())
+                            .chain(
+                                follow_ups
+                                    .clone()
+                                    .map(|(bg_id, _)| bind_group_guard[bg_id].raw.raw()),
+                            )
+                            .collect::<ArrayVec<[_; MAX_BIND_GROUPS]>>();
+                        temp_offsets.extend(follow_ups.flat_map(|(_, offsets)| offsets));
+                        unsafe {
+                            raw.bind_graphics_descriptor_sets(
+                                &pipeline_layout_guard[pipeline_layout_id].raw,
+                                index as usize,
+                                bind_groups,
+                                &temp_offsets,
+                            );
+                        }
+                    };
                 }
-                Ok(())
-            };
-
-            tracing::trace!(
-                "Encoding render pass begin in command buffer {:?}",
-                encoder_id
-            );
-            let rp_key = {
-                let depth_stencil = match depth_stencil_attachment {
-                    Some(at) => {
-                        let view = trackers
-                            .views
-                            .use_extend(&*view_guard, at.attachment, (), ())
-                            .map_err(|_| RenderPassError::InvalidAttachment(at.attachment))?;
-                        add_view(view)?;
-                        depth_stencil_aspects = view.aspects;
-
-                        let source_id = match view.inner {
-                            TextureViewInner::Native { ref source_id, .. } => source_id,
-                            TextureViewInner::SwapChain { .. } => {
-                                return Err(RenderPassError::SwapChainImageAsDepthStencil)
-                            }
-                        };
+                RenderCommand::SetPipeline(pipeline_id) => {
+                    state.pipeline = OptionalState::Set;
+                    let pipeline = trackers
+                        .render_pipes
+                        .use_extend(pipeline_guard, pipeline_id, (), ())
+                        .unwrap();
 
-                        // Using render pass for transition.
-                        let previous_use = base_trackers
-                            .textures
-                            .query(source_id.value, view.selector.clone());
-                        let new_use = if at.is_read_only(view.aspects)? {
-                            is_ds_read_only = true;
-                            TextureUse::ATTACHMENT_READ
-                        } else {
-                            TextureUse::ATTACHMENT_WRITE
-                        };
-                        output_attachments.push(OutputAttachment {
-                            texture_id: source_id,
-                            selector: &view.selector,
-                            previous_use,
-                            new_use,
-                        });
+                    if !context.compatible(&pipeline.pass_context) {
+                        return Err(RenderCommandError::IncompatiblePipeline.into());
+                    }
+                    if pipeline.flags.contains(PipelineFlags::WRITES_DEPTH_STENCIL)
+                        && is_ds_read_only
+                    {
+                        return Err(RenderCommandError::IncompatibleReadOnlyDepthStencil.into());
+                    }
 
-                        let new_layout = conv::map_texture_state(new_use, view.aspects).1;
-                        let old_layout = match previous_use {
-                            Some(usage) => conv::map_texture_state(usage, view.aspects).1,
-                            None => new_layout,
-                        };
+                    state
+                        .blend_color
+                        .require(pipeline.flags.contains(PipelineFlags::BLEND_COLOR));
+                    state
+                        .stencil_reference
+                        .require(pipeline.flags.contains(PipelineFlags::STENCIL_REFERENCE));
 
-                        let ds_at = hal::pass::Attachment {
-                            format: Some(conv::map_texture_format(
-                                view.format,
-                                device.private_features,
-                            )),
-                            samples: view.samples,
-                            ops: conv::map_load_store_ops(&at.depth),
-                            stencil_ops: conv::map_load_store_ops(&at.stencil),
-                            layouts: old_layout..new_layout,
-                        };
-                        Some((ds_at, new_layout))
+                    unsafe {
+                        raw.bind_graphics_pipeline(&pipeline.raw);
                     }
-                    None => None,
-                };
 
-                let mut colors = ArrayVec::new();
-                let mut resolves = ArrayVec::new();
+                    // Rebind resource
+                    if state.binder.pipeline_layout_id != Some(pipeline.layout_id.value) {
+                        let pipeline_layout = &pipeline_layout_guard[pipeline.layout_id.value];
 
-                for at in color_attachments {
-                    let view = trackers
-                        .views
-                        .use_extend(&*view_guard, at.attachment, (), ())
-                        .map_err(|_| RenderPassError::InvalidAttachment(at.attachment))?;
-                    add_view(view)?;
+                        state.binder.change_pipeline_layout(
+                            pipeline_layout_guard,
+                            pipeline.layout_id.value,
+                        );
 
-                    let layouts = match view.inner {
-                        TextureViewInner::Native { ref source_id, .. } => {
-                            let previous_use = base_trackers
-                                .textures
-                                .query(source_id.value, view.selector.clone());
-                            let new_use = TextureUse::ATTACHMENT_WRITE;
-                            output_attachments.push(OutputAttachment {
-                                texture_id: source_id,
-                                selector: &view.selector,
-                                previous_use,
-                                new_use,
-                            });
+                        let mut is_compatible = true;
 
-                            let new_layout =
-                                conv::map_texture_state(new_use, hal::format::Aspects::COLOR).1;
-                            let old_layout = match previous_use {
-                                Some(usage) => {
-                                    conv::map_texture_state(usage, hal::format::Aspects::COLOR).1
+                        for (index, (entry, &bgl_id)) in state
+                            .binder
+                            .entries
+                            .iter_mut()
+                            .zip(&pipeline_layout.bind_group_layout_ids)
+                            .enumerate()
+                        {
+                            match entry.expect_layout(bgl_id) {
+                                LayoutChange::Match(bg_id, offsets) if is_compatible => {
+                                    let desc_set = bind_group_guard[bg_id].raw.raw();
+                                    unsafe {
+                                        raw.bind_graphics_descriptor_sets(
+                                            &pipeline_layout.raw,
+                                            index,
+                                            iter::once(desc_set),
+                                            offsets.iter().cloned(),
+                                        );
+                                    }
                                 }
-                                None => new_layout,
-                            };
-                            old_layout..new_layout
-                        }
-                        TextureViewInner::SwapChain { ref source_id, .. } => {
-                            if let Some((ref sc_id, _)) = cmd_buf.used_swap_chain {
-                                if source_id.value != sc_id.value {
-                                    return Err(RenderPassError::SwapChainMismatch);
+                                LayoutChange::Match(..) | LayoutChange::Unchanged => {}
+                                LayoutChange::Mismatch => {
+                                    is_compatible = false;
                                 }
-                            } else {
-                                assert!(used_swap_chain.is_none());
-                                used_swap_chain = Some(source_id.clone());
                             }
-
-                            let end = hal::image::Layout::Present;
-                            let start = match at.channel.load_op {
-                                LoadOp::Clear => hal::image::Layout::Undefined,
-                                LoadOp::Load => end,
-                            };
-                            start..end
                         }
-                    };
-
-                    let color_at = hal::pass::Attachment {
-                        format: Some(conv::map_texture_format(
-                            view.format,
-                            device.private_features,
-                        )),
-                        samples: view.samples,
-                        ops: conv::map_load_store_ops(&at.channel),
-                        stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
-                        layouts,
-                    };
-                    colors.push((color_at, hal::image::Layout::ColorAttachmentOptimal));
-                }
 
-                for resolve_target in color_attachments.iter().flat_map(|at| at.resolve_target) {
-                    let view = trackers
-                        .views
-                        .use_extend(&*view_guard, resolve_target, (), ())
-                        .map_err(|_| RenderPassError::InvalidAttachment(resolve_target))?;
-                    if extent != Some(view.extent) {
-                        return Err(RenderPassError::ExtentStateMismatch {
-                            state_extent: extent.unwrap_or_default(),
-                            view_extent: view.extent,
-                        });
-                    }
-                    if view.samples != 1 {
-                        return Err(RenderPassError::InvalidResolveTargetSampleCount);
-                    }
-                    if sample_count == 1 {
-                        return Err(RenderPassError::InvalidResolveSourceSampleCount);
+                        // Clear push constant ranges
+                        let non_overlapping = super::bind::compute_nonoverlapping_ranges(
+                            &pipeline_layout.push_constant_ranges,
+                        );
+                        for range in non_overlapping {
+                            let offset = range.range.start;
+                            let size_bytes = range.range.end - offset;
+                            super::push_constant_clear(
+                                offset,
+                                size_bytes,
+                                |clear_offset, clear_data| unsafe {
+                                    raw.push_graphics_constants(
+                                        &pipeline_layout.raw,
+                                        conv::map_shader_stage_flags(range.stages),
+                                        clear_offset,
+                                        clear_data,
+                                    );
+                                },
+                            );
+                        }
                     }
 
-                    let layouts = match view.inner {
-                        TextureViewInner::Native { ref source_id, .. } => {
-                            let previous_use = base_trackers
-                                .textures
-                                .query(source_id.value, view.selector.clone());
-                            let new_use = TextureUse::ATTACHMENT_WRITE;
-                            output_attachments.push(OutputAttachment {
-                                texture_id: source_id,
-                                selector: &view.selector,
-                                previous_use,
-                                new_use,
-                            });
+                    // Rebind index buffer if the index format has changed with the pipeline switch
+                    if state.index.format != pipeline.index_format {
+                        state.index.format = pipeline.index_format;
+                        state.index.update_limit();
 
-                            let new_layout =
-                                conv::map_texture_state(new_use, hal::format::Aspects::COLOR).1;
-                            let old_layout = match previous_use {
-                                Some(usage) => {
-                                    conv::map_texture_state(usage, hal::format::Aspects::COLOR).1
-                                }
-                                None => new_layout,
+                        if let Some((buffer_id, ref range)) = state.index.bound_buffer_view {
+                            let buffer = trackers
+                                .buffers
+                                .use_extend(buffer_guard, buffer_id, (), BufferUse::INDEX)
+                                .unwrap();
+
+                            let view = hal::buffer::IndexBufferView {
+                                buffer: &buffer.raw,
+                                range: hal::buffer::SubRange {
+                                    offset: range.start,
+                                    size: Some(range.end - range.start),
+                                },
+                                index_type: conv::map_index_format(state.index.format),
                             };
-                            old_layout..new_layout
-                        }
-                        TextureViewInner::SwapChain { ref source_id, .. } => {
-                            if let Some((ref sc_id, _)) = cmd_buf.used_swap_chain {
-                                if source_id.value != sc_id.value {
-                                    return Err(RenderPassError::SwapChainMismatch);
-                                }
-                            } else {
-                                assert!(used_swap_chain.is_none());
-                                used_swap_chain = Some(source_id.clone());
+
+                            unsafe {
+                                raw.bind_index_buffer(view);
                             }
-                            hal::image::Layout::Undefined..hal::image::Layout::Present
                         }
+                    }
+                    // Update vertex buffer limits
+                    for (vbs, &(stride, rate)) in
+                        state.vertex.inputs.iter_mut().zip(&pipeline.vertex_strides)
+                    {
+                        vbs.stride = stride;
+                        vbs.rate = rate;
+                    }
+                    let vertex_strides_len = pipeline.vertex_strides.len();
+                    for vbs in state.vertex.inputs.iter_mut().skip(vertex_strides_len) {
+                        vbs.stride = 0;
+                        vbs.rate = InputStepMode::Vertex;
+                    }
+                    state.vertex.update_limits();
+                }
+                RenderCommand::SetIndexBuffer {
+                    buffer_id,
+                    offset,
+                    size,
+                } => {
+                    let buffer = trackers
+                        .buffers
+                        .use_extend(buffer_guard, buffer_id, (), BufferUse::INDEX)
+                        .unwrap();
+                    check_buffer_usage(buffer.usage, BufferUsage::INDEX)?;
+
+                    let end = match size {
+                        Some(s) => offset + s.get(),
+                        None => buffer.size,
                     };
+                    state.index.bound_buffer_view = Some((buffer_id, offset..end));
+                    state.index.update_limit();
 
-                    let resolve_at = hal::pass::Attachment {
-                        format: Some(conv::map_texture_format(
-                            view.format,
-                            device.private_features,
-                        )),
-                        samples: view.samples,
-                        ops: hal::pass::AttachmentOps::new(
-                            hal::pass::AttachmentLoadOp::DontCare,
-                            hal::pass::AttachmentStoreOp::Store,
-                        ),
-                        stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
-                        layouts,
+                    let view = hal::buffer::IndexBufferView {
+                        buffer: &buffer.raw,
+                        range: hal::buffer::SubRange {
+                            offset,
+                            size: Some(end - offset),
+                        },
+                        index_type: conv::map_index_format(state.index.format),
                     };
-                    resolves.push((resolve_at, hal::image::Layout::ColorAttachmentOptimal));
-                }
 
-                RenderPassKey {
-                    colors,
-                    resolves,
-                    depth_stencil,
+                    unsafe {
+                        raw.bind_index_buffer(view);
+                    }
                 }
-            };
-
-            if sample_count & sample_count_limit == 0 {
-                return Err(RenderPassError::InvalidSampleCount(sample_count));
-            }
-
-            let mut render_pass_cache = device.render_passes.lock();
-            let render_pass = match render_pass_cache.entry(rp_key.clone()) {
-                Entry::Occupied(e) => e.into_mut(),
-                Entry::Vacant(entry) => {
-                    let color_ids: [hal::pass::AttachmentRef; MAX_COLOR_TARGETS] = [
-                        (0, hal::image::Layout::ColorAttachmentOptimal),
-                        (1, hal::image::Layout::ColorAttachmentOptimal),
-                        (2, hal::image::Layout::ColorAttachmentOptimal),
-                        (3, hal::image::Layout::ColorAttachmentOptimal),
-                    ];
+                RenderCommand::SetVertexBuffer {
+                    slot,
+                    buffer_id,
+                    offset,
+                    size,
+                } => {
+                    let buffer = trackers
+                        .buffers
+                        .use_extend(buffer_guard, buffer_id, (), BufferUse::VERTEX)
+                        .unwrap();
+                    check_buffer_usage(buffer.usage, BufferUsage::VERTEX)?;
+                    let empty_slots = (1 + slot as usize).saturating_sub(state.vertex.inputs.len());
+                    state
+                        .vertex
+                        .inputs
+                        .extend(iter::repeat(VertexBufferState::EMPTY).take(empty_slots));
+                    state.vertex.inputs[slot as usize].total_size = match size {
+                        Some(s) => s.get(),
+                        None => buffer.size - offset,
+                    };
 
-                    let mut resolve_ids = ArrayVec::<[_; MAX_COLOR_TARGETS]>::new();
-                    let mut attachment_index = color_attachments.len();
-                    if color_attachments
-                        .iter()
-                        .any(|at| at.resolve_target.is_some())
-                    {
-                        for ((i, at), &(_, layout)) in color_attachments
-                            .iter()
-                            .enumerate()
-                            .zip(entry.key().resolves.iter())
-                        {
-                            let real_attachment_index = match at.resolve_target {
-                                Some(_) => attachment_index + i,
-                                None => hal::pass::ATTACHMENT_UNUSED,
-                            };
-                            resolve_ids.push((real_attachment_index, layout));
-                        }
-                        attachment_index += color_attachments.len();
+                    let range = hal::buffer::SubRange {
+                        offset,
+                        size: size.map(|s| s.get()),
+                    };
+                    unsafe {
+                        raw.bind_vertex_buffers(slot, iter::once((&buffer.raw, range)));
+                    }
+                    state.vertex.update_limits();
+                }
+                RenderCommand::SetBlendColor(ref color) => {
+                    state.blend_color = OptionalState::Set;
+                    unsafe {
+                        raw.set_blend_constants(conv::map_color_f32(color));
+                    }
+                }
+                RenderCommand::SetStencilReference(value) => {
+                    state.stencil_reference = OptionalState::Set;
+                    unsafe {
+                        raw.set_stencil_reference(hal::pso::Face::all(), value);
+                    }
+                }
+                RenderCommand::SetViewport {
+                    ref rect,
+                    depth_min,
+                    depth_max,
+                } => {
+                    use std::{convert::TryFrom, i16};
+                    let r = hal::pso::Rect {
+                        x: i16::try_from(rect.x.round() as i64).unwrap_or(0),
+                        y: i16::try_from(rect.y.round() as i64).unwrap_or(0),
+                        w: i16::try_from(rect.w.round() as i64).unwrap_or(i16::MAX),
+                        h: i16::try_from(rect.h.round() as i64).unwrap_or(i16::MAX),
+                    };
+                    unsafe {
+                        raw.set_viewports(
+                            0,
+                            iter::once(hal::pso::Viewport {
+                                rect: r,
+                                depth: depth_min..depth_max,
+                            }),
+                        );
                     }
+                }
+                RenderCommand::SetPushConstant {
+                    stages,
+                    offset,
+                    size_bytes,
+                    values_offset,
+                } => {
+                    let values_offset =
+                        values_offset.ok_or(RenderPassError::InvalidValuesOffset)?;
 
-                    let depth_id = depth_stencil_attachment.map(|_| {
-                        let usage = if is_ds_read_only {
-                            TextureUse::ATTACHMENT_READ
-                        } else {
-                            TextureUse::ATTACHMENT_WRITE
-                        };
-                        (
-                            attachment_index,
-                            conv::map_texture_state(usage, depth_stencil_aspects).1,
-                        )
-                    });
+                    let end_offset_bytes = offset + size_bytes;
+                    let values_end_offset = (values_offset + size_bytes / 4) as usize;
+                    let data_slice =
+                        &base.push_constant_data[(values_offset as usize)..values_end_offset];
 
-                    let subpass = hal::pass::SubpassDesc {
-                        colors: &color_ids[..color_attachments.len()],
-                        resolves: &resolve_ids,
-                        depth_stencil: depth_id.as_ref(),
-                        inputs: &[],
-                        preserves: &[],
+                    let pipeline_layout_id = state
+                        .binder
+                        .pipeline_layout_id
+                        .ok_or(DrawError::MissingPipeline)?;
+                    let pipeline_layout = &pipeline_layout_guard[pipeline_layout_id];
+
+                    pipeline_layout
+                        .validate_push_constant_ranges(stages, offset, end_offset_bytes)
+                        .map_err(RenderCommandError::from)?;
+
+                    unsafe {
+                        raw.push_graphics_constants(
+                            &pipeline_layout.raw,
+                            conv::map_shader_stage_flags(stages),
+                            offset,
+                            data_slice,
+                        )
+                    }
+                }
+                RenderCommand::SetScissor(ref rect) => {
+                    use std::{convert::TryFrom, i16};
+                    let r = hal::pso::Rect {
+                        x: i16::try_from(rect.x).unwrap_or(0),
+                        y: i16::try_from(rect.y).unwrap_or(0),
+                        w: i16::try_from(rect.w).unwrap_or(i16::MAX),
+                        h: i16::try_from(rect.h).unwrap_or(i16::MAX),
                     };
-                    let all = entry
-                        .key()
-                        .all()
-                        .map(|(at, _)| at)
-                        .collect::<AttachmentDataVec<_>>();
+                    unsafe {
+                        raw.set_scissors(0, iter::once(r));
+                    }
+                }
+                RenderCommand::Draw {
+                    vertex_count,
+                    instance_count,
+                    first_vertex,
+                    first_instance,
+                } => {
+                    state.is_ready()?;
+                    let last_vertex = first_vertex + vertex_count;
+                    let vertex_limit = state.vertex.vertex_limit;
+                    if last_vertex > vertex_limit {
+                        Err(DrawError::VertexBeyondLimit {
+                            last_vertex,
+                            vertex_limit,
+                        })?
+                    }
+                    let last_instance = first_instance + instance_count;
+                    let instance_limit = state.vertex.instance_limit;
+                    if last_instance > instance_limit {
+                        Err(DrawError::InstanceBeyondLimit {
+                            last_instance,
+                            instance_limit,
+                        })?
+                    }
 
-                    let pass =
-                        unsafe { device.raw.create_render_pass(all, iter::once(subpass), &[]) }
-                            .unwrap();
-                    entry.insert(pass)
+                    unsafe {
+                        raw.draw(
+                            first_vertex..first_vertex + vertex_count,
+                            first_instance..first_instance + instance_count,
+                        );
+                    }
                 }
-            };
+                RenderCommand::DrawIndexed {
+                    index_count,
+                    instance_count,
+                    first_index,
+                    base_vertex,
+                    first_instance,
+                } => {
+                    state.is_ready()?;
 
-            let mut framebuffer_cache;
-            let fb_key = FramebufferKey {
-                colors: color_attachments
-                    .iter()
-                    .map(|at| id::Valid(at.attachment))
-                    .collect(),
-                resolves: color_attachments
-                    .iter()
-                    .filter_map(|at| at.resolve_target)
-                    .map(id::Valid)
-                    .collect(),
-                depth_stencil: depth_stencil_attachment.map(|at| id::Valid(at.attachment)),
-            };
-            let context = RenderPassContext {
-                attachments: AttachmentData {
-                    colors: fb_key
-                        .colors
-                        .iter()
-                        .map(|&at| view_guard[at].format)
-                        .collect(),
-                    resolves: fb_key
-                        .resolves
-                        .iter()
-                        .map(|&at| view_guard[at].format)
-                        .collect(),
-                    depth_stencil: fb_key.depth_stencil.map(|at| view_guard[at].format),
-                },
-                sample_count,
-            };
+                    //TODO: validate that base_vertex + max_index() is within the provided range
+                    let last_index = first_index + index_count;
+                    let index_limit = state.index.limit;
+                    if last_index > index_limit {
+                        Err(DrawError::IndexBeyondLimit {
+                            last_index,
+                            index_limit,
+                        })?
+                    }
+                    let last_instance = first_instance + instance_count;
+                    let instance_limit = state.vertex.instance_limit;
+                    if last_instance > instance_limit {
+                        Err(DrawError::InstanceBeyondLimit {
+                            last_instance,
+                            instance_limit,
+                        })?
+                    }
 
-            let framebuffer = match used_swap_chain.take() {
-                Some(sc_id) => {
-                    assert!(cmd_buf.used_swap_chain.is_none());
-                    // Always create a new framebuffer and delete it after presentation.
-                    let attachments = fb_key
-                        .all()
-                        .map(|&id| match view_guard[id].inner {
-                            TextureViewInner::Native { ref raw, .. } => raw,
-                            TextureViewInner::SwapChain { ref image, .. } => Borrow::borrow(image),
-                        })
-                        .collect::<AttachmentDataVec<_>>();
-                    let framebuffer = unsafe {
-                        device
-                            .raw
-                            .create_framebuffer(&render_pass, attachments, extent.unwrap())
-                            .or(Err(RenderPassError::OutOfMemory))?
-                    };
-                    cmd_buf.used_swap_chain = Some((sc_id, framebuffer));
-                    &mut cmd_buf.used_swap_chain.as_mut().unwrap().1
-                }
-                None => {
-                    // Cache framebuffers by the device.
-                    framebuffer_cache = device.framebuffers.lock();
-                    match framebuffer_cache.entry(fb_key) {
-                        Entry::Occupied(e) => e.into_mut(),
-                        Entry::Vacant(e) => {
-                            let fb = {
-                                let attachments = e
-                                    .key()
-                                    .all()
-                                    .map(|&id| match view_guard[id].inner {
-                                        TextureViewInner::Native { ref raw, .. } => raw,
-                                        TextureViewInner::SwapChain { ref image, .. } => {
-                                            Borrow::borrow(image)
-                                        }
-                                    })
-                                    .collect::<AttachmentDataVec<_>>();
-                                unsafe {
-                                    device
-                                        .raw
-                                        .create_framebuffer(
-                                            &render_pass,
-                                            attachments,
-                                            extent.unwrap(),
-                                        )
-                                        .or(Err(RenderPassError::OutOfMemory))?
-                                }
-                            };
-                            e.insert(fb)
-                        }
+                    unsafe {
+                        raw.draw_indexed(
+                            first_index..first_index + index_count,
+                            base_vertex,
+                            first_instance..first_instance + instance_count,
+                        );
                     }
                 }
-            };
+                RenderCommand::MultiDrawIndirect {
+                    buffer_id,
+                    offset,
+                    count,
+                    indexed,
+                } => {
+                    state.is_ready()?;
 
-            let rect = {
-                let ex = extent.unwrap();
-                hal::pso::Rect {
-                    x: 0,
-                    y: 0,
-                    w: ex.width as _,
-                    h: ex.height as _,
+                    let stride = match indexed {
+                        false => 16,
+                        true => 20,
+                    };
+
+                    if count.is_some() {
+                        check_device_features(features, wgt::Features::MULTI_DRAW_INDIRECT)?;
+                    }
+
+                    let buffer = trackers
+                        .buffers
+                        .use_extend(buffer_guard, buffer_id, (), BufferUse::INDIRECT)
+                        .unwrap();
+                    check_buffer_usage(buffer.usage, BufferUsage::INDIRECT)?;
+
+                    let actual_count = count.map_or(1, |c| c.get());
+
+                    check_indirect_range(offset, count, stride, actual_count, buffer.size)?;
+
+                    match indexed {
+                        false => unsafe {
+                            raw.draw_indirect(&buffer.raw, offset, actual_count, stride as u32);
+                        },
+                        true => unsafe {
+                            raw.draw_indexed_indirect(
+                                &buffer.raw,
+                                offset,
+                                actual_count,
+                                stride as u32,
+                            );
+                        },
+                    }
                 }
-            };
+                RenderCommand::MultiDrawIndirectCount {
+                    buffer_id,
+                    offset,
+                    count_buffer_id,
+                    count_buffer_offset,
+                    max_count,
+                    indexed,
+                } => {
+                    state.is_ready()?;
 
-            let clear_values = color_attachments
-                .iter()
-                .zip(&rp_key.colors)
-                .flat_map(|(at, (rat, _layout))| {
-                    match at.channel.load_op {
-                        LoadOp::Load => None,
-                        LoadOp::Clear => {
-                            use hal::format::ChannelType;
-                            //TODO: validate sign/unsign and normalized ranges of the color values
-                            let value = match rat.format.unwrap().base_format().1 {
-                                ChannelType::Unorm
-                                | ChannelType::Snorm
-                                | ChannelType::Ufloat
-                                | ChannelType::Sfloat
-                                | ChannelType::Uscaled
-                                | ChannelType::Sscaled
-                                | ChannelType::Srgb => hal::command::ClearColor {
-                                    float32: conv::map_color_f32(&at.channel.clear_value),
-                                },
-                                ChannelType::Sint => hal::command::ClearColor {
-                                    sint32: conv::map_color_i32(&at.channel.clear_value),
-                                },
-                                ChannelType::Uint => hal::command::ClearColor {
-                                    uint32: conv::map_color_u32(&at.channel.clear_value),
-                                },
-                            };
-                            Some(hal::command::ClearValue { color: value })
-                        }
+                    let stride = match indexed {
+                        false => 16,
+                        true => 20,
+                    };
+
+                    check_device_features(features, wgt::Features::MULTI_DRAW_INDIRECT_COUNT)?;
+
+                    let buffer = trackers
+                        .buffers
+                        .use_extend(buffer_guard, buffer_id, (), BufferUse::INDIRECT)
+                        .unwrap();
+                    check_buffer_usage(buffer.usage, BufferUsage::INDIRECT)?;
+                    let count_buffer = trackers
+                        .buffers
+                        .use_extend(buffer_guard, count_buffer_id, (), BufferUse::INDIRECT)
+                        .unwrap();
+                    check_buffer_usage(count_buffer.usage, BufferUsage::INDIRECT)?;
+
+                    check_indirect_range(offset, None, stride, max_count, buffer.size)?;
+                    check_indirect_count_range(count_buffer_offset, count_buffer.size)?;
+
+                    match indexed {
+                        false => unsafe {
+                            raw.draw_indirect_count(
+                                &buffer.raw,
+                                offset,
+                                &count_buffer.raw,
+                                count_buffer_offset,
+                                max_count,
+                                stride as u32,
+                            );
+                        },
+                        true => unsafe {
+                            raw.draw_indexed_indirect_count(
+                                &buffer.raw,
+                                offset,
+                                &count_buffer.raw,
+                                count_buffer_offset,
+                                max_count,
+                                stride as u32,
+                            );
+                        },
+                    }
+                }
+                RenderCommand::PushDebugGroup { color, len } => {
+                    state.debug_scope_depth += 1;
+                    let label = str::from_utf8(&base.string_data[..len]).unwrap();
+                    unsafe {
+                        raw.begin_debug_marker(label, color);
                     }
-                })
-                .chain(depth_stencil_attachment.and_then(|at| {
-                    match (at.depth.load_op, at.stencil.load_op) {
-                        (LoadOp::Load, LoadOp::Load) => None,
-                        (LoadOp::Clear, _) | (_, LoadOp::Clear) => {
-                            let value = hal::command::ClearDepthStencil {
-                                depth: at.depth.clear_value,
-                                stencil: at.stencil.clear_value,
-                            };
-                            Some(hal::command::ClearValue {
-                                depth_stencil: value,
-                            })
-                        }
+                    base.string_data = &base.string_data[len..];
+                }
+                RenderCommand::PopDebugGroup => {
+                    if state.debug_scope_depth == 0 {
+                        return Err(RenderPassError::InvalidPopDebugGroup);
                     }
-                }))
-                .collect::<ArrayVec<[_; MAX_COLOR_TARGETS + 1]>>();
+                    state.debug_scope_depth -= 1;
+                    unsafe {
+                        raw.end_debug_marker();
+                    }
+                }
+                RenderCommand::InsertDebugMarker { color, len } => {
+                    let label = str::from_utf8(&base.string_data[..len]).unwrap();
+                    unsafe {
+                        raw.insert_debug_marker(label, color);
+                    }
+                }
+                RenderCommand::ExecuteBundle(bundle_id) => {
+                    let bundle = trackers
+                        .bundles
+                        .use_extend(bundle_guard, bundle_id, (), ())
+                        .unwrap();
 
-            unsafe {
-                raw.begin_render_pass(
-                    render_pass,
-                    framebuffer,
-                    rect,
-                    clear_values,
-                    hal::command::SubpassContents::Inline,
-                );
-                raw.set_scissors(0, iter::once(&rect));
-                raw.set_viewports(
-                    0,
-                    iter::once(hal::pso::Viewport {
-                        rect,
-                        depth: 0.0..1.0,
-                    }),
-                );
+                    if !context.compatible(&bundle.context) {
+                        return Err(RenderPassError::IncompatibleRenderBundle);
+                    }
+
+                    unsafe {
+                        bundle.execute(
+                            raw,
+                            pipeline_layout_guard,
+                            bind_group_guard,
+                            pipeline_guard,
+                            buffer_guard,
+                        )
+                    }
+
+                    trackers.merge_extend(&bundle.used);
+                    state.reset_bundle();
+                }
             }
+        }
 
-            context
-        };
+        Ok(())
+    }
 
-        let mut state = State {
-            binder: Binder::new(cmd_buf.limits.max_bind_groups),
-            blend_color: OptionalState::Unused,
-            stencil_reference: OptionalState::Unused,
-            pipeline: OptionalState::Required,
-            index: IndexState::default(),
-            vertex: VertexState::default(),
-            debug_scope_depth: 0,
+    /// Encode a sub-pass into a secondary command buffer allocated from a checked out
+    /// secondary pool, so that the sub-passes can be recorded in parallel.
+    ///
+    /// The command buffer is returned to its pool if the sub-pass is invalid.
+    fn encode_secondary(
+        &self,
+        allocator: &CommandAllocator<B>,
+        device: &B::Device,
+        render_pass: &B::RenderPass,
+        framebuffer: &B::Framebuffer,
+        base: BasePassRef<RenderCommand>,
+    ) -> Result<(usize, B::CommandBuffer, TrackerSet), RenderPassError> {
+        let (pool_index, mut raw) = allocator
+            .allocate_secondary(device)
+            .or(Err(RenderPassError::OutOfMemory))?;
+        let mut trackers = TrackerSet::new(B::VARIANT);
+        unsafe {
+            raw.begin(
+                hal::command::CommandBufferFlags::RENDER_PASS_CONTINUE,
+                hal::command::CommandBufferInheritanceInfo {
+                    subpass: Some(hal::pass::Subpass {
+                        index: 0,
+                        main_pass: render_pass,
+                    }),
+                    framebuffer: Some(framebuffer),
+                    ..Default::default()
+                },
+            );
+        }
+        let result = match self.encode(&mut raw, base, &mut trackers) {
+            Ok(()) => {
+                unsafe {
+                    raw.finish();
+                }
+                Ok((pool_index, raw, trackers))
+            }
+            Err(err) => {
+                allocator.discard_secondary(pool_index, raw);
+                Err(err)
+            }
         };
-        let mut temp_offsets = Vec::new();
-
-        for command in base.commands {
-            match *command {
-                RenderCommand::SetBindGroup {
-                    index,
-                    num_dynamic_offsets,
-                    bind_group_id,
-                } => {
-                    let max_bind_groups = device.limits.max_bind_groups;
-                    if (index as u32) >= max_bind_groups {
-                        return Err(RenderCommandError::BindGroupIndexOutOfRange {
-                            index,
-                            max: max_bind_groups,
-                        }
-                        .into());
-                    }
+        allocator.release_secondary_pool(pool_index);
+        result
+    }
+}
 
-                    temp_offsets.clear();
-                    temp_offsets
-                        .extend_from_slice(&base.dynamic_offsets[..num_dynamic_offsets as usize]);
-                    base.dynamic_offsets = &base.dynamic_offsets[num_dynamic_offsets as usize..];
+// Common routines between render/compute
 
-                    let bind_group = trackers
-                        .bind_groups
-                        .use_extend(&*bind_group_guard, bind_group_id, (), ())
-                        .unwrap();
-                    bind_group
-                        .validate_dynamic_bindings(&temp_offsets)
-                        .map_err(RenderPassError::from)?;
+impl<G: GlobalIdentityHandlerFactory> Global<G> {
+    pub fn command_encoder_run_render_pass<B: GfxBackend>(
+        &self,
+        encoder_id: id::CommandEncoderId,
+        pass: &RenderPass,
+    ) -> Result<(), RenderPassError> {
+        self.command_encoder_run_render_pass_impl::<B>(
+            encoder_id,
+            &[pass.base.as_ref()],
+            &pass.color_targets,
+            pass.depth_stencil_target.as_ref(),
+            &run_sub_passes_inline,
+        )
+    }
 
-                    trackers.merge_extend(&bind_group.used);
+    /// Runs several sub-passes, created with `RenderPass::sub_pass`, as one render pass.
+    ///
+    /// Each sub-pass starts with a fresh state. If the backend supports secondary
+    /// command buffers, the sub-passes are validated and encoded by jobs given
+    /// to `executor`, which can run them in parallel on the threads of the caller,
+    /// and the resulting trackers are merged once.
+    /// Otherwise, they are encoded inline, one after another.
+    pub fn command_encoder_run_parallel_render_pass<B: GfxBackend>(
+        &self,
+        encoder_id: id::CommandEncoderId,
+        sub_passes: &[RenderPass],
+        executor: &SubPassExecutor,
+    ) -> Result<(), RenderPassError> {
+        let first = sub_passes.first().ok_or(RenderPassError::NoSubPasses)?;
+        for (index, sub_pass) in sub_passes.iter().enumerate().skip(1) {
+            if sub_pass.parent_id != first.parent_id
+                || sub_pass.color_targets != first.color_targets
+                || sub_pass.depth_stencil_target != first.depth_stencil_target
+            {
+                return Err(RenderPassError::SubPassMismatch(index));
+            }
+        }
 
-                    if let Some((pipeline_layout_id, follow_ups)) = state.binder.provide_entry(
-                        index as usize,
-                        id::Valid(bind_group_id),
-                        bind_group,
-                        &temp_offsets,
-                    ) {
-                        let bind_groups = iter::once(bind_group.raw.raw())
-                            .chain(
-                                follow_ups
-                                    .clone()
-                                    .map(|(bg_id, _)| bind_group_guard[bg_id].raw.raw()),
-                            )
-                            .collect::<ArrayVec<[_; MAX_BIND_GROUPS]>>();
-                        temp_offsets.extend(follow_ups.flat_map(|(_, offsets)| offsets));
-                        unsafe {
-                            raw.bind_graphics_descriptor_sets(
-                                &pipeline_layout_guard[pipeline_layout_id].raw,
-                                index as usize,
-                                bind_groups,
-                                &temp_offsets,
-                            );
-                        }
-                    };
-                }
-                RenderCommand::SetPipeline(pipeline_id) => {
-                    state.pipeline = OptionalState::Set;
-                    let pipeline = trackers
-                        .render_pipes
-                        .use_extend(&*pipeline_guard, pipeline_id, (), ())
-                        .unwrap();
+        let bases = sub_passes
+            .iter()
+            .map(|sub_pass| sub_pass.base.as_ref())
+            .collect::<Vec<_>>();
+        self.command_encoder_run_render_pass_impl::<B>(
+            encoder_id,
+            &bases,
+            &first.color_targets,
+            first.depth_stencil_target.as_ref(),
+            executor,
+        )
+    }
 
-                    if !context.compatible(&pipeline.pass_context) {
-                        return Err(RenderCommandError::IncompatiblePipeline.into());
-                    }
-                    if pipeline.flags.contains(PipelineFlags::WRITES_DEPTH_STENCIL)
-                        && is_ds_read_only
-                    {
-                        return Err(RenderCommandError::IncompatibleReadOnlyDepthStencil.into());
-                    }
+    #[doc(hidden)]
+    pub fn command_encoder_run_render_pass_impl<B: GfxBackend>(
+        &self,
+        encoder_id: id::CommandEncoderId,
+        bases: &[BasePassRef<RenderCommand>],
+        color_attachments: &[ColorAttachmentDescriptor],
+        depth_stencil_attachment: Option<&DepthStencilAttachmentDescriptor>,
+        executor: &SubPassExecutor,
+    ) -> Result<(), RenderPassError> {
+        span!(_guard, INFO, "CommandEncoder::run_render_pass");
 
-                    state
-                        .blend_color
-                        .require(pipeline.flags.contains(PipelineFlags::BLEND_COLOR));
-                    state
-                        .stencil_reference
-                        .require(pipeline.flags.contains(PipelineFlags::STENCIL_REFERENCE));
+        let hub = B::hub(self);
+        let mut token = Token::root();
 
-                    unsafe {
-                        raw.bind_graphics_pipeline(&pipeline.raw);
-                    }
+        let (device_guard, mut token) = hub.devices.read(&mut token);
+        let (mut cmb_guard, mut token) = hub.command_buffers.write(&mut token);
 
-                    // Rebind resource
-                    if state.binder.pipeline_layout_id != Some(pipeline.layout_id.value) {
-                        let pipeline_layout = &pipeline_layout_guard[pipeline.layout_id.value];
+        let mut trackers = TrackerSet::new(B::VARIANT);
+        let cmd_buf = CommandBuffer::get_encoder(&mut *cmb_guard, encoder_id)?;
+        let device = &device_guard[cmd_buf.device_id.value];
+        let mut raw = device.cmd_allocator.extend(cmd_buf);
 
-                        state.binder.change_pipeline_layout(
-                            &*pipeline_layout_guard,
-                            pipeline.layout_id.value,
-                        );
+        #[cfg(feature = "trace")]
+        match cmd_buf.commands {
+            Some(ref mut list) => {
+                let target_colors = color_attachments.iter().cloned().collect();
+                let target_depth_stencil = depth_stencil_attachment.cloned();
+                list.push(match *bases {
+                    [base] => crate::device::trace::Command::RunRenderPass {
+                        base: BasePass::from_ref(base),
+                        target_colors,
+                        target_depth_stencil,
+                    },
+                    _ => crate::device::trace::Command::RunParallelRenderPass {
+                        bases: bases.iter().map(|&base| BasePass::from_ref(base)).collect(),
+                        target_colors,
+                        target_depth_stencil,
+                    },
+                });
+            }
+            None => {}
+        }
 
-                        let mut is_compatible = true;
+        unsafe {
+            raw.begin_primary(cmd_buf.begin_flags());
+        }
 
-                        for (index, (entry, &bgl_id)) in state
-                            .binder
-                            .entries
-                            .iter_mut()
-                            .zip(&pipeline_layout.bind_group_layout_ids)
-                            .enumerate()
-                        {
-                            match entry.expect_layout(bgl_id) {
-                                LayoutChange::Match(bg_id, offsets) if is_compatible => {
-                                    let desc_set = bind_group_guard[bg_id].raw.raw();
-                                    unsafe {
-                                        raw.bind_graphics_descriptor_sets(
-                                            &pipeline_layout.raw,
-                                            index,
-                                            iter::once(desc_set),
-                                            offsets.iter().cloned(),
-                                        );
-                                    }
-                                }
-                                LayoutChange::Match(..) | LayoutChange::Unchanged => {}
-                                LayoutChange::Mismatch => {
-                                    is_compatible = false;
-                                }
-                            }
-                        }
+        // Sub-passes are encoded inline, one after another, if the backend
+        // can't execute secondary command buffers.
+        let use_secondaries = bases.len() > 1 && device.private_features.secondary_command_buffers;
 
-                        // Clear push constant ranges
-                        let non_overlapping = super::bind::compute_nonoverlapping_ranges(
-                            &pipeline_layout.push_constant_ranges,
-                        );
-                        for range in non_overlapping {
-                            let offset = range.range.start;
-                            let size_bytes = range.range.end - offset;
-                            super::push_constant_clear(
-                                offset,
-                                size_bytes,
-                                |clear_offset, clear_data| unsafe {
-                                    raw.push_graphics_constants(
-                                        &pipeline_layout.raw,
-                                        conv::map_shader_stage_flags(range.stages),
-                                        clear_offset,
-                                        clear_data,
-                                    );
-                                },
-                            );
-                        }
-                    }
+        let (bundle_guard, mut token) = hub.render_bundles.read(&mut token);
+        let (pipeline_layout_guard, mut token) = hub.pipeline_layouts.read(&mut token);
+        let (bind_group_guard, mut token) = hub.bind_groups.read(&mut token);
+        let (pipeline_guard, mut token) = hub.render_pipelines.read(&mut token);
+        let (buffer_guard, mut token) = hub.buffers.read(&mut token);
+        let (texture_guard, mut token) = hub.textures.read(&mut token);
+        let (view_guard, _) = hub.texture_views.read(&mut token);
 
-                    // Rebind index buffer if the index format has changed with the pipeline switch
-                    if state.index.format != pipeline.index_format {
-                        state.index.format = pipeline.index_format;
-                        state.index.update_limit();
+        // We default to false intentionally, even if depth-stencil isn't used at all.
+        // This allows us to use the primary raw pipeline in `RenderPipeline`,
+        // instead of the special read-only one, which would be `None`.
+        let mut is_ds_read_only = false;
 
-                        if let Some((buffer_id, ref range)) = state.index.bound_buffer_view {
-                            let buffer = trackers
-                                .buffers
-                                .use_extend(&*buffer_guard, buffer_id, (), BufferUse::INDEX)
-                                .unwrap();
+        struct OutputAttachment<'a> {
+            texture_id: &'a Stored<id::TextureId>,
+            selector: &'a TextureSelector,
+            previous_use: Option<TextureUse>,
+            new_use: TextureUse,
+        }
+        let mut output_attachments = AttachmentDataVec::<OutputAttachment>::new();
 
-                            let view = hal::buffer::IndexBufferView {
-                                buffer: &buffer.raw,
-                                range: hal::buffer::SubRange {
-                                    offset: range.start,
-                                    size: Some(range.end - range.start),
-                                },
-                                index_type: conv::map_index_format(state.index.format),
-                            };
+        let secondaries = {
+            use hal::device::Device as _;
 
-                            unsafe {
-                                raw.bind_index_buffer(view);
-                            }
-                        }
-                    }
-                    // Update vertex buffer limits
-                    for (vbs, &(stride, rate)) in
-                        state.vertex.inputs.iter_mut().zip(&pipeline.vertex_strides)
-                    {
-                        vbs.stride = stride;
-                        vbs.rate = rate;
-                    }
-                    let vertex_strides_len = pipeline.vertex_strides.len();
-                    for vbs in state.vertex.inputs.iter_mut().skip(vertex_strides_len) {
-                        vbs.stride = 0;
-                        vbs.rate = InputStepMode::Vertex;
+            let sample_count_limit = device.hal_limits.framebuffer_color_sample_counts;
+            let base_trackers = &cmd_buf.trackers;
+
+            let mut extent = None;
+            let mut sample_count = 0;
+            let mut depth_stencil_aspects = hal::format::Aspects::empty();
+            let mut used_swap_chain = None::<Stored<id::SwapChainId>>;
+
+            let mut add_view = |view: &TextureView<B>| {
+                if let Some(ex) = extent {
+                    if ex != view.extent {
+                        return Err(RenderPassError::ExtentStateMismatch {
+                            state_extent: ex,
+                            view_extent: view.extent,
+                        });
                     }
-                    state.vertex.update_limits();
+                } else {
+                    extent = Some(view.extent);
                 }
-                RenderCommand::SetIndexBuffer {
-                    buffer_id,
-                    offset,
-                    size,
-                } => {
-                    let buffer = trackers
-                        .buffers
-                        .use_extend(&*buffer_guard, buffer_id, (), BufferUse::INDEX)
-                        .unwrap();
-                    check_buffer_usage(buffer.usage, BufferUsage::INDEX)?;
+                if sample_count == 0 {
+                    sample_count = view.samples;
+                } else if sample_count != view.samples {
+                    return Err(RenderPassError::SampleCountMismatch {
+                        actual: view.samples,
+                        expected: sample_count,
+                    });
+                }
+                Ok(())
+            };
 
-                    let end = match size {
-                        Some(s) => offset + s.get(),
-                        None => buffer.size,
-                    };
-                    state.index.bound_buffer_view = Some((buffer_id, offset..end));
-                    state.index.update_limit();
+            tracing::trace!(
+                "Encoding render pass begin in command buffer {:?}",
+                encoder_id
+            );
+            let rp_key = {
+                let depth_stencil = match depth_stencil_attachment {
+                    Some(at) => {
+                        let view = trackers
+                            .views
+                            .use_extend(&*view_guard, at.attachment, (), ())
+                            .map_err(|_| RenderPassError::InvalidAttachment(at.attachment))?;
+                        add_view(view)?;
+                        depth_stencil_aspects = view.aspects;
 
-                    let view = hal::buffer::IndexBufferView {
-                        buffer: &buffer.raw,
-                        range: hal::buffer::SubRange {
-                            offset,
-                            size: Some(end - offset),
-                        },
-                        index_type: conv::map_index_format(state.index.format),
-                    };
+                        let source_id = match view.inner {
+                            TextureViewInner::Native { ref source_id, .. } => source_id,
+                            TextureViewInner::SwapChain { .. } => {
+                                return Err(RenderPassError::SwapChainImageAsDepthStencil)
+                            }
+                        };
 
-                    unsafe {
-                        raw.bind_index_buffer(view);
-                    }
-                }
-                RenderCommand::SetVertexBuffer {
-                    slot,
-                    buffer_id,
-                    offset,
-                    size,
-                } => {
-                    let buffer = trackers
-                        .buffers
-                        .use_extend(&*buffer_guard, buffer_id, (), BufferUse::VERTEX)
-                        .unwrap();
-                    check_buffer_usage(buffer.usage, BufferUsage::VERTEX)?;
-                    let empty_slots = (1 + slot as usize).saturating_sub(state.vertex.inputs.len());
-                    state
-                        .vertex
-                        .inputs
-                        .extend(iter::repeat(VertexBufferState::EMPTY).take(empty_slots));
-                    state.vertex.inputs[slot as usize].total_size = match size {
-                        Some(s) => s.get(),
-                        None => buffer.size - offset,
-                    };
+                        // Using render pass for transition.
+                        let previous_use = base_trackers
+                            .textures
+                            .query(source_id.value, view.selector.clone());
+                        let new_use = if at.is_read_only(view.aspects)? {
+                            is_ds_read_only = true;
+                            TextureUse::ATTACHMENT_READ
+                        } else {
+                            TextureUse::ATTACHMENT_WRITE
+                        };
+                        output_attachments.push(OutputAttachment {
+                            texture_id: source_id,
+                            selector: &view.selector,
+                            previous_use,
+                            new_use,
+                        });
 
-                    let range = hal::buffer::SubRange {
-                        offset,
-                        size: size.map(|s| s.get()),
-                    };
-                    unsafe {
-                        raw.bind_vertex_buffers(slot, iter::once((&buffer.raw, range)));
-                    }
-                    state.vertex.update_limits();
-                }
-                RenderCommand::SetBlendColor(ref color) => {
-                    state.blend_color = OptionalState::Set;
-                    unsafe {
-                        raw.set_blend_constants(conv::map_color_f32(color));
-                    }
-                }
-                RenderCommand::SetStencilReference(value) => {
-                    state.stencil_reference = OptionalState::Set;
-                    unsafe {
-                        raw.set_stencil_reference(hal::pso::Face::all(), value);
-                    }
-                }
-                RenderCommand::SetViewport {
-                    ref rect,
-                    depth_min,
-                    depth_max,
-                } => {
-                    use std::{convert::TryFrom, i16};
-                    let r = hal::pso::Rect {
-                        x: i16::try_from(rect.x.round() as i64).unwrap_or(0),
-                        y: i16::try_from(rect.y.round() as i64).unwrap_or(0),
-                        w: i16::try_from(rect.w.round() as i64).unwrap_or(i16::MAX),
-                        h: i16::try_from(rect.h.round() as i64).unwrap_or(i16::MAX),
-                    };
-                    unsafe {
-                        raw.set_viewports(
-                            0,
-                            iter::once(hal::pso::Viewport {
-                                rect: r,
-                                depth: depth_min..depth_max,
-                            }),
-                        );
+                        let new_layout = conv::map_texture_state(new_use, view.aspects).1;
+                        let old_layout = match previous_use {
+                            Some(usage) => conv::map_texture_state(usage, view.aspects).1,
+                            None => new_layout,
+                        };
+
+                        let ds_at = hal::pass::Attachment {
+                            format: Some(conv::map_texture_format(
+                                view.format,
+                                device.private_features,
+                            )),
+                            samples: view.samples,
+                            ops: conv::map_load_store_ops(&at.depth),
+                            stencil_ops: conv::map_load_store_ops(&at.stencil),
+                            layouts: old_layout..new_layout,
+                        };
+                        Some((ds_at, new_layout))
                     }
-                }
-                RenderCommand::SetPushConstant {
-                    stages,
-                    offset,
-                    size_bytes,
-                    values_offset,
-                } => {
-                    let values_offset =
-                        values_offset.ok_or(RenderPassError::InvalidValuesOffset)?;
+                    None => None,
+                };
 
-                    let end_offset_bytes = offset + size_bytes;
-                    let values_end_offset = (values_offset + size_bytes / 4) as usize;
-                    let data_slice =
-                        &base.push_constant_data[(values_offset as usize)..values_end_offset];
+                let mut colors = ArrayVec::new();
+                let mut resolves = ArrayVec::new();
 
-                    let pipeline_layout_id = state
-                        .binder
-                        .pipeline_layout_id
-                        .ok_or(DrawError::MissingPipeline)?;
-                    let pipeline_layout = &pipeline_layout_guard[pipeline_layout_id];
+                for at in color_attachments {
+                    let view = trackers
+                        .views
+                        .use_extend(&*view_guard, at.attachment, (), ())
+                        .map_err(|_| RenderPassError::InvalidAttachment(at.attachment))?;
+                    add_view(view)?;
+
+                    let layouts = match view.inner {
+                        TextureViewInner::Native { ref source_id, .. } => {
+                            let previous_use = base_trackers
+                                .textures
+                                .query(source_id.value, view.selector.clone());
+                            let new_use = TextureUse::ATTACHMENT_WRITE;
+                            output_attachments.push(OutputAttachment {
+                                texture_id: source_id,
+                                selector: &view.selector,
+                                previous_use,
+                                new_use,
+                            });
+
+                            let new_layout =
+                                conv::map_texture_state(new_use, hal::format::Aspects::COLOR).1;
+                            let old_layout = match previous_use {
+                                Some(usage) => {
+                                    conv::map_texture_state(usage, hal::format::Aspects::COLOR).1
+                                }
+                                None => new_layout,
+                            };
+                            old_layout..new_layout
+                        }
+                        TextureViewInner::SwapChain { ref source_id, .. } => {
+                            if let Some((ref sc_id, _)) = cmd_buf.used_swap_chain {
+                                if source_id.value != sc_id.value {
+                                    return Err(RenderPassError::SwapChainMismatch);
+                                }
+                            } else {
+                                assert!(used_swap_chain.is_none());
+                                used_swap_chain = Some(source_id.clone());
+                            }
 
-                    pipeline_layout
-                        .validate_push_constant_ranges(stages, offset, end_offset_bytes)
-                        .map_err(RenderCommandError::from)?;
+                            let end = hal::image::Layout::Present;
+                            let start = match at.channel.load_op {
+                                LoadOp::Clear => hal::image::Layout::Undefined,
+                                LoadOp::Load => end,
+                            };
+                            start..end
+                        }
+                    };
 
-                    unsafe {
-                        raw.push_graphics_constants(
-                            &pipeline_layout.raw,
-                            conv::map_shader_stage_flags(stages),
-                            offset,
-                            data_slice,
-                        )
-                    }
-                }
-                RenderCommand::SetScissor(ref rect) => {
-                    use std::{convert::TryFrom, i16};
-                    let r = hal::pso::Rect {
-                        x: i16::try_from(rect.x).unwrap_or(0),
-                        y: i16::try_from(rect.y).unwrap_or(0),
-                        w: i16::try_from(rect.w).unwrap_or(i16::MAX),
-                        h: i16::try_from(rect.h).unwrap_or(i16::MAX),
+                    let color_at = hal::pass::Attachment {
+                        format: Some(conv::map_texture_format(
+                            view.format,
+                            device.private_features,
+                        )),
+                        samples: view.samples,
+                        ops: conv::map_load_store_ops(&at.channel),
+                        stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
+                        layouts,
                     };
-                    unsafe {
-                        raw.set_scissors(0, iter::once(r));
-                    }
+                    colors.push((color_at, hal::image::Layout::ColorAttachmentOptimal));
                 }
-                RenderCommand::Draw {
-                    vertex_count,
-                    instance_count,
-                    first_vertex,
-                    first_instance,
-                } => {
-                    state.is_ready()?;
-                    let last_vertex = first_vertex + vertex_count;
-                    let vertex_limit = state.vertex.vertex_limit;
-                    if last_vertex > vertex_limit {
-                        Err(DrawError::VertexBeyondLimit {
-                            last_vertex,
-                            vertex_limit,
-                        })?
-                    }
-                    let last_instance = first_instance + instance_count;
-                    let instance_limit = state.vertex.instance_limit;
-                    if last_instance > instance_limit {
-                        Err(DrawError::InstanceBeyondLimit {
-                            last_instance,
-                            instance_limit,
-                        })?
-                    }
 
-                    unsafe {
-                        raw.draw(
-                            first_vertex..first_vertex + vertex_count,
-                            first_instance..first_instance + instance_count,
-                        );
+                for resolve_target in color_attachments.iter().flat_map(|at| at.resolve_target) {
+                    let view = trackers
+                        .views
+                        .use_extend(&*view_guard, resolve_target, (), ())
+                        .map_err(|_| RenderPassError::InvalidAttachment(resolve_target))?;
+                    if extent != Some(view.extent) {
+                        return Err(RenderPassError::ExtentStateMismatch {
+                            state_extent: extent.unwrap_or_default(),
+                            view_extent: view.extent,
+                        });
                     }
-                }
-                RenderCommand::DrawIndexed {
-                    index_count,
-                    instance_count,
-                    first_index,
-                    base_vertex,
-                    first_instance,
-                } => {
-                    state.is_ready()?;
-
-                    //TODO: validate that base_vertex + max_index() is within the provided range
-                    let last_index = first_index + index_count;
-                    let index_limit = state.index.limit;
-                    if last_index > index_limit {
-                        Err(DrawError::IndexBeyondLimit {
-                            last_index,
-                            index_limit,
-                        })?
+                    if view.samples != 1 {
+                        return Err(RenderPassError::InvalidResolveTargetSampleCount);
                     }
-                    let last_instance = first_instance + instance_count;
-                    let instance_limit = state.vertex.instance_limit;
-                    if last_instance > instance_limit {
-                        Err(DrawError::InstanceBeyondLimit {
-                            last_instance,
-                            instance_limit,
-                        })?
+                    if sample_count == 1 {
+                        return Err(RenderPassError::InvalidResolveSourceSampleCount);
                     }
 
-                    unsafe {
-                        raw.draw_indexed(
-                            first_index..first_index + index_count,
-                            base_vertex,
-                            first_instance..first_instance + instance_count,
-                        );
-                    }
-                }
-                RenderCommand::MultiDrawIndirect {
-                    buffer_id,
-                    offset,
-                    count,
-                    indexed,
-                } => {
-                    state.is_ready()?;
+                    let layouts = match view.inner {
+                        TextureViewInner::Native { ref source_id, .. } => {
+                            let previous_use = base_trackers
+                                .textures
+                                .query(source_id.value, view.selector.clone());
+                            let new_use = TextureUse::ATTACHMENT_WRITE;
+                            output_attachments.push(OutputAttachment {
+                                texture_id: source_id,
+                                selector: &view.selector,
+                                previous_use,
+                                new_use,
+                            });
 
-                    let stride = match indexed {
-                        false => 16,
-                        true => 20,
+                            let new_layout =
+                                conv::map_texture_state(new_use, hal::format::Aspects::COLOR).1;
+                            let old_layout = match previous_use {
+                                Some(usage) => {
+                                    conv::map_texture_state(usage, hal::format::Aspects::COLOR).1
+                                }
+                                None => new_layout,
+                            };
+                            old_layout..new_layout
+                        }
+                        TextureViewInner::SwapChain { ref source_id, .. } => {
+                            if let Some((ref sc_id, _)) = cmd_buf.used_swap_chain {
+                                if source_id.value != sc_id.value {
+                                    return Err(RenderPassError::SwapChainMismatch);
+                                }
+                            } else {
+                                assert!(used_swap_chain.is_none());
+                                used_swap_chain = Some(source_id.clone());
+                            }
+                            hal::image::Layout::Undefined..hal::image::Layout::Present
+                        }
                     };
 
-                    if count.is_some() {
-                        check_device_features(device.features, wgt::Features::MULTI_DRAW_INDIRECT)?;
-                    }
+                    let resolve_at = hal::pass::Attachment {
+                        format: Some(conv::map_texture_format(
+                            view.format,
+                            device.private_features,
+                        )),
+                        samples: view.samples,
+                        ops: hal::pass::AttachmentOps::new(
+                            hal::pass::AttachmentLoadOp::DontCare,
+                            hal::pass::AttachmentStoreOp::Store,
+                        ),
+                        stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
+                        layouts,
+                    };
+                    resolves.push((resolve_at, hal::image::Layout::ColorAttachmentOptimal));
+                }
 
-                    let buffer = trackers
-                        .buffers
-                        .use_extend(&*buffer_guard, buffer_id, (), BufferUse::INDIRECT)
-                        .unwrap();
-                    check_buffer_usage(buffer.usage, BufferUsage::INDIRECT)?;
+                RenderPassKey {
+                    colors,
+                    resolves,
+                    depth_stencil,
+                }
+            };
 
-                    let actual_count = count.map_or(1, |c| c.get());
+            if sample_count & sample_count_limit == 0 {
+                return Err(RenderPassError::InvalidSampleCount(sample_count));
+            }
 
-                    let begin_offset = offset;
-                    let end_offset = offset + stride * actual_count as u64;
-                    if end_offset > buffer.size {
-                        return Err(RenderPassError::IndirectBufferOverrun {
-                            offset,
-                            count,
-                            begin_offset,
-                            end_offset,
-                            buffer_size: buffer.size,
-                        });
-                    }
+            let mut render_pass_cache = device.render_passes.lock();
+            let render_pass = match render_pass_cache.entry(rp_key.clone()) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(entry) => {
+                    let color_ids: [hal::pass::AttachmentRef; MAX_COLOR_TARGETS] = [
+                        (0, hal::image::Layout::ColorAttachmentOptimal),
+                        (1, hal::image::Layout::ColorAttachmentOptimal),
+                        (2, hal::image::Layout::ColorAttachmentOptimal),
+                        (3, hal::image::Layout::ColorAttachmentOptimal),
+                    ];
 
-                    match indexed {
-                        false => unsafe {
-                            raw.draw_indirect(&buffer.raw, offset, actual_count, stride as u32);
-                        },
-                        true => unsafe {
-                            raw.draw_indexed_indirect(
-                                &buffer.raw,
-                                offset,
-                                actual_count,
-                                stride as u32,
-                            );
-                        },
-                    }
-                }
-                RenderCommand::MultiDrawIndirectCount {
-                    buffer_id,
-                    offset,
-                    count_buffer_id,
-                    count_buffer_offset,
-                    max_count,
-                    indexed,
-                } => {
-                    state.is_ready()?;
+                    let mut resolve_ids = ArrayVec::<[_; MAX_COLOR_TARGETS]>::new();
+                    let mut attachment_index = color_attachments.len();
+                    if color_attachments
+                        .iter()
+                        .any(|at| at.resolve_target.is_some())
+                    {
+                        for ((i, at), &(_, layout)) in color_attachments
+                            .iter()
+                            .enumerate()
+                            .zip(entry.key().resolves.iter())
+                        {
+                            let real_attachment_index = match at.resolve_target {
+                                Some(_) => attachment_index + i,
+                                None => hal::pass::ATTACHMENT_UNUSED,
+                            };
+                            resolve_ids.push((real_attachment_index, layout));
+                        }
+                        attachment_index += color_attachments.len();
+                    }
 
-                    let stride = match indexed {
-                        false => 16,
-                        true => 20,
-                    };
+                    let depth_id = depth_stencil_attachment.map(|_| {
+                        let usage = if is_ds_read_only {
+                            TextureUse::ATTACHMENT_READ
+                        } else {
+                            TextureUse::ATTACHMENT_WRITE
+                        };
+                        (
+                            attachment_index,
+                            conv::map_texture_state(usage, depth_stencil_aspects).1,
+                        )
+                    });
 
-                    check_device_features(
-                        device.features,
-                        wgt::Features::MULTI_DRAW_INDIRECT_COUNT,
-                    )?;
+                    let subpass = hal::pass::SubpassDesc {
+                        colors: &color_ids[..color_attachments.len()],
+                        resolves: &resolve_ids,
+                        depth_stencil: depth_id.as_ref(),
+                        inputs: &[],
+                        preserves: &[],
+                    };
+                    let all = entry
+                        .key()
+                        .all()
+                        .map(|(at, _)| at)
+                        .collect::<AttachmentDataVec<_>>();
 
-                    let buffer = trackers
-                        .buffers
-                        .use_extend(&*buffer_guard, buffer_id, (), BufferUse::INDIRECT)
-                        .unwrap();
-                    check_buffer_usage(buffer.usage, BufferUsage::INDIRECT)?;
-                    let count_buffer = trackers
-                        .buffers
-                        .use_extend(&*buffer_guard, count_buffer_id, (), BufferUse::INDIRECT)
-                        .unwrap();
-                    check_buffer_usage(count_buffer.usage, BufferUsage::INDIRECT)?;
+                    let pass =
+                        unsafe { device.raw.create_render_pass(all, iter::once(subpass), &[]) }
+                            .unwrap();
+                    entry.insert(pass)
+                }
+            };
 
-                    let begin_offset = offset;
-                    let end_offset = offset + stride * max_count as u64;
-                    if end_offset > buffer.size {
-                        return Err(RenderPassError::IndirectBufferOverrun {
-                            offset,
-                            count: None,
-                            begin_offset,
-                            end_offset,
-                            buffer_size: buffer.size,
-                        });
-                    }
+            let mut framebuffer_cache;
+            let fb_key = FramebufferKey {
+                colors: color_attachments
+                    .iter()
+                    .map(|at| id::Valid(at.attachment))
+                    .collect(),
+                resolves: color_attachments
+                    .iter()
+                    .filter_map(|at| at.resolve_target)
+                    .map(id::Valid)
+                    .collect(),
+                depth_stencil: depth_stencil_attachment.map(|at| id::Valid(at.attachment)),
+            };
+            let context = RenderPassContext {
+                attachments: AttachmentData {
+                    colors: fb_key
+                        .colors
+                        .iter()
+                        .map(|&at| view_guard[at].format)
+                        .collect(),
+                    resolves: fb_key
+                        .resolves
+                        .iter()
+                        .map(|&at| view_guard[at].format)
+                        .collect(),
+                    depth_stencil: fb_key.depth_stencil.map(|at| view_guard[at].format),
+                },
+                sample_count,
+            };
 
-                    let begin_count_offset = count_buffer_offset;
-                    let end_count_offset = count_buffer_offset + 4;
-                    if end_count_offset > count_buffer.size {
-                        return Err(RenderPassError::IndirectCountBufferOverrun {
-                            begin_count_offset,
-                            end_count_offset,
-                            count_buffer_size: count_buffer.size,
-                        });
+            let framebuffer = match used_swap_chain.take() {
+                Some(sc_id) => {
+                    assert!(cmd_buf.used_swap_chain.is_none());
+                    // Always create a new framebuffer and delete it after presentation.
+                    let attachments = fb_key
+                        .all()
+                        .map(|&id| match view_guard[id].inner {
+                            TextureViewInner::Native { ref raw, .. } => raw,
+                            TextureViewInner::SwapChain { ref image, .. } => Borrow::borrow(image),
+                        })
+                        .collect::<AttachmentDataVec<_>>();
+                    let framebuffer = unsafe {
+                        device
+                            .raw
+                            .create_framebuffer(&render_pass, attachments, extent.unwrap())
+                            .or(Err(RenderPassError::OutOfMemory))?
+                    };
+                    cmd_buf.used_swap_chain = Some((sc_id, framebuffer));
+                    &mut cmd_buf.used_swap_chain.as_mut().unwrap().1
+                }
+                None => {
+                    // Cache framebuffers by the device.
+                    framebuffer_cache = device.framebuffers.lock();
+                    match framebuffer_cache.entry(fb_key) {
+                        Entry::Occupied(e) => e.into_mut(),
+                        Entry::Vacant(e) => {
+                            let fb = {
+                                let attachments = e
+                                    .key()
+                                    .all()
+                                    .map(|&id| match view_guard[id].inner {
+                                        TextureViewInner::Native { ref raw, .. } => raw,
+                                        TextureViewInner::SwapChain { ref image, .. } => {
+                                            Borrow::borrow(image)
+                                        }
+                                    })
+                                    .collect::<AttachmentDataVec<_>>();
+                                unsafe {
+                                    device
+                                        .raw
+                                        .create_framebuffer(
+                                            &render_pass,
+                                            attachments,
+                                            extent.unwrap(),
+                                        )
+                                        .or(Err(RenderPassError::OutOfMemory))?
+                                }
+                            };
+                            e.insert(fb)
+                        }
                     }
+                }
+            };
 
-                    match indexed {
-                        false => unsafe {
-                            raw.draw_indirect_count(
-                                &buffer.raw,
-                                offset,
-                                &count_buffer.raw,
-                                count_buffer_offset,
-                                max_count,
-                                stride as u32,
-                            );
-                        },
-                        true => unsafe {
-                            raw.draw_indexed_indirect_count(
-                                &buffer.raw,
-                                offset,
-                                &count_buffer.raw,
-                                count_buffer_offset,
-                                max_count,
-                                stride as u32,
-                            );
-                        },
-                    }
+            let rect = {
+                let ex = extent.unwrap();
+                hal::pso::Rect {
+                    x: 0,
+                    y: 0,
+                    w: ex.width as _,
+                    h: ex.height as _,
                 }
-                RenderCommand::PushDebugGroup { color, len } => {
-                    state.debug_scope_depth += 1;
-                    let label = str::from_utf8(&base.string_data[..len]).unwrap();
-                    unsafe {
-                        raw.begin_debug_marker(label, color);
+            };
+
+            let clear_values = color_attachments
+                .iter()
+                .zip(&rp_key.colors)
+                .flat_map(|(at, (rat, _layout))| {
+                    match at.channel.load_op {
+                        LoadOp::Load => None,
+                        LoadOp::Clear => {
+                            use hal::format::ChannelType;
+                            //TODO: validate sign/unsign and normalized ranges of the color values
+                            let value = match rat.format.unwrap().base_format().1 {
+                                ChannelType::Unorm
+                                | ChannelType::Snorm
+                                | ChannelType::Ufloat
+                                | ChannelType::Sfloat
+                                | ChannelType::Uscaled
+                                | ChannelType::Sscaled
+                                | ChannelType::Srgb => hal::command::ClearColor {
+                                    float32: conv::map_color_f32(&at.channel.clear_value),
+                                },
+                                ChannelType::Sint => hal::command::ClearColor {
+                                    sint32: conv::map_color_i32(&at.channel.clear_value),
+                                },
+                                ChannelType::Uint => hal::command::ClearColor {
+                                    uint32: conv::map_color_u32(&at.channel.clear_value),
+                                },
+                            };
+                            Some(hal::command::ClearValue { color: value })
+                        }
                     }
-                    base.string_data = &base.string_data[len..];
-                }
-                RenderCommand::PopDebugGroup => {
-                    if state.debug_scope_depth == 0 {
-                        return Err(RenderPassError::InvalidPopDebugGroup);
+                })
+                .chain(depth_stencil_attachment.and_then(|at| {
+                    match (at.depth.load_op, at.stencil.load_op) {
+                        (LoadOp::Load, LoadOp::Load) => None,
+                        (LoadOp::Clear, _) | (_, LoadOp::Clear) => {
+                            let value = hal::command::ClearDepthStencil {
+                                depth: at.depth.clear_value,
+                                stencil: at.stencil.clear_value,
+                            };
+                            Some(hal::command::ClearValue {
+                                depth_stencil: value,
+                            })
+                        }
                     }
-                    state.debug_scope_depth -= 1;
-                    unsafe {
-                        raw.end_debug_marker();
+                }))
+                .collect::<ArrayVec<[_; MAX_COLOR_TARGETS + 1]>>();
+
+            let contents = if use_secondaries {
+                hal::command::SubpassContents::SecondaryBuffers
+            } else {
+                hal::command::SubpassContents::Inline
+            };
+            unsafe {
+                raw.begin_render_pass(render_pass, framebuffer, rect, clear_values, contents);
+            }
+
+            let env = SubPassEnv {
+                features: device.features,
+                max_bind_groups: device.limits.max_bind_groups,
+                context: &context,
+                rect,
+                is_ds_read_only,
+                bundle_guard: &bundle_guard,
+                pipeline_layout_guard: &pipeline_layout_guard,
+                bind_group_guard: &bind_group_guard,
+                pipeline_guard: &pipeline_guard,
+                buffer_guard: &buffer_guard,
+            };
+
+            if use_secondaries {
+                // Each sub-pass is recorded by its own job into a secondary command
+                // buffer. The jobs store their results, so that a job that's run twice
+                // by the executor doesn't encode the sub-pass again.
+                let results = bases.iter().map(|_| Mutex::new(None)).collect::<Vec<_>>();
+                let jobs = bases
+                    .iter()
+                    .zip(&results)
+                    .map(|(&base, result)| {
+                        let env = &env;
+                        let render_pass = &*render_pass;
+                        let framebuffer = &*framebuffer;
+                        move || {
+                            let mut result = result.lock();
+                            if result.is_none() {
+                                *result = Some(env.encode_secondary(
+                                    &device.cmd_allocator,
+                                    &device.raw,
+                                    render_pass,
+                                    framebuffer,
+                                    base,
+                                ));
+                            }
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let job_refs = jobs
+                    .iter()
+                    .map(|job| -> &SubPassJob { job })
+                    .collect::<Vec<_>>();
+                executor(&job_refs);
+                // Encode the sub-passes left out by the executor.
+                run_sub_passes_inline(&job_refs);
+
+                let mut secondaries = Vec::with_capacity(results.len());
+                let mut error = None;
+                for result in results.iter() {
+                    match result.lock().take().unwrap() {
+                        Ok((pool_index, secondary, sub_pass_trackers)) => {
+                            if error.is_none() {
+                                if let Err(conflict) = trackers.try_merge_extend(&sub_pass_trackers)
+                                {
+                                    error = Some(RenderPassError::ResourceUsageConflict(conflict));
+                                }
+                            }
+                            secondaries.push((pool_index, secondary));
+                        }
+                        Err(err) => {
+                            error.get_or_insert(err);
+                        }
                     }
                 }
-                RenderCommand::InsertDebugMarker { color, len } => {
-                    let label = str::from_utf8(&base.string_data[..len]).unwrap();
-                    unsafe {
-                        raw.insert_debug_marker(label, color);
+                if let Some(err) = error {
+                    for (pool_index, secondary) in secondaries {
+                        device
+                            .cmd_allocator
+                            .discard_secondary(pool_index, secondary);
                     }
+                    return Err(err);
                 }
-                RenderCommand::ExecuteBundle(bundle_id) => {
-                    let bundle = trackers
-                        .bundles
-                        .use_extend(&*bundle_guard, bundle_id, (), ())
-                        .unwrap();
-
-                    if !context.compatible(&bundle.context) {
-                        return Err(RenderPassError::IncompatibleRenderBundle);
-                    }
-
-                    unsafe {
-                        bundle.execute(
-                            &mut raw,
-                            &*pipeline_layout_guard,
-                            &*bind_group_guard,
-                            &*pipeline_guard,
-                            &*buffer_guard,
-                        )
-                    }
-
-                    trackers.merge_extend(&bundle.used);
-                    state.reset_bundle();
+                secondaries
+            } else {
+                for &base in bases {
+                    env.encode(&mut raw, base, &mut trackers)?;
                 }
+                Vec::new()
             }
-        }
+        };
 
         tracing::trace!("Merging {:?} with the render pass", encoder_id);
         unsafe {
+            if use_secondaries {
+                raw.execute_commands(secondaries.iter().map(|(_, secondary)| secondary));
+            }
             raw.end_render_pass();
         }
 
@@ -1548,6 +1746,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             cmd_buf.raw.last_mut().unwrap().finish();
         }
         cmd_buf.raw.push(raw);
+        cmd_buf.secondary.extend(secondaries);
 
         Ok(())
     }
@@ -13,6 +13,7 @@ use crate::{
     resource::{BufferUse, Texture, TextureUse},
     span,
     track::TextureSelector,
+    PrivateFeatures,
 };
 
 use hal::command::CommandBuffer as _;
@@ -89,6 +90,15 @@ pub enum TransferError {
     InvalidRowsPerImage,
     #[error("source and destination layers have different aspects")]
     MismatchedAspects,
+    #[error("texture of format {format:?} has no {aspect:?} aspect")]
+    MissingTextureAspect {
+        format: wgt::TextureFormat,
+        aspect: wgt::TextureAspect,
+    },
+    #[error("copies between a buffer and a texture of format {0:?} must select a single aspect")]
+    AmbiguousAspect(wgt::TextureFormat),
+    #[error("depth of D24Plus textures cannot be copied to or from a buffer")]
+    CannotCopyD24Plus,
 }
 
 /// Error encountered while attempting to do a copy on a command encoder.
@@ -118,6 +128,8 @@ pub(crate) fn texture_copy_view_to_hal<B: hal::Backend>(
         .get(view.texture)
         .map_err(|_| TransferError::InvalidTexture(view.texture))?;
 
+    let aspects = copy_aspects(texture.format, texture.aspects, view.aspect)?;
+
     let level = view.mip_level as hal::image::Level;
     let (layer, layer_count, z) = match texture.dimension {
         wgt::TextureDimension::D1 | wgt::TextureDimension::D2 => (
@@ -133,7 +145,7 @@ pub(crate) fn texture_copy_view_to_hal<B: hal::Backend>(
     #[allow(clippy::range_plus_one)]
     Ok((
         hal::image::SubresourceLayers {
-            aspects: texture.aspects,
+            aspects,
             level,
             layers: layer..layer + layer_count,
         },
@@ -149,6 +161,42 @@ pub(crate) fn texture_copy_view_to_hal<B: hal::Backend>(
     ))
 }
 
+/// Returns the aspects of a texture selected by a copy view.
+fn copy_aspects(
+    format: wgt::TextureFormat,
+    texture_aspects: hal::format::Aspects,
+    aspect: wgt::TextureAspect,
+) -> Result<hal::format::Aspects, TransferError> {
+    let aspects = conv::map_texture_aspect(aspect, texture_aspects);
+    if texture_aspects.contains(aspects) {
+        Ok(aspects)
+    } else {
+        Err(TransferError::MissingTextureAspect { format, aspect })
+    }
+}
+
+/// Returns the number of bytes a texel block of the given aspect of `format`
+/// occupies in a buffer.
+pub(crate) fn aspect_bytes_per_block(
+    format: wgt::TextureFormat,
+    aspect: wgt::TextureAspect,
+    private_features: PrivateFeatures,
+) -> Result<u32, TransferError> {
+    use wgt::{TextureAspect as Ta, TextureFormat as Tf};
+    match (format, aspect) {
+        (Tf::Depth24PlusStencil8, Ta::All) => Err(TransferError::AmbiguousAspect(format)),
+        (Tf::Depth24PlusStencil8, Ta::StencilOnly) => Ok(1),
+        // The representation of the depth depends on the backing format.
+        (Tf::Depth24Plus, _) | (Tf::Depth24PlusStencil8, Ta::DepthOnly) => {
+            Err(TransferError::CannotCopyD24Plus)
+        }
+        _ => Ok(conv::map_texture_format(format, private_features)
+            .surface_desc()
+            .bits as u32
+            / BITS_PER_BYTE),
+    }
+}
+
 /// Function copied with minor modifications from webgpu standard https://gpuweb.github.io/gpuweb/#valid-texture-copy-range
 pub(crate) fn validate_linear_texture_data(
     layout: &wgt::TextureDataLayout,
@@ -454,10 +502,11 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let dst_barriers = dst_pending.map(|pending| pending.into_hal(dst_texture));
 
         let bytes_per_row_alignment = wgt::COPY_BYTES_PER_ROW_ALIGNMENT;
-        let bytes_per_block = conv::map_texture_format(dst_texture.format, cmd_buf.private_features)
-            .surface_desc()
-            .bits as u32
-            / BITS_PER_BYTE;
+        let bytes_per_block = aspect_bytes_per_block(
+            dst_texture.format,
+            destination.aspect,
+            cmd_buf.private_features,
+        )?;
         let src_bytes_per_row = source.layout.bytes_per_row;
         if bytes_per_row_alignment % bytes_per_block != 0 {
             Err(TransferError::UnalignedBytesPerRow)?
@@ -568,10 +617,8 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let dst_barrier = dst_barriers.map(|pending| pending.into_hal(dst_buffer));
 
         let bytes_per_row_alignment = wgt::COPY_BYTES_PER_ROW_ALIGNMENT;
-        let bytes_per_block = conv::map_texture_format(src_texture.format, cmd_buf.private_features)
-            .surface_desc()
-            .bits as u32
-            / BITS_PER_BYTE;
+        let bytes_per_block =
+            aspect_bytes_per_block(src_texture.format, source.aspect, cmd_buf.private_features)?;
         let dst_bytes_per_row = destination.layout.bytes_per_row;
         if bytes_per_row_alignment % bytes_per_block != 0 {
             Err(TransferError::UnalignedBytesPerRow)?
@@ -735,3 +782,82 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{aspect_bytes_per_block, copy_aspects, TransferError};
+    use crate::PrivateFeatures;
+    use hal::format::Aspects;
+    use wgt::{TextureAspect as Ta, TextureFormat as Tf};
+
+    const FEATURES: PrivateFeatures = PrivateFeatures {
+        shader_validation: wgt::ShaderValidation::Off,
+        anisotropic_filtering: false,
+        texture_d24: true,
+        texture_d24_s8: true,
+        secondary_command_buffers: false,
+        unified_memory: false,
+    };
+
+    #[test]
+    fn aspect_selection() {
+        let depth_stencil = Aspects::DEPTH | Aspects::STENCIL;
+        assert_eq!(
+            copy_aspects(Tf::Depth24PlusStencil8, depth_stencil, Ta::All).unwrap(),
+            depth_stencil
+        );
+        assert_eq!(
+            copy_aspects(Tf::Depth24PlusStencil8, depth_stencil, Ta::DepthOnly).unwrap(),
+            Aspects::DEPTH
+        );
+        assert_eq!(
+            copy_aspects(Tf::Depth24PlusStencil8, depth_stencil, Ta::StencilOnly).unwrap(),
+            Aspects::STENCIL
+        );
+        assert_eq!(
+            copy_aspects(Tf::Rgba8Unorm, Aspects::COLOR, Ta::All).unwrap(),
+            Aspects::COLOR
+        );
+    }
+
+    #[test]
+    fn missing_aspect() {
+        match copy_aspects(Tf::Depth32Float, Aspects::DEPTH, Ta::StencilOnly) {
+            Err(TransferError::MissingTextureAspect {
+                format: Tf::Depth32Float,
+                aspect: Ta::StencilOnly,
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match copy_aspects(Tf::Rgba8Unorm, Aspects::COLOR, Ta::DepthOnly) {
+            Err(TransferError::MissingTextureAspect { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn buffer_copy_aspects() {
+        match aspect_bytes_per_block(Tf::Depth24PlusStencil8, Ta::All, FEATURES) {
+            Err(TransferError::AmbiguousAspect(Tf::Depth24PlusStencil8)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(
+            aspect_bytes_per_block(Tf::Depth24PlusStencil8, Ta::StencilOnly, FEATURES).unwrap(),
+            1
+        );
+        for &(format, aspect) in &[
+            (Tf::Depth24Plus, Ta::All),
+            (Tf::Depth24Plus, Ta::DepthOnly),
+            (Tf::Depth24PlusStencil8, Ta::DepthOnly),
+        ] {
+            match aspect_bytes_per_block(format, aspect, FEATURES) {
+                Err(TransferError::CannotCopyD24Plus) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        assert_eq!(
+            aspect_bytes_per_block(Tf::Depth32Float, Ta::DepthOnly, FEATURES).unwrap(),
+            4
+        );
+    }
+}
@@ -402,6 +402,17 @@ pub(crate) fn map_texture_format(
     }
 }
 
+pub fn map_texture_aspect(
+    aspect: wgt::TextureAspect,
+    texture_aspects: hal::format::Aspects,
+) -> hal::format::Aspects {
+    match aspect {
+        wgt::TextureAspect::All => texture_aspects,
+        wgt::TextureAspect::DepthOnly => hal::format::Aspects::DEPTH,
+        wgt::TextureAspect::StencilOnly => hal::format::Aspects::STENCIL,
+    }
+}
+
 pub fn texture_block_size(format: wgt::TextureFormat) -> (u32, u32) {
     use wgt::TextureFormat as Tf;
     match format {
@@ -570,11 +581,27 @@ pub fn map_texture_dimension_size(
         depth,
     }: wgt::Extent3d,
     sample_size: u32,
+    limits: &wgt::Limits,
 ) -> Result<hal::image::Kind, resource::TextureDimensionError> {
     use hal::image::Kind as H;
-    use resource::TextureDimensionError as Tde;
+    use resource::{TextureDimensionError as Tde, TextureErrorDimension as Ted};
     use wgt::TextureDimension::*;
 
+    let check_dimension = |dim, given, limit| {
+        if given > limit {
+            Err(Tde::LimitExceeded { dim, given, limit })
+        } else {
+            Ok(())
+        }
+    };
+    let check_layers = |layers| {
+        if layers > limits.max_texture_array_layers {
+            Err(Tde::TooManyLayers(layers))
+        } else {
+            layers.try_into().or(Err(Tde::TooManyLayers(layers)))
+        }
+    };
+
     Ok(match dimension {
         D1 => {
             if height != 1 {
@@ -583,20 +610,24 @@ pub fn map_texture_dimension_size(
             if sample_size != 1 {
                 return Err(Tde::InvalidSampleCount(sample_size));
             }
-            let layers = depth.try_into().or(Err(Tde::TooManyLayers(depth)))?;
-            H::D1(width, layers)
+            check_dimension(Ted::X, width, limits.max_texture_dimension_1d)?;
+            H::D1(width, check_layers(depth)?)
         }
         D2 => {
             if sample_size > 32 || !is_power_of_two(sample_size) {
                 return Err(Tde::InvalidSampleCount(sample_size));
             }
-            let layers = depth.try_into().or(Err(Tde::TooManyLayers(depth)))?;
-            H::D2(width, height, layers, sample_size as u8)
+            check_dimension(Ted::X, width, limits.max_texture_dimension_2d)?;
+            check_dimension(Ted::Y, height, limits.max_texture_dimension_2d)?;
+            H::D2(width, height, check_layers(depth)?, sample_size as u8)
         }
         D3 => {
             if sample_size != 1 {
                 return Err(Tde::InvalidSampleCount(sample_size));
             }
+            check_dimension(Ted::X, width, limits.max_texture_dimension_3d)?;
+            check_dimension(Ted::Y, height, limits.max_texture_dimension_3d)?;
+            check_dimension(Ted::Z, depth, limits.max_texture_dimension_3d)?;
             H::D3(width, height, depth)
         }
     })
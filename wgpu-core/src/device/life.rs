@@ -5,7 +5,10 @@
 #[cfg(feature = "trace")]
 use crate::device::trace;
 use crate::{
-    device::DeviceError,
+    device::{
+        queue::{PendingReadback, QueueReadPendingCallback, StagingChunk},
+        DestroyedResource, DeviceError, SubmittedWorkDoneClosure,
+    },
     hub::{GfxBackend, GlobalIdentityHandlerFactory, Hub, Token},
     id, resource,
     track::TrackerSet,
@@ -17,12 +20,50 @@ use gfx_descriptor::{DescriptorAllocator, DescriptorSet};
 use gfx_memory::{Heaps, MemoryBlock};
 use hal::device::Device as _;
 use parking_lot::Mutex;
+use smallvec::SmallVec;
 use thiserror::Error;
 
-use std::sync::atomic::Ordering;
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
 const CLEANUP_WAIT_MS: u64 = 5000;
 
+/// Waits for a fence returned by `LifetimeTracker::free_slot_fence`.
+///
+/// This is meant to be called with no device locks held.
+pub(crate) fn wait_for_free_slot<B: hal::Backend>(
+    device: &B::Device,
+    fence: &B::Fence,
+) -> Result<(), WaitIdleError> {
+    match wait_for_fence::<B>(device, fence, Some(Duration::from_millis(CLEANUP_WAIT_MS))) {
+        Err(WaitIdleError::Timeout) => Err(WaitIdleError::StuckGpu),
+        other => other,
+    }
+}
+
+/// Waits for a submission fence, up to an optional timeout.
+///
+/// This is meant to be called with no device locks held.
+pub(crate) fn wait_for_fence<B: hal::Backend>(
+    device: &B::Device,
+    fence: &B::Fence,
+    timeout: Option<Duration>,
+) -> Result<(), WaitIdleError> {
+    let timeout_ns = timeout.map_or(!0, |t| t.as_nanos().min(!0u64 as u128) as u64);
+    let done = unsafe {
+        device
+            .wait_for_fence(fence, timeout_ns)
+            .map_err(DeviceError::from)?
+    };
+    if done {
+        Ok(())
+    } else {
+        Err(WaitIdleError::Timeout)
+    }
+}
+
 /// A struct that keeps lists of resources that are no longer needed by the user.
 #[derive(Debug, Default)]
 pub struct SuspectedResources {
@@ -36,6 +77,7 @@ pub struct SuspectedResources {
     pub(crate) bind_group_layouts: Vec<id::Valid<id::BindGroupLayoutId>>,
     pub(crate) pipeline_layouts: Vec<Stored<id::PipelineLayoutId>>,
     pub(crate) render_bundles: Vec<id::Valid<id::RenderBundleId>>,
+    pub(crate) compute_bundles: Vec<id::Valid<id::ComputeBundleId>>,
 }
 
 impl SuspectedResources {
@@ -50,6 +92,7 @@ impl SuspectedResources {
         self.bind_group_layouts.clear();
         self.pipeline_layouts.clear();
         self.render_bundles.clear();
+        self.compute_bundles.clear();
     }
 
     pub(crate) fn extend(&mut self, other: &Self) {
@@ -67,6 +110,8 @@ impl SuspectedResources {
         self.pipeline_layouts
             .extend_from_slice(&other.pipeline_layouts);
         self.render_bundles.extend_from_slice(&other.render_bundles);
+        self.compute_bundles
+            .extend_from_slice(&other.compute_bundles);
     }
 
     pub(crate) fn add_trackers(&mut self, trackers: &TrackerSet) {
@@ -78,6 +123,7 @@ impl SuspectedResources {
         self.compute_pipelines.extend(trackers.compute_pipes.used());
         self.render_pipelines.extend(trackers.render_pipes.used());
         self.render_bundles.extend(trackers.bundles.used());
+        self.compute_bundles.extend(trackers.compute_bundles.used());
     }
 }
 
@@ -96,6 +142,8 @@ struct NonReferencedResources<B: hal::Backend> {
     graphics_pipes: Vec<B::GraphicsPipeline>,
     descriptor_set_layouts: Vec<B::DescriptorSetLayout>,
     pipeline_layouts: Vec<B::PipelineLayout>,
+    /// Resources reported as destroyed once these are cleaned up, with their labels.
+    destroyed: Vec<(DestroyedResource, String)>,
 }
 
 impl<B: hal::Backend> NonReferencedResources<B> {
@@ -111,6 +159,7 @@ impl<B: hal::Backend> NonReferencedResources<B> {
             graphics_pipes: Vec::new(),
             descriptor_set_layouts: Vec::new(),
             pipeline_layouts: Vec::new(),
+            destroyed: Vec::new(),
         }
     }
 
@@ -123,6 +172,7 @@ impl<B: hal::Backend> NonReferencedResources<B> {
         self.desc_sets.extend(other.desc_sets);
         self.compute_pipes.extend(other.compute_pipes);
         self.graphics_pipes.extend(other.graphics_pipes);
+        self.destroyed.extend(other.destroyed);
         assert!(other.descriptor_set_layouts.is_empty());
         assert!(other.pipeline_layouts.is_empty());
     }
@@ -181,9 +231,12 @@ impl<B: hal::Backend> NonReferencedResources<B> {
 #[derive(Debug)]
 struct ActiveSubmission<B: hal::Backend> {
     index: SubmissionIndex,
-    fence: B::Fence,
+    /// Shared with the callers waiting on it without the device locks.
+    fence: Arc<B::Fence>,
     last_resources: NonReferencedResources<B>,
     mapped: Vec<id::Valid<id::BufferId>>,
+    work_done_closures: SmallVec<[SubmittedWorkDoneClosure; 1]>,
+    readbacks: Vec<PendingReadback<B>>,
 }
 
 #[derive(Clone, Debug, Error)]
@@ -192,6 +245,13 @@ pub enum WaitIdleError {
     Device(#[from] DeviceError),
     #[error("GPU got stuck :(")]
     StuckGpu,
+    #[error("timed out waiting for the submission")]
+    Timeout,
+    #[error("submission was made on queue {submission_queue:?}, not on device {device:?}")]
+    WrongSubmission {
+        submission_queue: id::QueueId,
+        device: id::DeviceId,
+    },
 }
 
 /// A struct responsible for tracking resource lifetimes.
@@ -221,6 +281,18 @@ pub(crate) struct LifetimeTracker<B: hal::Backend> {
     /// actual deletion.
     free_resources: NonReferencedResources<B>,
     ready_to_map: Vec<id::Valid<id::BufferId>>,
+    /// Closures of the submissions that are done, waiting to be fired.
+    work_done_closures: SmallVec<[SubmittedWorkDoneClosure; 1]>,
+    /// Readbacks recorded for the upcoming submission.
+    future_readbacks: Vec<PendingReadback<B>>,
+    /// Readbacks of the submissions that are done, waiting to be read.
+    ready_readbacks: Vec<PendingReadback<B>>,
+    /// Staging chunks of the resolved readbacks, to be returned to the staging ring.
+    free_staging_chunks: Vec<StagingChunk<B>>,
+    /// Resources that got destroyed, waiting to be reported.
+    destroyed_resources: Vec<(DestroyedResource, String)>,
+    /// Fences of the done submissions that are still being waited on.
+    lingering_fences: Vec<Arc<B::Fence>>,
 }
 
 impl<B: hal::Backend> LifetimeTracker<B> {
@@ -233,6 +305,12 @@ impl<B: hal::Backend> LifetimeTracker<B> {
             active: Vec::new(),
             free_resources: NonReferencedResources::new(),
             ready_to_map: Vec::new(),
+            work_done_closures: SmallVec::new(),
+            future_readbacks: Vec::new(),
+            ready_readbacks: Vec::new(),
+            free_staging_chunks: Vec::new(),
+            destroyed_resources: Vec::new(),
+            lingering_fences: Vec::new(),
         }
     }
 
@@ -258,9 +336,11 @@ impl<B: hal::Backend> LifetimeTracker<B> {
         self.suspected_resources.extend(new_suspects);
         self.active.alloc().init(ActiveSubmission {
             index,
-            fence,
+            fence: Arc::new(fence),
             last_resources,
             mapped: Vec::new(),
+            work_done_closures: SmallVec::new(),
+            readbacks: self.future_readbacks.drain(..).collect(),
         });
     }
 
@@ -274,7 +354,7 @@ impl<B: hal::Backend> LifetimeTracker<B> {
             let status = unsafe {
                 device
                     .wait_for_fences(
-                        self.active.iter().map(|a| &a.fence),
+                        self.active.iter().map(|a| &*a.fence),
                         hal::device::WaitFor::All,
                         CLEANUP_WAIT_MS * 1_000_000,
                     )
@@ -290,6 +370,91 @@ impl<B: hal::Backend> LifetimeTracker<B> {
         Ok(())
     }
 
+    /// Returns the fence of the given submission, if it's still active.
+    ///
+    /// Submissions that are not active anymore are done.
+    pub fn submission_fence(&self, index: SubmissionIndex) -> Option<Arc<B::Fence>> {
+        self.active
+            .iter()
+            .find(|a| a.index == index)
+            .map(|a| Arc::clone(&a.fence))
+    }
+
+    /// Returns the fence to wait on for less than `max` submissions to be in flight,
+    /// if there are too many of them.
+    pub fn free_slot_fence(&self, max: usize) -> Option<Arc<B::Fence>> {
+        if self.active.len() < max {
+            return None;
+        }
+        // Submissions complete in order, so waiting on this one frees enough slots.
+        let active = &self.active[self.active.len() - max];
+        tracing::debug!("Waiting for submission {} to free a slot...", active.index);
+        Some(Arc::clone(&active.fence))
+    }
+
+    pub(crate) fn add_readback(&mut self, readback: PendingReadback<B>) {
+        self.future_readbacks.push(readback);
+    }
+
+    /// Reads back the data of the done submissions.
+    pub(crate) fn handle_readbacks(&mut self, device: &B::Device) -> Vec<QueueReadPendingCallback> {
+        let mut callbacks = Vec::with_capacity(self.ready_readbacks.len());
+        for readback in self.ready_readbacks.drain(..) {
+            let (chunk, callback) = readback.resolve(device);
+            self.free_staging_chunks.push(chunk);
+            callbacks.push(callback);
+        }
+        callbacks
+    }
+
+    pub(crate) fn take_free_staging_chunks(&mut self) -> impl Iterator<Item = StagingChunk<B>> {
+        std::mem::take(&mut self.free_staging_chunks).into_iter()
+    }
+
+    /// Resolves the readbacks of the done submissions, and fails the other ones.
+    ///
+    /// Returns all the staging chunks owned by the tracker.
+    pub(crate) fn into_staging_chunks(
+        mut self,
+        device: &B::Device,
+        callbacks: &mut Vec<QueueReadPendingCallback>,
+    ) -> impl Iterator<Item = StagingChunk<B>> {
+        callbacks.extend(self.handle_readbacks(device));
+        let mut chunks = self.free_staging_chunks;
+        let outstanding = self
+            .active
+            .into_iter()
+            .flat_map(|a| a.readbacks)
+            .chain(self.future_readbacks);
+        for readback in outstanding {
+            let (chunk, callback) = readback.cancel();
+            chunks.push(chunk);
+            callbacks.push(callback);
+        }
+        chunks.into_iter()
+    }
+
+    /// Attaches the closure to the last submission, or makes it ready
+    /// if there is nothing in flight.
+    pub fn add_work_done_closure(&mut self, closure: SubmittedWorkDoneClosure) {
+        match self.active.last_mut() {
+            Some(active) => active.work_done_closures.push(closure),
+            None => self.work_done_closures.push(closure),
+        }
+    }
+
+    pub(crate) fn take_work_done_closures(&mut self) -> SmallVec<[SubmittedWorkDoneClosure; 1]> {
+        std::mem::take(&mut self.work_done_closures)
+    }
+
+    /// Returns the index of the oldest submission that is not done yet, if any.
+    pub fn first_pending_submission(&self, device: &B::Device) -> Option<SubmissionIndex> {
+        self.active
+            .iter()
+            .find(|a| unsafe { !device.get_fence_status(&a.fence).unwrap_or(false) })
+            .map(|a| a.index)
+    }
+
     /// Returns the last submission index that is done.
     pub fn triage_submissions(
         &mut self,
@@ -306,18 +471,26 @@ impl<B: hal::Backend> LifetimeTracker<B> {
             .iter()
             .position(|a| unsafe { !device.get_fence_status(&a.fence).unwrap_or(false) })
             .unwrap_or_else(|| self.active.len());
-        let last_done = if done_count != 0 {
-            self.active[done_count - 1].index
-        } else {
-            return Ok(0);
+        let last_done = match done_count {
+            0 => 0,
+            count => self.active[count - 1].index,
         };
 
         for a in self.active.drain(..done_count) {
             tracing::trace!("Active submission {} is done", a.index);
             self.free_resources.extend(a.last_resources);
             self.ready_to_map.extend(a.mapped);
-            unsafe {
-                device.destroy_fence(a.fence);
+            self.work_done_closures.extend(a.work_done_closures);
+            self.ready_readbacks.extend(a.readbacks);
+            self.lingering_fences.push(a.fence);
+        }
+        // Fences that are still waited on get destroyed by a later triage.
+        for fence in std::mem::take(&mut self.lingering_fences) {
+            match Arc::try_unwrap(fence) {
+                Ok(fence) => unsafe {
+                    device.destroy_fence(fence);
+                },
+                Err(fence) => self.lingering_fences.push(fence),
             }
         }
 
@@ -335,6 +508,15 @@ impl<B: hal::Backend> LifetimeTracker<B> {
                 .clean(device, heaps_mutex, descriptor_allocator_mutex);
             descriptor_allocator_mutex.lock().cleanup(device);
         }
+        for &(resource, ref label) in self.free_resources.destroyed.iter() {
+            tracing::debug!("{:?} (label {:?}) is destroyed", resource, label);
+        }
+        self.destroyed_resources
+            .append(&mut self.free_resources.destroyed);
+    }
+
+    pub(crate) fn take_destroyed_resources(&mut self) -> Vec<(DestroyedResource, String)> {
+        std::mem::take(&mut self.destroyed_resources)
     }
 }
 
@@ -356,6 +538,29 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                     trace.map(|t| t.lock().add(trace::Action::DestroyRenderBundle(id.0)));
                     let res = hub.render_bundles.unregister_locked(id.0, &mut *guard);
                     self.suspected_resources.add_trackers(&res.used);
+                    self.free_resources
+                        .destroyed
+                        .push((DestroyedResource::RenderBundle(id.0), res.life_guard.label));
+                }
+            }
+        }
+
+        if !self.suspected_resources.compute_bundles.is_empty() {
+            let mut trackers = trackers.lock();
+            let (mut guard, _) = hub.compute_bundles.write(token);
+
+            while let Some(id) = self.suspected_resources.compute_bundles.pop() {
+                if trackers.compute_bundles.remove_abandoned(id) {
+                    #[cfg(feature = "trace")]
+                    trace.map(|t| t.lock().add(trace::Action::DestroyComputeBundle(id.0)));
+                    let res = hub.compute_bundles.unregister_locked(id.0, &mut *guard);
+                    self.suspected_resources.add_trackers(&res.used);
+                    for scope in res.scopes.iter() {
+                        self.suspected_resources.add_trackers(scope);
+                    }
+                    self.free_resources
+                        .destroyed
+                        .push((DestroyedResource::ComputeBundle(id.0), res.life_guard.label));
                 }
             }
         }
@@ -373,12 +578,15 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                     self.suspected_resources.add_trackers(&res.used);
 
                     let submit_index = res.life_guard.submission_index.load(Ordering::Acquire);
-                    self.active
+                    let resources = self
+                        .active
                         .iter_mut()
                         .find(|a| a.index == submit_index)
-                        .map_or(&mut self.free_resources, |a| &mut a.last_resources)
-                        .desc_sets
-                        .push(res.raw);
+                        .map_or(&mut self.free_resources, |a| &mut a.last_resources);
+                    resources.desc_sets.push(res.raw);
+                    resources
+                        .destroyed
+                        .push((DestroyedResource::BindGroup(id.0), res.life_guard.label));
                 }
             }
         }
@@ -402,12 +610,15 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                     };
 
                     let submit_index = res.life_guard.submission_index.load(Ordering::Acquire);
-                    self.active
+                    let resources = self
+                        .active
                         .iter_mut()
                         .find(|a| a.index == submit_index)
-                        .map_or(&mut self.free_resources, |a| &mut a.last_resources)
-                        .image_views
-                        .push((id, raw));
+                        .map_or(&mut self.free_resources, |a| &mut a.last_resources);
+                    resources.image_views.push((id, raw));
+                    resources
+                        .destroyed
+                        .push((DestroyedResource::TextureView(id.0), res.life_guard.label));
                 }
             }
         }
@@ -423,12 +634,15 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                     let res = hub.textures.unregister_locked(id.0, &mut *guard);
 
                     let submit_index = res.life_guard.submission_index.load(Ordering::Acquire);
-                    self.active
+                    let resources = self
+                        .active
                         .iter_mut()
                         .find(|a| a.index == submit_index)
-                        .map_or(&mut self.free_resources, |a| &mut a.last_resources)
-                        .images
-                        .push((res.raw, res.memory));
+                        .map_or(&mut self.free_resources, |a| &mut a.last_resources);
+                    resources.images.push((res.raw, res.memory));
+                    resources
+                        .destroyed
+                        .push((DestroyedResource::Texture(id.0), res.life_guard.label));
                 }
             }
         }
@@ -444,12 +658,15 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                     let res = hub.samplers.unregister_locked(id.0, &mut *guard);
 
                     let submit_index = res.life_guard.submission_index.load(Ordering::Acquire);
-                    self.active
+                    let resources = self
+                        .active
                         .iter_mut()
                         .find(|a| a.index == submit_index)
-                        .map_or(&mut self.free_resources, |a| &mut a.last_resources)
-                        .samplers
-                        .push(res.raw);
+                        .map_or(&mut self.free_resources, |a| &mut a.last_resources);
+                    resources.samplers.push(res.raw);
+                    resources
+                        .destroyed
+                        .push((DestroyedResource::Sampler(id.0), res.life_guard.label));
                 }
             }
         }
@@ -466,12 +683,15 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                     tracing::debug!("Buffer {:?} is detached", id);
 
                     let submit_index = res.life_guard.submission_index.load(Ordering::Acquire);
-                    self.active
+                    let resources = self
+                        .active
                         .iter_mut()
                         .find(|a| a.index == submit_index)
-                        .map_or(&mut self.free_resources, |a| &mut a.last_resources)
-                        .buffers
-                        .push((res.raw, res.memory));
+                        .map_or(&mut self.free_resources, |a| &mut a.last_resources);
+                    resources.buffers.push((res.raw, res.memory));
+                    resources
+                        .destroyed
+                        .push((DestroyedResource::Buffer(id.0), res.life_guard.label));
                 }
             }
         }
@@ -487,12 +707,16 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                     let res = hub.compute_pipelines.unregister_locked(id.0, &mut *guard);
 
                     let submit_index = res.life_guard.submission_index.load(Ordering::Acquire);
-                    self.active
+                    let resources = self
+                        .active
                         .iter_mut()
                         .find(|a| a.index == submit_index)
-                        .map_or(&mut self.free_resources, |a| &mut a.last_resources)
-                        .compute_pipes
-                        .push(res.raw);
+                        .map_or(&mut self.free_resources, |a| &mut a.last_resources);
+                    resources.compute_pipes.push(res.raw);
+                    resources.destroyed.push((
+                        DestroyedResource::ComputePipeline(id.0),
+                        res.life_guard.label,
+                    ));
                 }
             }
         }
@@ -508,12 +732,16 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                     let res = hub.render_pipelines.unregister_locked(id.0, &mut *guard);
 
                     let submit_index = res.life_guard.submission_index.load(Ordering::Acquire);
-                    self.active
+                    let resources = self
+                        .active
                         .iter_mut()
                         .find(|a| a.index == submit_index)
-                        .map_or(&mut self.free_resources, |a| &mut a.last_resources)
-                        .graphics_pipes
-                        .push(res.raw);
+                        .map_or(&mut self.free_resources, |a| &mut a.last_resources);
+                    resources.graphics_pipes.push(res.raw);
+                    resources.destroyed.push((
+                        DestroyedResource::RenderPipeline(id.0),
+                        res.life_guard.label,
+                    ));
                 }
             }
         }
@@ -536,6 +764,10 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                         .bind_group_layouts
                         .extend_from_slice(&layout.bind_group_layout_ids);
                     self.free_resources.pipeline_layouts.push(layout.raw);
+                    self.free_resources.destroyed.push((
+                        DestroyedResource::PipelineLayout(id.0),
+                        layout.life_guard.label,
+                    ));
                 }
             }
         }
@@ -553,6 +785,9 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                     trace.map(|t| t.lock().add(trace::Action::DestroyBindGroupLayout(id.0)));
                     let layout = hub.bind_group_layouts.unregister_locked(id.0, &mut *guard);
                     self.free_resources.descriptor_set_layouts.push(layout.raw);
+                    self.free_resources
+                        .destroyed
+                        .push((DestroyedResource::BindGroupLayout(id.0), layout.label));
                 }
             }
         }
@@ -690,7 +925,7 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                     resource::BufferMapState::Waiting(pending_mapping) => pending_mapping,
                     _ => panic!("No pending mapping."),
                 };
-                let status = if mapping.sub_range.size.map_or(true, |x| x != 0) {
+                let result = if mapping.sub_range.size.map_or(true, |x| x != 0) {
                     tracing::debug!("Buffer {:?} map state -> Active", buffer_id);
                     let host = mapping.op.host;
                     match super::map_buffer(raw, buffer, mapping.sub_range.clone(), host) {
@@ -699,20 +934,49 @@ impl<B: GfxBackend> LifetimeTracker<B> {
                                 ptr,
                                 sub_range: mapping.sub_range,
                                 host,
+                                mapped_ranges: Vec::new(),
                             };
-                            resource::BufferMapAsyncStatus::Success
+                            Ok(())
                         }
                         Err(e) => {
                             tracing::error!("Mapping failed {:?}", e);
-                            resource::BufferMapAsyncStatus::Error
+                            Err(e)
                         }
                     }
                 } else {
-                    resource::BufferMapAsyncStatus::Success
+                    Ok(())
                 };
-                pending_callbacks.push((mapping.op, status));
+                pending_callbacks.push((mapping.op, result));
             }
         }
         pending_callbacks
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{wait_for_free_slot, LifetimeTracker, SuspectedResources};
+    use crate::backend::Empty;
+    use std::{iter, sync::Arc};
+
+    #[test]
+    fn free_slot_fence() {
+        let device = gfx_backend_empty::Device;
+        let mut tracker = LifetimeTracker::<Empty>::new();
+        let suspected = SuspectedResources::default();
+        for index in 1..=2 {
+            tracker.track_submission(index, (), &suspected, iter::empty());
+        }
+
+        // Below the limit, submitting doesn't block.
+        assert!(tracker.free_slot_fence(3).is_none());
+        // At the limit, the oldest submissions have to be done first.
+        let fence = tracker.free_slot_fence(2).unwrap();
+        assert!(Arc::ptr_eq(&fence, &tracker.active[0].fence));
+        let last = tracker.free_slot_fence(1).unwrap();
+        assert!(Arc::ptr_eq(&last, &tracker.active[1].fence));
+        // The wait doesn't need the tracker, and returns once the fence is signaled.
+        drop(tracker);
+        wait_for_free_slot::<Empty>(&device, &fence).unwrap();
+    }
+}
@@ -30,8 +30,16 @@ use thiserror::Error;
 use wgt::{BufferAddress, BufferSize, InputStepMode, TextureDimension, TextureFormat};
 
 use std::{
-    borrow::Cow, collections::hash_map::Entry, iter, marker::PhantomData, mem, ops::Range, ptr,
-    sync::atomic::Ordering,
+    borrow::Cow,
+    collections::hash_map::Entry,
+    fmt, iter,
+    marker::PhantomData,
+    mem,
+    num::NonZeroU32,
+    ops::Range,
+    ptr,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
 };
 
 mod life;
@@ -39,6 +47,8 @@ mod queue;
 #[cfg(any(feature = "trace", feature = "replay"))]
 pub mod trace;
 
+pub use queue::{QueueReadCallback, SubmittedWorkDoneCallback, SubmittedWorkDoneClosure};
+
 use smallvec::SmallVec;
 #[cfg(feature = "trace")]
 use trace::{Action, Trace};
@@ -78,6 +88,70 @@ pub enum HostMap {
     Write,
 }
 
+/// Opaque handle of a queue submission, returned by `queue_submit`.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct SubmissionHandle {
+    queue_id: id::QueueId,
+    index: SubmissionIndex,
+}
+
+/// Specifies how `device_poll` waits for the submitted work.
+#[derive(Clone, Copy, Debug)]
+pub enum Maintain {
+    /// Only handle the work that is already done.
+    Poll,
+    /// Wait for all the submitted work to be done.
+    Wait,
+    /// Wait for the given submission to be done, up to an optional timeout.
+    WaitForSubmission {
+        submission: SubmissionHandle,
+        timeout: Option<Duration>,
+    },
+}
+
+/// Resource physically destroyed by a device.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DestroyedResource {
+    Buffer(id::BufferId),
+    Texture(id::TextureId),
+    TextureView(id::TextureViewId),
+    Sampler(id::SamplerId),
+    BindGroup(id::BindGroupId),
+    BindGroupLayout(id::BindGroupLayoutId),
+    PipelineLayout(id::PipelineLayoutId),
+    ComputePipeline(id::ComputePipelineId),
+    RenderPipeline(id::RenderPipelineId),
+    RenderBundle(id::RenderBundleId),
+    ComputeBundle(id::ComputeBundleId),
+    ShaderModule(id::ShaderModuleId),
+}
+
+/// Callback invoked with each resource destroyed by a device, along with its label.
+///
+/// It's called after the device locks are released, so it may call back into wgpu-core.
+#[derive(Clone)]
+pub struct ResourceDestroyedClosure(Arc<ResourceDestroyedFn>);
+
+type ResourceDestroyedFn = dyn Fn(DestroyedResource, &str) + Send + Sync;
+
+impl ResourceDestroyedClosure {
+    pub fn new(callback: impl Fn(DestroyedResource, &str) + Send + Sync + 'static) -> Self {
+        ResourceDestroyedClosure(Arc::new(callback))
+    }
+}
+
+impl fmt::Debug for ResourceDestroyedClosure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResourceDestroyedClosure")
+    }
+}
+
+/// Resources destroyed by a device, to be reported to its callback.
+struct ResourceDestructions {
+    callback: ResourceDestroyedClosure,
+    resources: Vec<(DestroyedResource, String)>,
+}
+
 #[derive(Clone, Debug, Hash, PartialEq)]
 #[cfg_attr(feature = "serial-pass", derive(serde::Deserialize, serde::Serialize))]
 pub(crate) struct AttachmentData<T> {
@@ -116,7 +190,82 @@ impl RenderPassContext {
     }
 }
 
-type BufferMapPendingCallback = (resource::BufferMapOperation, resource::BufferMapAsyncStatus);
+type BufferMapPendingCallback = (
+    resource::BufferMapOperation,
+    Result<(), resource::BufferAccessError>,
+);
+
+/// User callbacks collected under the locks, to be fired once they are released.
+#[derive(Default)]
+struct UserClosures {
+    mappings: Vec<BufferMapPendingCallback>,
+    submissions: SmallVec<[SubmittedWorkDoneClosure; 1]>,
+    readbacks: Vec<queue::QueueReadPendingCallback>,
+    destructions: Vec<ResourceDestructions>,
+}
+
+impl UserClosures {
+    fn extend(&mut self, other: Self) {
+        self.mappings.extend(other.mappings);
+        self.submissions.extend(other.submissions);
+        self.readbacks.extend(other.readbacks);
+        self.destructions.extend(other.destructions);
+    }
+
+    fn fire(self) {
+        for (operation, result) in self.mappings {
+            operation.callback.call(result);
+        }
+        for (callback, result) in self.readbacks {
+            callback(result);
+        }
+        for closure in self.submissions {
+            closure.call();
+        }
+        for destructions in self.destructions {
+            for (resource, label) in destructions.resources {
+                (destructions.callback.0)(resource, &label);
+            }
+        }
+    }
+}
+
+/// Shader stage with its pipeline-overridable constants applied.
+///
+/// Owns the shader module generated for the pipeline, if any, and destroys it
+/// once the pipeline is created.
+struct SpecializedStage<'a, B: hal::Backend> {
+    device: &'a B::Device,
+    raw: Option<B::ShaderModule>,
+    specialization: hal::pso::Specialization<'static>,
+}
+
+impl<B: hal::Backend> SpecializedStage<'_, B> {
+    fn entry_point<'a>(
+        &'a self,
+        entry: &'a str,
+        module: &'a pipeline::ShaderModule<B>,
+    ) -> hal::pso::EntryPoint<'a, B> {
+        hal::pso::EntryPoint {
+            entry,
+            module: self.raw.as_ref().unwrap_or(&module.raw),
+            specialization: hal::pso::Specialization {
+                constants: Cow::Borrowed(&self.specialization.constants),
+                data: Cow::Borrowed(&self.specialization.data),
+            },
+        }
+    }
+}
+
+impl<B: hal::Backend> Drop for SpecializedStage<'_, B> {
+    fn drop(&mut self) {
+        if let Some(raw) = self.raw.take() {
+            unsafe {
+                self.device.destroy_shader_module(raw);
+            }
+        }
+    }
+}
 
 fn map_buffer<B: hal::Backend>(
     raw: &B::Device,
@@ -165,15 +314,10 @@ fn unmap_buffer<B: hal::Backend>(
 
 //Note: this logic is specifically moved out of `handle_mapping()` in order to
 // have nothing locked by the time we execute users callback code.
-fn fire_map_callbacks<I: IntoIterator<Item = BufferMapPendingCallback>>(callbacks: I) {
-    for (operation, status) in callbacks {
-        unsafe { (operation.callback)(status, operation.user_data) }
-    }
-}
-
 #[derive(Debug)]
 pub struct Device<B: hal::Backend> {
-    pub(crate) raw: B::Device,
+    // Shared, so that fences can be waited on without holding the device locks.
+    pub(crate) raw: Arc<B::Device>,
     pub(crate) adapter_id: Stored<id::AdapterId>,
     pub(crate) queue_group: hal::queue::QueueGroup<B>,
     pub(crate) cmd_allocator: command::CommandAllocator<B>,
@@ -192,6 +336,8 @@ pub struct Device<B: hal::Backend> {
     pub(crate) private_features: PrivateFeatures,
     pub(crate) limits: wgt::Limits,
     pub(crate) features: wgt::Features,
+    max_submissions_in_flight: Option<NonZeroU32>,
+    resource_destroyed_callback: Option<ResourceDestroyedClosure>,
     //TODO: move this behind another mutex. This would allow several methods to switch
     // to borrow Device immutably, such as `write_buffer`, `write_texture`, and `buffer_unmap`.
     pending_writes: queue::PendingWrites<B>,
@@ -240,13 +386,13 @@ impl<B: GfxBackend> Device<B> {
         }
 
         Ok(Device {
-            raw,
+            raw: Arc::new(raw),
             adapter_id,
             cmd_allocator,
             mem_allocator: Mutex::new(heaps),
             desc_allocator: Mutex::new(descriptors),
             queue_group,
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new("<Device>"),
             active_submission_index: 0,
             trackers: Mutex::new(TrackerSet::new(B::VARIANT)),
             render_passes: Mutex::new(FastHashMap::default()),
@@ -271,6 +417,8 @@ impl<B: GfxBackend> Device<B> {
             private_features,
             limits: desc.limits.clone(),
             features: desc.features.clone(),
+            max_submissions_in_flight: desc.max_submissions_in_flight,
+            resource_destroyed_callback: None,
             pending_writes: queue::PendingWrites::new(),
         })
     }
@@ -279,6 +427,20 @@ impl<B: GfxBackend> Device<B> {
         self.life_guard.submission_index.load(Ordering::Acquire)
     }
 
+    /// Returns the label to keep with a new resource, for reporting its destruction.
+    ///
+    /// Labels are only needed by the destruction callback and the debug logs,
+    /// so they aren't stored otherwise.
+    pub(crate) fn resource_label<'a>(&self, label: Option<&'a str>) -> &'a str {
+        if self.resource_destroyed_callback.is_some()
+            || tracing::level_enabled!(tracing::Level::DEBUG)
+        {
+            label.unwrap_or_default()
+        } else {
+            ""
+        }
+    }
+
     fn lock_life_internal<'this, 'token: 'this>(
         tracker: &'this Mutex<life::LifetimeTracker<B>>,
         _token: &mut Token<'token, Self>,
@@ -298,7 +460,7 @@ impl<B: GfxBackend> Device<B> {
         hub: &Hub<B, G>,
         force_wait: bool,
         token: &mut Token<'token, Self>,
-    ) -> Result<Vec<BufferMapPendingCallback>, WaitIdleError> {
+    ) -> Result<UserClosures, WaitIdleError> {
         let mut life_tracker = self.lock_life(token);
 
         life_tracker.triage_suspected(
@@ -311,14 +473,28 @@ impl<B: GfxBackend> Device<B> {
         life_tracker.triage_mapped(hub, token);
         life_tracker.triage_framebuffers(hub, &mut *self.framebuffers.lock(), token);
         let last_done = life_tracker.triage_submissions(&self.raw, force_wait)?;
-        let callbacks = life_tracker.handle_mapping(hub, &self.raw, &self.trackers, token);
+        let mut closures = UserClosures {
+            mappings: life_tracker.handle_mapping(hub, &self.raw, &self.trackers, token),
+            submissions: life_tracker.take_work_done_closures(),
+            readbacks: life_tracker.handle_readbacks(&self.raw),
+            destructions: Vec::new(),
+        };
         life_tracker.cleanup(&self.raw, &self.mem_allocator, &self.desc_allocator);
+        let destroyed = life_tracker.take_destroyed_resources();
+        if let Some(ref callback) = self.resource_destroyed_callback {
+            if !destroyed.is_empty() {
+                closures.destructions.push(ResourceDestructions {
+                    callback: callback.clone(),
+                    resources: destroyed,
+                });
+            }
+        }
 
         self.life_guard
             .submission_index
             .store(last_done, Ordering::Release);
         self.cmd_allocator.maintain(&self.raw, last_done);
-        Ok(callbacks)
+        Ok(closures)
     }
 
     fn untrack<'this, 'token: 'this, G: GlobalIdentityHandlerFactory>(
@@ -398,17 +574,24 @@ impl<B: GfxBackend> Device<B> {
             use gfx_memory::MemoryUsage;
             use wgt::BufferUsage as Bu;
 
+            let is_native_only = self
+                .features
+                .contains(wgt::Features::MAPPABLE_PRIMARY_BUFFERS);
+            let host_access =
+                desc.mapped_at_creation || desc.usage.intersects(Bu::MAP_READ | Bu::MAP_WRITE);
             //TODO: use linear allocation when we can ensure the freeing is linear
-            if !desc.usage.intersects(Bu::MAP_READ | Bu::MAP_WRITE) {
+            if is_native_only && self.private_features.unified_memory && host_access {
+                // Device-local memory is host-visible, so there is no need for staging.
+                MemoryUsage::Dynamic {
+                    sparse_updates: false,
+                }
+            } else if !desc.usage.intersects(Bu::MAP_READ | Bu::MAP_WRITE) {
                 MemoryUsage::Private
             } else if (Bu::MAP_WRITE | Bu::COPY_SRC).contains(desc.usage) {
                 MemoryUsage::Staging { read_back: false }
             } else if (Bu::MAP_READ | Bu::COPY_DST).contains(desc.usage) {
                 MemoryUsage::Staging { read_back: true }
             } else {
-                let is_native_only = self
-                    .features
-                    .contains(wgt::Features::MAPPABLE_PRIMARY_BUFFERS);
                 if !is_native_only {
                     return Err(resource::CreateBufferError::UsageMismatch(desc.usage));
                 }
@@ -452,7 +635,7 @@ impl<B: GfxBackend> Device<B> {
             full_range: (),
             sync_mapped_writes: None,
             map_state: resource::BufferMapState::Idle,
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new(self.resource_label(desc.label.as_deref())),
         })
     }
 
@@ -463,20 +646,12 @@ impl<B: GfxBackend> Device<B> {
     ) -> Result<resource::Texture<B>, resource::CreateTextureError> {
         debug_assert_eq!(self_id.backend(), B::VARIANT);
 
-        // Ensure `D24Plus` textures cannot be copied
-        match desc.format {
-            TextureFormat::Depth24Plus | TextureFormat::Depth24PlusStencil8 => {
-                if desc
-                    .usage
-                    .intersects(wgt::TextureUsage::COPY_SRC | wgt::TextureUsage::COPY_DST)
-                {
-                    return Err(resource::CreateTextureError::CannotCopyD24Plus);
-                }
-            }
-            _ => {}
-        }
-
-        let kind = conv::map_texture_dimension_size(desc.dimension, desc.size, desc.sample_count)?;
+        let kind = conv::map_texture_dimension_size(
+            desc.dimension,
+            desc.size,
+            desc.sample_count,
+            &self.limits,
+        )?;
         let format = conv::map_texture_format(desc.format, self.private_features);
         let aspects = format.surface_desc().aspects;
         let usage = conv::map_texture_usage(desc.usage, aspects);
@@ -551,7 +726,7 @@ impl<B: GfxBackend> Device<B> {
                 layers: 0..kind.num_layers(),
             },
             memory,
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new(self.resource_label(desc.label.as_deref())),
         })
     }
 
@@ -691,6 +866,7 @@ impl<B: GfxBackend> Device<B> {
                 .count(),
             count_validator,
             entries: entry_map,
+            label: self.resource_label(label).to_string(),
         })
     }
 
@@ -792,7 +968,7 @@ impl<B: GfxBackend> Device<B> {
                 value: id::Valid(self_id),
                 ref_count: self.life_guard.add_ref(),
             },
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new(self.resource_label(desc.label.as_deref())),
             bind_group_layout_ids: desc
                 .bind_group_layouts
                 .iter()
@@ -842,6 +1018,30 @@ impl<B: hal::Backend> Device<B> {
         }
     }
 
+    fn create_raw_shader_module(&self, spv: &[u32]) -> Result<B::ShaderModule, DeviceError> {
+        unsafe {
+            self.raw.create_shader_module(spv).map_err(|err| match err {
+                hal::device::ShaderError::OutOfMemory(_) => DeviceError::OutOfMemory,
+                _ => panic!("failed to create shader module: {}", err),
+            })
+        }
+    }
+
+    fn specialize_stage(
+        &self,
+        specialization: pipeline::StageSpecialization,
+    ) -> Result<SpecializedStage<'_, B>, DeviceError> {
+        let raw = match specialization.spv {
+            Some(ref spv) => Some(self.create_raw_shader_module(spv)?),
+            None => None,
+        };
+        Ok(SpecializedStage {
+            device: &self.raw,
+            raw,
+            specialization: specialization.specialization,
+        })
+    }
+
     /// Wait for idle and remove resources that we can, before we die.
     pub(crate) fn prepare_to_die(&mut self) {
         let mut life_tracker = self.life_tracker.lock();
@@ -851,9 +1051,17 @@ impl<B: hal::Backend> Device<B> {
         life_tracker.cleanup(&self.raw, &self.mem_allocator, &self.desc_allocator);
     }
 
-    pub(crate) fn dispose(self) {
+    /// Destroys the device, returning the callbacks of the readbacks
+    /// that can't be completed anymore, to be fired once the locks are released.
+    pub(crate) fn dispose(mut self) -> Vec<queue::QueueReadPendingCallback> {
         let mut desc_alloc = self.desc_allocator.into_inner();
         let mut mem_alloc = self.mem_allocator.into_inner();
+        let mut readbacks = self.pending_writes.cancel_readbacks();
+        let chunks = self
+            .life_tracker
+            .into_inner()
+            .into_staging_chunks(&self.raw, &mut readbacks);
+        self.pending_writes.staging.reclaim(chunks);
         self.pending_writes
             .dispose(&self.raw, &self.cmd_allocator, &mut mem_alloc);
         self.cmd_allocator.destroy(&self.raw);
@@ -867,6 +1075,7 @@ impl<B: hal::Backend> Device<B> {
                 self.raw.destroy_framebuffer(fbo);
             }
         }
+        readbacks
     }
 }
 
@@ -971,10 +1180,17 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let buffer_use = if !desc.mapped_at_creation {
             resource::BufferUse::EMPTY
-        } else if desc.usage.contains(wgt::BufferUsage::MAP_WRITE) {
-            // buffer is mappable, so we are just doing that at start
+        } else if desc.usage.contains(wgt::BufferUsage::MAP_WRITE)
+            || (device.private_features.unified_memory
+                && buffer
+                    .memory
+                    .properties()
+                    .contains(hal::memory::Properties::CPU_VISIBLE))
+        {
+            // buffer is mappable, or lives in host-visible memory,
+            // so we are just doing that at start
             let ptr = map_buffer(
-                &device.raw,
+                &*device.raw,
                 &mut buffer,
                 hal::buffer::SubRange::WHOLE,
                 HostMap::Write,
@@ -984,6 +1200,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 ptr,
                 sub_range: hal::buffer::SubRange::WHOLE,
                 host: HostMap::Write,
+                mapped_ranges: Vec::new(),
             };
 
             resource::BufferUse::MAP_WRITE
@@ -1006,6 +1223,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 .ptr();
             buffer.map_state = resource::BufferMapState::Init {
                 ptr,
+                mapped_ranges: Vec::new(),
                 stage_buffer: stage.raw,
                 stage_memory: stage.memory,
             };
@@ -1098,7 +1316,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         };
 
         let ptr = map_buffer(
-            &device.raw,
+            &*device.raw,
             &mut buffer,
             hal::buffer::SubRange {
                 offset,
@@ -1111,7 +1329,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             ptr::copy_nonoverlapping(data.as_ptr(), ptr.as_ptr(), data.len());
         }
 
-        unmap_buffer(&device.raw, buffer)?;
+        unmap_buffer(&*device.raw, buffer)?;
 
         Ok(())
     }
@@ -1140,7 +1358,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         //assert!(buffer isn't used by the GPU);
 
         let ptr = map_buffer(
-            &device.raw,
+            &*device.raw,
             &mut buffer,
             hal::buffer::SubRange {
                 offset,
@@ -1153,7 +1371,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             ptr::copy_nonoverlapping(ptr.as_ptr(), data.as_mut_ptr(), data.len());
         }
 
-        unmap_buffer(&device.raw, buffer)?;
+        unmap_buffer(&*device.raw, buffer)?;
 
         Ok(())
     }
@@ -1342,11 +1560,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             });
         };
 
-        let aspects = match desc.aspect {
-            wgt::TextureAspect::All => texture.aspects,
-            wgt::TextureAspect::DepthOnly => hal::format::Aspects::DEPTH,
-            wgt::TextureAspect::StencilOnly => hal::format::Aspects::STENCIL,
-        };
+        let aspects = conv::map_texture_aspect(desc.aspect, texture.aspects);
         if !texture.aspects.contains(aspects) {
             return Err(resource::CreateTextureViewError::InvalidAspect {
                 requested: aspects,
@@ -1400,7 +1614,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             extent: texture.kind.extent().at_level(desc.base_mip_level as _),
             samples: texture.kind.num_samples(),
             selector,
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new(device.resource_label(desc.label.as_deref())),
         };
         let ref_count = view.life_guard.add_ref();
 
@@ -1538,7 +1752,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 value: id::Valid(device_id),
                 ref_count: device.life_guard.add_ref(),
             },
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new(device.resource_label(desc.label.as_deref())),
             comparison: info.comparison.is_some(),
         };
         let ref_count = sampler.life_guard.add_ref();
@@ -1970,8 +2184,16 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             }
                         };
 
-                        if bb.offset % wgt::BIND_BUFFER_ALIGNMENT != 0 {
-                            return Err(CreateBindGroupError::UnalignedBufferOffset(bb.offset));
+                        let alignment = if pub_usage == wgt::BufferUsage::UNIFORM {
+                            device.limits.min_uniform_buffer_offset_alignment
+                        } else {
+                            device.limits.min_storage_buffer_offset_alignment
+                        };
+                        if bb.offset % alignment as BufferAddress != 0 {
+                            return Err(CreateBindGroupError::UnalignedBufferOffset {
+                                offset: bb.offset,
+                                alignment,
+                            });
                         }
 
                         let buffer = used
@@ -1998,11 +2220,17 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         {
                             return Err(CreateBindGroupError::UniformBufferRangeTooLarge);
                         }
+                        if pub_usage == wgt::BufferUsage::STORAGE
+                            && (device.limits.max_storage_buffer_binding_size as u64) < bind_size
+                        {
+                            return Err(CreateBindGroupError::StorageBufferRangeTooLarge);
+                        }
 
                         // Record binding info for validating dynamic offsets
                         if dynamic {
                             dynamic_binding_info.push(binding_model::BindGroupDynamicBindingData {
                                 maximum_dynamic_offset: buffer.size - bind_end,
+                                alignment,
                             });
                         }
 
@@ -2189,7 +2417,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 ref_count: device.life_guard.add_ref(),
             },
             layout_id: id::Valid(desc.layout),
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new(device.resource_label(desc.label.as_deref())),
             used,
             dynamic_binding_info,
         };
@@ -2272,71 +2500,100 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let device = device_guard
             .get(device_id)
             .map_err(|_| DeviceError::Invalid)?;
-        let spv_flags = if cfg!(debug_assertions) {
-            naga::back::spv::WriterFlags::DEBUG
-        } else {
-            naga::back::spv::WriterFlags::empty()
-        };
+        let validate = device.private_features.shader_validation != wgt::ShaderValidation::Off;
 
-        let (spv, naga) = match source {
+        // Record the original source, so that replaying goes through the same path.
+        #[cfg(feature = "trace")]
+        let trace_data = device.trace.as_ref().map(|trace| {
+            let mut trace = trace.lock();
+            match source {
+                pipeline::ShaderModuleSource::SpirV(ref spv) => {
+                    let data = trace.make_binary("spv", unsafe {
+                        std::slice::from_raw_parts(spv.as_ptr() as *const u8, spv.len() * 4)
+                    });
+                    (data, None)
+                }
+                pipeline::ShaderModuleSource::Wgsl(ref code) => {
+                    (trace.make_binary("wgsl", code.as_bytes()), None)
+                }
+                pipeline::ShaderModuleSource::Glsl {
+                    ref source,
+                    stage,
+                    ref defines,
+                } => {
+                    let options = trace::GlslOptions {
+                        stage,
+                        defines: defines.clone(),
+                    };
+                    (trace.make_binary("glsl", source.as_bytes()), Some(options))
+                }
+                pipeline::ShaderModuleSource::Naga(ref module) => (trace.make_ron(module), None),
+            }
+        });
+
+        let (spv, naga, generated) = match source {
             pipeline::ShaderModuleSource::SpirV(spv) => {
-                let module = if device.private_features.shader_validation {
-                    // Parse the given shader code and store its representation.
-                    let spv_iter = spv.into_iter().cloned();
-                    naga::front::spv::Parser::new(spv_iter)
-                        .parse()
-                        .map_err(|err| {
-                            // TODO: eventually, when Naga gets support for all features,
-                            // we want to convert these to a hard error,
-                            tracing::warn!("Failed to parse shader SPIR-V code: {:?}", err);
-                            tracing::warn!("Shader module will not be validated");
-                        })
-                        .ok()
-                } else {
-                    None
+                let module = match device.private_features.shader_validation {
+                    wgt::ShaderValidation::Off => None,
+                    level => {
+                        // Parse the given shader code and store its representation.
+                        let spv_iter = spv.iter().cloned();
+                        match naga::front::spv::Parser::new(spv_iter).parse() {
+                            Ok(module) => Some(module),
+                            Err(err) => {
+                                let err = pipeline::ShaderParseError::from_spirv(err);
+                                if level == wgt::ShaderValidation::Strict {
+                                    return Err(err.into());
+                                }
+                                tracing::warn!("{}", err);
+                                tracing::warn!("Shader module will not be validated");
+                                None
+                            }
+                        }
+                    }
                 };
-                (spv, module)
+                (spv, module, false)
             }
             pipeline::ShaderModuleSource::Wgsl(code) => {
-                // TODO: refactor the corresponding Naga error to be owned, and then
-                // display it instead of unwrapping
-                let module = naga::front::wgsl::parse_str(&code).unwrap();
-                let spv = naga::back::spv::Writer::new(&module.header, spv_flags).write(&module);
-                (
-                    Cow::Owned(spv),
-                    if device.private_features.shader_validation {
-                        Some(module)
-                    } else {
-                        None
-                    },
-                )
+                let module = naga::front::wgsl::parse_str(&code)
+                    .map_err(|err| pipeline::ShaderParseError::from_wgsl(err, &code))?;
+                let spv =
+                    naga::back::spv::Writer::new(&module.header, pipeline::spv_writer_flags())
+                        .write(&module);
+                (Cow::Owned(spv), Some(module), true)
+            }
+            pipeline::ShaderModuleSource::Glsl {
+                source,
+                stage,
+                defines,
+            } => {
+                let code = pipeline::preprocess_glsl(&source, &defines)?;
+                let module = naga::front::glsl_new::parse_str(&code, "main".to_string(), stage)
+                    .map_err(|err| {
+                        // The fields of naga's GLSL errors are private, so the location is
+                        // recovered from the `Debug` output of their `TokenMetadata`.
+                        pipeline::ShaderParseError::from_glsl(err.kind.to_string(), &code)
+                    })?;
+                let spv =
+                    naga::back::spv::Writer::new(&module.header, pipeline::spv_writer_flags())
+                        .write(&module);
+                (Cow::Owned(spv), Some(module), true)
             }
             pipeline::ShaderModuleSource::Naga(module) => {
-                let spv = naga::back::spv::Writer::new(&module.header, spv_flags).write(&module);
-                (
-                    Cow::Owned(spv),
-                    if device.private_features.shader_validation {
-                        Some(module)
-                    } else {
-                        None
-                    },
-                )
+                let spv =
+                    naga::back::spv::Writer::new(&module.header, pipeline::spv_writer_flags())
+                        .write(&module);
+                (Cow::Owned(spv), Some(module), true)
             }
         };
 
-        if let Some(ref module) = naga {
-            naga::proc::Validator::new().validate(module)?;
+        if validate {
+            if let Some(ref module) = naga {
+                naga::proc::Validator::new().validate(module)?;
+            }
         }
 
-        let raw = unsafe {
-            device
-                .raw
-                .create_shader_module(&spv)
-                .map_err(|err| match err {
-                    hal::device::ShaderError::OutOfMemory(_) => DeviceError::OutOfMemory,
-                    _ => panic!("failed to create shader module: {}", err),
-                })?
-        };
+        let raw = device.create_raw_shader_module(&spv)?;
         let shader = pipeline::ShaderModule {
             raw,
             device_id: Stored {
@@ -2344,22 +2601,23 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 ref_count: device.life_guard.add_ref(),
             },
             module: naga,
+            validate,
+            generated,
+            spirv_info: validation::scan_spirv(&spv),
         };
 
         let id = hub
             .shader_modules
             .register_identity(id_in, shader, &mut token);
         #[cfg(feature = "trace")]
-        match device.trace {
-            Some(ref trace) => {
-                let mut trace = trace.lock();
-                let data = trace.make_binary("spv", unsafe {
-                    std::slice::from_raw_parts(spv.as_ptr() as *const u8, spv.len() * 4)
-                });
-                trace.add(trace::Action::CreateShaderModule { id: id.0, data });
-            }
-            None => {}
-        };
+        if let Some((data, glsl)) = trace_data {
+            let action = trace::Action::CreateShaderModule {
+                id: id.0,
+                data,
+                glsl,
+            };
+            device.trace.as_ref().unwrap().lock().add(action);
+        }
         Ok(id.0)
     }
 
@@ -2373,6 +2631,25 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         hub.shader_modules.register_error(id_in, &mut token)
     }
 
+    pub fn shader_module_get_reflection<B: GfxBackend>(
+        &self,
+        shader_module_id: id::ShaderModuleId,
+    ) -> Result<pipeline::ShaderModuleReflection, pipeline::ShaderReflectionError> {
+        span!(_guard, INFO, "ShaderModule::get_reflection");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (_, mut token) = hub.devices.read(&mut token);
+        let (shader_module_guard, _) = hub.shader_modules.read(&mut token);
+        let shader_module = shader_module_guard
+            .get(shader_module_id)
+            .map_err(|_| pipeline::ShaderReflectionError::InvalidModule)?;
+        match shader_module.module {
+            Some(ref module) => validation::reflect_module(module, &shader_module.spirv_info),
+            None => Err(pipeline::ShaderReflectionError::NotParsed),
+        }
+    }
+
     pub fn shader_module_drop<B: GfxBackend>(&self, shader_module_id: id::ShaderModuleId) {
         span!(_guard, INFO, "ShaderModule::drop");
 
@@ -2392,6 +2669,14 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         unsafe {
             device.raw.destroy_shader_module(module.raw);
         }
+        tracing::debug!("Shader module {:?} is destroyed", shader_module_id);
+
+        // Shader modules are not used by the GPU, so they are destroyed right away.
+        let callback = device.resource_destroyed_callback.clone();
+        drop(device_guard);
+        if let Some(callback) = callback {
+            (callback.0)(DestroyedResource::ShaderModule(shader_module_id), "");
+        }
     }
 
     pub fn device_create_command_encoder<B: GfxBackend>(
@@ -2423,15 +2708,17 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             #[cfg(feature = "trace")]
             device.trace.is_some(),
         )?;
+        command_buffer.reusable = desc.reusable;
 
         unsafe {
+            let begin_flags = command_buffer.begin_flags();
             let raw_command_buffer = command_buffer.raw.last_mut().unwrap();
             if let Some(ref label) = desc.label {
                 device
                     .raw
                     .set_command_buffer_name(raw_command_buffer, label);
             }
-            raw_command_buffer.begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
+            raw_command_buffer.begin_primary(begin_flags);
         }
 
         let id = hub
@@ -2472,7 +2759,11 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let device = &mut device_guard[cmdbuf.device_id.value];
         device.untrack::<G>(&hub, &cmdbuf.trackers, &mut token);
-        device.cmd_allocator.discard(cmdbuf);
+        match cmdbuf.submission_index {
+            // A reusable command buffer may still be in flight.
+            Some(index) => device.cmd_allocator.after_submit(cmdbuf, index),
+            None => device.cmd_allocator.discard(cmdbuf),
+        }
     }
 
     pub fn command_buffer_drop<B: GfxBackend>(&self, command_buffer_id: id::CommandBufferId) {
@@ -2528,6 +2819,54 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             .push(id::Valid(render_bundle_id));
     }
 
+    pub fn device_create_compute_bundle_encoder(
+        &self,
+        device_id: id::DeviceId,
+        desc: &command::ComputeBundleEncoderDescriptor,
+    ) -> id::ComputeBundleEncoderId {
+        span!(_guard, INFO, "Device::create_compute_bundle_encoder");
+        let encoder = command::ComputeBundleEncoder::new(desc, device_id, None);
+        Box::into_raw(Box::new(encoder))
+    }
+
+    pub fn compute_bundle_error<B: GfxBackend>(
+        &self,
+        id_in: Input<G, id::ComputeBundleId>,
+    ) -> id::ComputeBundleId {
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (_, mut token) = hub.devices.read(&mut token);
+        hub.compute_bundles.register_error(id_in, &mut token)
+    }
+
+    pub fn compute_bundle_drop<B: GfxBackend>(&self, compute_bundle_id: id::ComputeBundleId) {
+        span!(_guard, INFO, "ComputeBundle::drop");
+        let hub = B::hub(self);
+        let mut token = Token::root();
+
+        let (device_guard, mut token) = hub.devices.read(&mut token);
+        let device_id = {
+            let (mut bundle_guard, _) = hub.compute_bundles.write(&mut token);
+            match bundle_guard.get_mut(compute_bundle_id) {
+                Ok(bundle) => {
+                    bundle.life_guard.ref_count.take();
+                    bundle.device_id.value
+                }
+                Err(InvalidId) => {
+                    hub.compute_bundles
+                        .unregister_locked(compute_bundle_id, &mut *bundle_guard);
+                    return;
+                }
+            }
+        };
+
+        device_guard[device_id]
+            .lock_life(&mut token)
+            .suspected_resources
+            .compute_bundles
+            .push(id::Valid(compute_bundle_id));
+    }
+
     pub fn device_create_render_pipeline<B: GfxBackend>(
         &self,
         device_id: id::DeviceId,
@@ -2564,7 +2903,18 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let mut interface = validation::StageInterface::default();
         let mut validated_stages = wgt::ShaderStage::empty();
 
+        let (device_guard, mut token) = hub.devices.read(&mut token);
+        let device = device_guard
+            .get(device_id)
+            .map_err(|_| DeviceError::Invalid)?;
+
         let desc_vbs = &desc.vertex_state.vertex_buffers;
+        if desc_vbs.len() > device.limits.max_vertex_buffers as usize {
+            return Err(pipeline::CreateRenderPipelineError::TooManyVertexBuffers {
+                given: desc_vbs.len() as u32,
+                limit: device.limits.max_vertex_buffers,
+            });
+        }
         let mut vertex_strides = Vec::with_capacity(desc_vbs.len());
         let mut vertex_buffers = Vec::with_capacity(desc_vbs.len());
         let mut attributes = Vec::new();
@@ -2581,6 +2931,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     stride: vb_state.stride,
                 });
             }
+            if vb_state.stride > device.limits.max_vertex_buffer_array_stride as BufferAddress {
+                return Err(pipeline::CreateRenderPipelineError::VertexStrideTooLarge {
+                    index: i as u32,
+                    given: vb_state.stride,
+                    limit: device.limits.max_vertex_buffer_array_stride,
+                });
+            }
             vertex_buffers.alloc().init(hal::pso::VertexBufferDesc {
                 binding: i as u32,
                 stride: vb_state.stride as u32,
@@ -2613,6 +2970,14 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 );
             }
         }
+        if attributes.len() > device.limits.max_vertex_attributes as usize {
+            return Err(
+                pipeline::CreateRenderPipelineError::TooManyVertexAttributes {
+                    given: attributes.len() as u32,
+                    limit: device.limits.max_vertex_attributes,
+                },
+            );
+        }
 
         let input_assembler = hal::pso::InputAssemblerDesc {
             primitive: conv::map_primitive_topology(desc.primitive_topology),
@@ -2651,10 +3016,6 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             depth_bounds: None,
         };
 
-        let (device_guard, mut token) = hub.devices.read(&mut token);
-        let device = device_guard
-            .get(device_id)
-            .map_err(|_| DeviceError::Invalid)?;
         if rasterization_state.clamp_depth
             && !device.features.contains(wgt::Features::DEPTH_CLAMPING)
         {
@@ -2715,6 +3076,40 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 }),
             };
 
+            let vertex_stage = {
+                let flag = wgt::ShaderStage::VERTEX;
+                let specialization = shader_module_guard
+                    .get(desc.vertex_stage.module)
+                    .map_err(|_| pipeline::CreateRenderPipelineError::Stage {
+                        flag,
+                        error: validation::StageError::InvalidModule,
+                    })?
+                    .specialize(&desc.vertex_stage.constants)
+                    .map_err(|error| pipeline::CreateRenderPipelineError::Stage {
+                        flag,
+                        error: error.into(),
+                    })?;
+                device.specialize_stage(specialization)?
+            };
+            let fragment_stage = match desc.fragment_stage {
+                Some(ref stage) => {
+                    let flag = wgt::ShaderStage::FRAGMENT;
+                    let specialization = shader_module_guard
+                        .get(stage.module)
+                        .map_err(|_| pipeline::CreateRenderPipelineError::Stage {
+                            flag,
+                            error: validation::StageError::InvalidModule,
+                        })?
+                        .specialize(&stage.constants)
+                        .map_err(|error| pipeline::CreateRenderPipelineError::Stage {
+                            flag,
+                            error: error.into(),
+                        })?;
+                    Some(device.specialize_stage(specialization)?)
+                }
+                None => None,
+            };
+
             let vertex = {
                 let entry_point_name = &desc.vertex_stage.entry_point;
                 let flag = wgt::ShaderStage::VERTEX;
@@ -2727,7 +3122,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             error: validation::StageError::InvalidModule,
                         })?;
 
-                if let Some(ref module) = shader_module.module {
+                if let Some(module) = shader_module.validation_module() {
                     let group_layouts = match desc.layout {
                         Some(pipeline_layout_id) => Device::get_introspection_bind_group_layouts(
                             pipeline_layout_guard
@@ -2742,6 +3137,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
                     interface = validation::check_stage(
                         module,
+                        &shader_module.spirv_info,
                         group_layouts,
                         &entry_point_name,
                         naga::ShaderStage::Vertex,
@@ -2749,13 +3145,19 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     )
                     .map_err(|error| pipeline::CreateRenderPipelineError::Stage { flag, error })?;
                     validated_stages |= flag;
-                }
 
-                hal::pso::EntryPoint::<B> {
-                    entry: &entry_point_name, // TODO
-                    module: &shader_module.raw,
-                    specialization: hal::pso::Specialization::EMPTY,
+                    let components = validation::interface_component_count(&interface);
+                    if components > device.limits.max_inter_stage_shader_components {
+                        return Err(
+                            pipeline::CreateRenderPipelineError::TooManyInterStageComponents {
+                                given: components,
+                                limit: device.limits.max_inter_stage_shader_components,
+                            },
+                        );
+                    }
                 }
+
+                vertex_stage.entry_point(entry_point_name, shader_module)
             };
 
             let fragment = match &desc.fragment_stage {
@@ -2783,9 +3185,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     };
 
                     if validated_stages == wgt::ShaderStage::VERTEX {
-                        if let Some(ref module) = shader_module.module {
+                        if let Some(module) = shader_module.validation_module() {
                             interface = validation::check_stage(
                                 module,
+                                &shader_module.spirv_info,
                                 group_layouts,
                                 &entry_point_name,
                                 naga::ShaderStage::Fragment,
@@ -2798,11 +3201,9 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         }
                     }
 
-                    Some(hal::pso::EntryPoint::<B> {
-                        entry: &entry_point_name,
-                        module: &shader_module.raw,
-                        specialization: hal::pso::Specialization::EMPTY,
-                    })
+                    fragment_stage
+                        .as_ref()
+                        .map(|specialized| specialized.entry_point(entry_point_name, shader_module))
                 }
                 None => None,
             };
@@ -2949,7 +3350,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             flags,
             index_format: desc.vertex_state.index_format,
             vertex_strides,
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new(device.resource_label(desc.label.as_deref())),
         };
 
         let id = hub
@@ -3070,6 +3471,17 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             let pipeline_stage = &desc.compute_stage;
             let (shader_module_guard, _) = hub.shader_modules.read(&mut token);
 
+            let specialization = shader_module_guard
+                .get(pipeline_stage.module)
+                .map_err(|_| {
+                    pipeline::CreateComputePipelineError::Stage(
+                        validation::StageError::InvalidModule,
+                    )
+                })?
+                .specialize(&pipeline_stage.constants)
+                .map_err(|error| pipeline::CreateComputePipelineError::Stage(error.into()))?;
+            let specialized_stage = device.specialize_stage(specialization)?;
+
             let entry_point_name = &pipeline_stage.entry_point;
             let shader_module = shader_module_guard
                 .get(pipeline_stage.module)
@@ -3079,7 +3491,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     )
                 })?;
 
-            if let Some(ref module) = shader_module.module {
+            if let Some(module) = shader_module.validation_module() {
                 let group_layouts = match desc.layout {
                     Some(pipeline_layout_id) => Device::get_introspection_bind_group_layouts(
                         pipeline_layout_guard
@@ -3098,6 +3510,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 };
                 let _ = validation::check_stage(
                     module,
+                    &shader_module.spirv_info,
                     group_layouts,
                     &entry_point_name,
                     naga::ShaderStage::Compute,
@@ -3110,11 +3523,16 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 ))?
             }
 
-            let shader = hal::pso::EntryPoint::<B> {
-                entry: &entry_point_name, // TODO
-                module: &shader_module.raw,
-                specialization: hal::pso::Specialization::EMPTY,
-            };
+            validation::check_workgroup_limits(
+                shader_module.validation_module(),
+                &shader_module.spirv_info,
+                entry_point_name,
+                &pipeline_stage.constants,
+                &device.limits,
+            )
+            .map_err(pipeline::CreateComputePipelineError::Stage)?;
+
+            let shader = specialized_stage.entry_point(entry_point_name, shader_module);
 
             // TODO
             let flags = hal::pso::PipelineCreationFlags::empty();
@@ -3173,7 +3591,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 value: id::Valid(device_id),
                 ref_count: device.life_guard.add_ref(),
             },
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new(device.resource_label(desc.label.as_deref())),
         };
         let id = hub
             .compute_pipelines
@@ -3378,7 +3796,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         };
 
         let swap_chain = swap_chain::SwapChain {
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new("<SwapChain>"),
             device_id: Stored {
                 value: id::Valid(device_id),
                 ref_count: device.life_guard.add_ref(),
@@ -3418,30 +3836,81 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         Ok(())
     }
 
+    /// Sets the callback invoked with each resource physically destroyed by the device.
+    ///
+    /// Labels are only kept while a callback is set or debug logs are enabled,
+    /// so resources created before are reported with an empty label.
+    /// Shader modules have no label.
+    pub fn device_set_resource_destroyed_callback<B: GfxBackend>(
+        &self,
+        device_id: id::DeviceId,
+        callback: Option<ResourceDestroyedClosure>,
+    ) -> Result<(), InvalidDevice> {
+        span!(_guard, INFO, "Device::set_resource_destroyed_callback");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (mut device_guard, _) = hub.devices.write(&mut token);
+        let device = device_guard.get_mut(device_id).map_err(|_| InvalidDevice)?;
+        device.resource_destroyed_callback = callback;
+        Ok(())
+    }
+
     pub fn device_poll<B: GfxBackend>(
         &self,
         device_id: id::DeviceId,
-        force_wait: bool,
+        maintain: Maintain,
     ) -> Result<(), WaitIdleError> {
         span!(_guard, INFO, "Device::poll");
 
         let hub = B::hub(self);
         let mut token = Token::root();
-        let callbacks = {
+        let force_wait = match maintain {
+            Maintain::Poll => false,
+            Maintain::Wait => true,
+            Maintain::WaitForSubmission {
+                submission,
+                timeout,
+            } => {
+                if submission.queue_id != device_id {
+                    return Err(WaitIdleError::WrongSubmission {
+                        submission_queue: submission.queue_id,
+                        device: device_id,
+                    });
+                }
+                let (raw, fence) = {
+                    let (device_guard, mut token) = hub.devices.read(&mut token);
+                    let device = device_guard
+                        .get(device_id)
+                        .map_err(|_| DeviceError::Invalid)?;
+                    let fence = device
+                        .lock_life(&mut token)
+                        .submission_fence(submission.index);
+                    (Arc::clone(&device.raw), fence)
+                };
+                // Wait without holding the locks, so that other threads can keep
+                // using the device, and triage once the submission is done.
+                if let Some(fence) = fence {
+                    life::wait_for_fence::<B>(&raw, &fence, timeout)?;
+                }
+                false
+            }
+        };
+        let closures = {
             let (device_guard, mut token) = hub.devices.read(&mut token);
-            device_guard
+            let device = device_guard
                 .get(device_id)
-                .map_err(|_| DeviceError::Invalid)?
-                .maintain(&hub, force_wait, &mut token)?
+                .map_err(|_| DeviceError::Invalid)?;
+            device.maintain(&hub, force_wait, &mut token)?
         };
-        fire_map_callbacks(callbacks);
+        closures.fire();
         Ok(())
     }
 
     fn poll_devices<B: GfxBackend>(
         &self,
         force_wait: bool,
-        callbacks: &mut Vec<BufferMapPendingCallback>,
+        closures: &mut UserClosures,
     ) -> Result<(), WaitIdleError> {
         span!(_guard, INFO, "Device::poll_devices");
 
@@ -3450,31 +3919,31 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let (device_guard, mut token) = hub.devices.read(&mut token);
         for (_, device) in device_guard.iter(B::VARIANT) {
             let cbs = device.maintain(&hub, force_wait, &mut token)?;
-            callbacks.extend(cbs);
+            closures.extend(cbs);
         }
         Ok(())
     }
 
     pub fn poll_all_devices(&self, force_wait: bool) -> Result<(), WaitIdleError> {
         use crate::backend;
-        let mut callbacks = Vec::new();
+        let mut closures = UserClosures::default();
 
         backends! {
             #[vulkan] {
-                self.poll_devices::<backend::Vulkan>(force_wait, &mut callbacks)?;
+                self.poll_devices::<backend::Vulkan>(force_wait, &mut closures)?;
             }
             #[metal] {
-                self.poll_devices::<backend::Metal>(force_wait, &mut callbacks)?;
+                self.poll_devices::<backend::Metal>(force_wait, &mut closures)?;
             }
             #[dx12] {
-                self.poll_devices::<backend::Dx12>(force_wait, &mut callbacks)?;
+                self.poll_devices::<backend::Dx12>(force_wait, &mut closures)?;
             }
             #[dx11] {
-                self.poll_devices::<backend::Dx11>(force_wait, &mut callbacks)?;
+                self.poll_devices::<backend::Dx11>(force_wait, &mut closures)?;
             }
         }
 
-        fire_map_callbacks(callbacks);
+        closures.fire();
 
         Ok(())
     }
@@ -3504,7 +3973,12 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 .unregister(device.adapter_id.value.0, &mut token);
         }
 
-        device.dispose();
+        let readbacks = device.dispose();
+        UserClosures {
+            readbacks,
+            ..UserClosures::default()
+        }
+        .fire();
     }
 
     pub fn buffer_map_async<B: GfxBackend>(
@@ -3537,11 +4011,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
             check_buffer_usage(buffer.usage, pub_usage)?;
             buffer.map_state = match buffer.map_state {
-                resource::BufferMapState::Init { .. } | resource::BufferMapState::Active { .. } => {
+                resource::BufferMapState::Init { .. }
+                | resource::BufferMapState::Active { .. }
+                | resource::BufferMapState::Persistent { .. } => {
                     return Err(resource::BufferAccessError::AlreadyMapped);
                 }
                 resource::BufferMapState::Waiting(_) => {
-                    op.call_error();
+                    op.call_error(resource::BufferAccessError::MapAlreadyPending);
                     return Ok(());
                 }
                 resource::BufferMapState::Idle => {
@@ -3575,30 +4051,215 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         Ok(())
     }
 
+    /// Obtain a pointer to a sub-range of a mapped buffer.
+    ///
+    /// Several ranges can be obtained from the same mapping as long as they don't overlap.
+    /// All of them become invalid once the buffer is unmapped.
+    /// Persistently mapped buffers don't track the obtained ranges.
     pub fn buffer_get_mapped_range<B: GfxBackend>(
         &self,
         buffer_id: id::BufferId,
         offset: BufferAddress,
-        _size: Option<BufferSize>,
+        size: Option<BufferSize>,
     ) -> Result<*mut u8, resource::BufferAccessError> {
         span!(_guard, INFO, "Device::buffer_get_mapped_range");
 
         let hub = B::hub(self);
         let mut token = Token::root();
+        let (mut buffer_guard, _) = hub.buffers.write(&mut token);
+        let buffer = buffer_guard
+            .get_mut(buffer_id)
+            .map_err(|_| resource::BufferAccessError::InvalidBuffer)?;
+
+        let (ptr, mapped, mapped_ranges) = match buffer.map_state {
+            resource::BufferMapState::Init {
+                ptr,
+                ref mut mapped_ranges,
+                ..
+            } => (ptr, 0..buffer.size, Some(mapped_ranges)),
+            resource::BufferMapState::Persistent { ptr, .. } => (ptr, 0..buffer.size, None),
+            resource::BufferMapState::Active {
+                ptr,
+                ref sub_range,
+                ref mut mapped_ranges,
+                ..
+            } => (
+                ptr,
+                sub_range.offset..sub_range.offset + sub_range.size_to(buffer.size),
+                Some(mapped_ranges),
+            ),
+            resource::BufferMapState::Idle | resource::BufferMapState::Waiting(_) => {
+                return Err(resource::BufferAccessError::NotMapped);
+            }
+        };
+
+        if offset % wgt::MAP_ALIGNMENT != 0 {
+            return Err(resource::BufferAccessError::UnalignedOffset(offset));
+        }
+        let end = match size {
+            Some(size) => offset.saturating_add(size.get()),
+            None => mapped.end.max(offset),
+        };
+        if (end - offset) % wgt::COPY_BUFFER_ALIGNMENT != 0 {
+            return Err(resource::BufferAccessError::UnalignedRange);
+        }
+        let requested = offset..end;
+        if requested.start < mapped.start || requested.end > mapped.end {
+            return Err(resource::BufferAccessError::OutOfBoundsRange { requested, mapped });
+        }
+        if let Some(mapped_ranges) = mapped_ranges {
+            if let Some(existing) = mapped_ranges
+                .iter()
+                .find(|r| r.start < requested.end && requested.start < r.end)
+            {
+                return Err(resource::BufferAccessError::OverlappingRange {
+                    requested,
+                    existing: existing.clone(),
+                });
+            }
+            mapped_ranges.push(requested);
+        }
+
+        Ok(unsafe { ptr.as_ptr().offset((offset - mapped.start) as isize) })
+    }
+
+    /// Maps the whole buffer until it's unmapped, while still allowing the GPU to use it.
+    ///
+    /// Requires unified memory and `Features::MAPPABLE_PRIMARY_BUFFERS`. The host
+    /// accesses have to be synchronized with the GPU by the user, and made visible
+    /// with `buffer_flush_mapped_range`.
+    pub fn buffer_map_persistently<B: GfxBackend>(
+        &self,
+        buffer_id: id::BufferId,
+        host: HostMap,
+    ) -> Result<*mut u8, resource::BufferAccessError> {
+        span!(_guard, INFO, "Device::buffer_map_persistently");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, mut token) = hub.devices.read(&mut token);
+        let (mut buffer_guard, _) = hub.buffers.write(&mut token);
+        let buffer = buffer_guard
+            .get_mut(buffer_id)
+            .map_err(|_| resource::BufferAccessError::InvalidBuffer)?;
+        let device = &device_guard[buffer.device_id.value];
+
+        if !device.private_features.unified_memory
+            || !device
+                .features
+                .contains(wgt::Features::MAPPABLE_PRIMARY_BUFFERS)
+        {
+            return Err(resource::BufferAccessError::PersistentMappingUnsupported);
+        }
+        let pub_usage = match host {
+            HostMap::Read => wgt::BufferUsage::MAP_READ,
+            HostMap::Write => wgt::BufferUsage::MAP_WRITE,
+        };
+        check_buffer_usage(buffer.usage, pub_usage)?;
+        match buffer.map_state {
+            resource::BufferMapState::Idle => {}
+            resource::BufferMapState::Waiting(_) => {
+                return Err(resource::BufferAccessError::MapAlreadyPending)
+            }
+            _ => return Err(resource::BufferAccessError::AlreadyMapped),
+        }
+
+        let ptr = map_buffer(&*device.raw, buffer, hal::buffer::SubRange::WHOLE, host)?;
+        tracing::debug!("Buffer {:?} map state -> Persistent", buffer_id);
+        buffer.map_state = resource::BufferMapState::Persistent { ptr, host };
+        Ok(ptr.as_ptr())
+    }
+
+    /// Makes the host writes to a range of a persistently mapped buffer visible
+    /// to the GPU, or the GPU writes visible to the host for read mappings.
+    pub fn buffer_flush_mapped_range<B: GfxBackend>(
+        &self,
+        buffer_id: id::BufferId,
+        range: Range<BufferAddress>,
+    ) -> Result<(), resource::BufferAccessError> {
+        span!(_guard, INFO, "Device::buffer_flush_mapped_range");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, mut token) = hub.devices.read(&mut token);
         let (buffer_guard, _) = hub.buffers.read(&mut token);
         let buffer = buffer_guard
             .get(buffer_id)
             .map_err(|_| resource::BufferAccessError::InvalidBuffer)?;
+        let device = &device_guard[buffer.device_id.value];
 
-        match buffer.map_state {
-            resource::BufferMapState::Init { ptr, .. }
-            | resource::BufferMapState::Active { ptr, .. } => unsafe {
-                Ok(ptr.as_ptr().offset(offset as isize))
-            },
-            resource::BufferMapState::Idle | resource::BufferMapState::Waiting(_) => {
-                Err(resource::BufferAccessError::NotMapped)
+        let (ptr, host) = match buffer.map_state {
+            resource::BufferMapState::Persistent { ptr, host } => (ptr, host),
+            _ => return Err(resource::BufferAccessError::NotMapped),
+        };
+        if range.start % wgt::COPY_BUFFER_ALIGNMENT != 0
+            || range.end % wgt::COPY_BUFFER_ALIGNMENT != 0
+        {
+            return Err(resource::BufferAccessError::UnalignedRange);
+        }
+        if range.start > range.end || range.end > buffer.size {
+            return Err(resource::BufferAccessError::OutOfBoundsRange {
+                requested: range,
+                mapped: 0..buffer.size,
+            });
+        }
+
+        if host == HostMap::Write {
+            #[cfg(feature = "trace")]
+            match device.trace {
+                Some(ref trace) => {
+                    let mut trace = trace.lock();
+                    let data = trace.make_binary("bin", unsafe {
+                        std::slice::from_raw_parts(
+                            ptr.as_ptr().offset(range.start as isize),
+                            (range.end - range.start) as usize,
+                        )
+                    });
+                    trace.add(trace::Action::WriteBuffer {
+                        id: buffer_id,
+                        data,
+                        range: range.clone(),
+                        queued: false,
+                    });
+                }
+                None => (),
+            };
+        }
+        let _ = ptr;
+
+        if buffer
+            .memory
+            .properties()
+            .contains(hal::memory::Properties::COHERENT)
+        {
+            return Ok(());
+        }
+        // Flushed ranges have to be aligned to the non-coherent atom size.
+        let atom = device.hal_limits.non_coherent_atom_size as BufferAddress;
+        let block = buffer.memory.segment();
+        let block_end = block.offset + block.size.unwrap_or(buffer.size);
+        let start = block.offset + range.start - (block.offset + range.start) % atom;
+        let end = match (block.offset + range.end) % atom {
+            0 => block.offset + range.end,
+            other => (block.offset + range.end - other + atom).min(block_end),
+        };
+        let segment = hal::memory::Segment {
+            offset: start,
+            size: Some(end - start),
+        };
+        let memory = buffer.memory.memory();
+        unsafe {
+            match host {
+                HostMap::Write => device
+                    .raw
+                    .flush_mapped_memory_ranges(iter::once((memory, segment))),
+                HostMap::Read => device
+                    .raw
+                    .invalidate_mapped_memory_ranges(iter::once((memory, segment))),
             }
         }
+        .or(Err(DeviceError::OutOfMemory))?;
+        Ok(())
     }
 
     pub fn buffer_unmap<B: GfxBackend>(
@@ -3623,6 +4284,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 ptr,
                 stage_buffer,
                 stage_memory,
+                ..
             } => {
                 #[cfg(feature = "trace")]
                 match device.trace {
@@ -3683,6 +4345,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 ptr,
                 sub_range,
                 host,
+                ..
             } => {
                 if host == HostMap::Write {
                     #[cfg(feature = "trace")]
@@ -3697,14 +4360,37 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                                 id: buffer_id,
                                 data,
                                 range: sub_range.offset..sub_range.offset + size,
-                                queued: false,
+                                // buffers mapped at creation in unified memory may not be mappable
+                                queued: !buffer.usage.contains(wgt::BufferUsage::MAP_WRITE),
                             });
                         }
                         None => (),
                     };
                     let _ = (ptr, sub_range);
                 }
-                unmap_buffer(&device.raw, buffer)?;
+                unmap_buffer(&*device.raw, buffer)?;
+            }
+            resource::BufferMapState::Persistent { ptr, host } => {
+                if host == HostMap::Write {
+                    #[cfg(feature = "trace")]
+                    match device.trace {
+                        Some(ref trace) => {
+                            let mut trace = trace.lock();
+                            let data = trace.make_binary("bin", unsafe {
+                                std::slice::from_raw_parts(ptr.as_ptr(), buffer.size as usize)
+                            });
+                            trace.add(trace::Action::WriteBuffer {
+                                id: buffer_id,
+                                data,
+                                range: 0..buffer.size,
+                                queued: false,
+                            });
+                        }
+                        None => (),
+                    };
+                    let _ = ptr;
+                }
+                unmap_buffer(&*device.raw, buffer)?;
             }
         }
         Ok(())
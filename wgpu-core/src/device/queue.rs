@@ -6,33 +6,428 @@
 use crate::device::trace::Action;
 use crate::{
     command::{
-        texture_copy_view_to_hal, validate_linear_texture_data, validate_texture_copy_range,
-        CommandAllocator, CommandBuffer, CopySide, TextureCopyView, TransferError, BITS_PER_BYTE,
+        aspect_bytes_per_block, texture_copy_view_to_hal, validate_linear_texture_data,
+        validate_texture_copy_range, CommandAllocator, CommandBuffer, CopySide, TextureCopyView,
+        TransferError,
     },
     conv,
-    device::{DeviceError, WaitIdleError},
-    hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Token},
+    device::{life::SuspectedResources, DeviceError, WaitIdleError},
+    hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Storage, Token},
     id,
-    resource::{BufferAccessError, BufferMapState, BufferUse, TextureUse},
+    resource::{Buffer, BufferAccessError, BufferMapState, BufferUse, Texture, TextureUse},
     span,
+    track::{TextureSelector, TrackerSet},
+    Stored, SubmissionIndex,
 };
 
 use gfx_memory::{Block, Heaps, MemoryBlock};
 use hal::{command::CommandBuffer as _, device::Device as _, queue::CommandQueue as _};
+use parking_lot::Mutex;
 use smallvec::SmallVec;
-use std::iter;
+use std::{fmt, iter, sync::Arc};
 use thiserror::Error;
 
-struct StagingData<B: hal::Backend> {
+pub type SubmittedWorkDoneCallback = unsafe extern "C" fn(user_data: *mut u8);
+
+/// Callback fired once all the work submitted before it was registered is done.
+#[derive(Debug)]
+pub struct SubmittedWorkDoneClosure {
+    pub callback: SubmittedWorkDoneCallback,
+    pub user_data: *mut u8,
+}
+
+unsafe impl Send for SubmittedWorkDoneClosure {}
+unsafe impl Sync for SubmittedWorkDoneClosure {}
+
+impl SubmittedWorkDoneClosure {
+    pub(crate) fn call(self) {
+        unsafe {
+            (self.callback)(self.user_data);
+        }
+    }
+}
+
+/// Callback receiving the tightly packed bytes of a `queue_read_*` operation.
+pub type QueueReadCallback = Box<dyn FnOnce(Result<Vec<u8>, DeviceError>) + Send>;
+
+pub(crate) type QueueReadPendingCallback = (QueueReadCallback, Result<Vec<u8>, DeviceError>);
+
+/// Minimal size of a chunk of the staging ring.
+const STAGING_CHUNK_SIZE: wgt::BufferAddress = 1 << 20;
+/// Maximum number of free chunks kept around by the staging ring.
+const MAX_FREE_STAGING_CHUNKS: usize = 4;
+
+/// Returns true if a chunk of `size` bytes that is no longer used should be
+/// kept for reuse, given the number of chunks that are already free.
+///
+/// Chunks created for oversized writes are released right away, so that
+/// a single large write doesn't hold on to its memory for the device lifetime.
+fn keep_free_staging_chunk(size: wgt::BufferAddress, free_count: usize) -> bool {
+    size <= STAGING_CHUNK_SIZE && free_count < MAX_FREE_STAGING_CHUNKS
+}
+
+fn align_offset(offset: wgt::BufferAddress, alignment: wgt::BufferAddress) -> wgt::BufferAddress {
+    match offset % alignment {
+        0 => offset,
+        other => offset - other + alignment,
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct StagingChunk<B: hal::Backend> {
     buffer: B::Buffer,
     memory: MemoryBlock<B>,
+    size: wgt::BufferAddress,
+    /// Offset of the first free byte in the chunk.
+    offset: wgt::BufferAddress,
+}
+
+impl<B: hal::Backend> StagingChunk<B> {
+    fn aligned_offset(&self, alignment: wgt::BufferAddress) -> wgt::BufferAddress {
+        align_offset(self.offset, alignment)
+    }
+
+    fn fits(&self, size: wgt::BufferAddress, alignment: wgt::BufferAddress) -> bool {
+        self.aligned_offset(alignment) + size <= self.size
+    }
+}
+
+/// Staging memory for `queue_write_*` operations.
+///
+/// Chunks are sub-allocated linearly, and recycled once the submission
+/// that used them is done, so that writes don't create buffers on their own.
+#[derive(Debug)]
+pub(crate) struct StagingRing<B: hal::Backend> {
+    /// Chunk that is currently being filled.
+    active: Option<StagingChunk<B>>,
+    /// Filled chunks, used by the pending writes.
+    closed: Vec<StagingChunk<B>>,
+    /// Chunks used by a submission that may not be done yet.
+    submitted: Vec<(StagingChunk<B>, SubmissionIndex)>,
+    free: Vec<StagingChunk<B>>,
+    /// Unused chunks that are not worth keeping, waiting to be destroyed.
+    retired: Vec<StagingChunk<B>>,
+}
+
+impl<B: hal::Backend> StagingRing<B> {
+    fn new() -> Self {
+        StagingRing {
+            active: None,
+            closed: Vec::new(),
+            submitted: Vec::new(),
+            free: Vec::new(),
+            retired: Vec::new(),
+        }
+    }
+
+    /// Puts an unused chunk into the free list, or retires it.
+    fn release(&mut self, mut chunk: StagingChunk<B>) {
+        chunk.offset = 0;
+        if keep_free_staging_chunk(chunk.size, self.free.len()) {
+            self.free.push(chunk);
+        } else {
+            self.retired.push(chunk);
+        }
+    }
+
+    /// Associates all the chunks used by the pending writes with a submission.
+    fn submit(&mut self, index: SubmissionIndex) {
+        for chunk in self.active.take().into_iter().chain(self.closed.drain(..)) {
+            self.submitted.push((chunk, index));
+        }
+    }
+
+    /// Makes the chunks of all the submissions before `first_pending_index` free.
+    fn recycle(&mut self, first_pending_index: SubmissionIndex) {
+        for i in (0..self.submitted.len()).rev() {
+            if self.submitted[i].1 < first_pending_index {
+                let (chunk, _) = self.submitted.swap_remove(i);
+                self.release(chunk);
+            }
+        }
+    }
+
+    /// Makes the chunks of the resolved readbacks free.
+    pub(crate) fn reclaim(&mut self, chunks: impl Iterator<Item = StagingChunk<B>>) {
+        for chunk in chunks {
+            self.release(chunk);
+        }
+    }
+
+    /// Returns the chunk of a stage that ended up not being used,
+    /// rolling back its allocation.
+    fn give_back(&mut self, mut chunk: StagingChunk<B>, offset: wgt::BufferAddress) {
+        chunk.offset = offset;
+        if self.active.is_none() {
+            self.active = Some(chunk);
+        } else if offset == 0 {
+            self.release(chunk);
+        } else {
+            self.closed.push(chunk);
+        }
+    }
+
+    /// Destroys the retired chunks.
+    fn trim(&mut self, device: &B::Device, mem_allocator: &mut Heaps<B>) {
+        for chunk in self.retired.drain(..) {
+            mem_allocator.free(device, chunk.memory);
+            unsafe {
+                device.destroy_buffer(chunk.buffer);
+            }
+        }
+    }
+
+    fn take_free(&mut self, size: wgt::BufferAddress) -> Option<StagingChunk<B>> {
+        let index = self.free.iter().position(|chunk| chunk.size >= size)?;
+        Some(self.free.swap_remove(index))
+    }
+
+    fn dispose(self, device: &B::Device, mem_allocator: &mut Heaps<B>) {
+        let chunks = self
+            .active
+            .into_iter()
+            .chain(self.closed)
+            .chain(self.submitted.into_iter().map(|(chunk, _)| chunk))
+            .chain(self.free)
+            .chain(self.retired);
+        for chunk in chunks {
+            mem_allocator.free(device, chunk.memory);
+            unsafe {
+                device.destroy_buffer(chunk.buffer);
+            }
+        }
+    }
+}
+
+struct StagingData<B: hal::Backend> {
+    chunk: StagingChunk<B>,
+    /// Offset of the staged data in the chunk.
+    offset: wgt::BufferAddress,
+    size: wgt::BufferAddress,
     cmdbuf: B::CommandBuffer,
 }
 
-#[derive(Debug, Default)]
+impl<B: hal::Backend> StagingData<B> {
+    fn range(&self) -> hal::buffer::SubRange {
+        hal::buffer::SubRange {
+            offset: self.offset,
+            size: Some(self.size),
+        }
+    }
+
+    fn write(
+        &mut self,
+        device: &B::Device,
+        fun: impl FnOnce(&mut [u8]),
+    ) -> Result<(), DeviceError> {
+        let segment = hal::memory::Segment {
+            offset: self.offset,
+            size: Some(self.size),
+        };
+        let mut mapped = self
+            .chunk
+            .memory
+            .map(device, segment)
+            .map_err(|err| match err {
+                hal::device::MapError::OutOfMemory(_) => DeviceError::OutOfMemory,
+                _ => panic!("failed to map staging buffer: {}", err),
+            })?;
+        let writer = unsafe { mapped.write(device, hal::memory::Segment::ALL) }
+            .expect("failed to get writer to mapped staging buffer");
+        fun(writer.slice);
+        Ok(())
+    }
+}
+
+/// Data copied into a staging chunk by a `queue_read_*` operation,
+/// waiting for its submission to be done.
+pub(crate) struct PendingReadback<B: hal::Backend> {
+    chunk: StagingChunk<B>,
+    /// Number of bytes between the starts of two rows in the chunk.
+    stage_bytes_per_row: wgt::BufferAddress,
+    /// Number of bytes of a row in the packed result.
+    packed_bytes_per_row: wgt::BufferAddress,
+    rows: u32,
+    callback: QueueReadCallback,
+}
+
+impl<B: hal::Backend> fmt::Debug for PendingReadback<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingReadback")
+            .field("chunk", &self.chunk)
+            .field("stage_bytes_per_row", &self.stage_bytes_per_row)
+            .field("packed_bytes_per_row", &self.packed_bytes_per_row)
+            .field("rows", &self.rows)
+            .finish()
+    }
+}
+
+impl<B: hal::Backend> PendingReadback<B> {
+    fn range(&self) -> hal::buffer::SubRange {
+        hal::buffer::SubRange {
+            offset: 0,
+            size: Some(self.stage_bytes_per_row * self.rows as wgt::BufferAddress),
+        }
+    }
+
+    /// Reads back the packed data, returning the chunk to be recycled.
+    pub(crate) fn resolve(
+        mut self,
+        device: &B::Device,
+    ) -> (StagingChunk<B>, QueueReadPendingCallback) {
+        let size = self.stage_bytes_per_row * self.rows as wgt::BufferAddress;
+        let segment = hal::memory::Segment {
+            offset: 0,
+            size: Some(size),
+        };
+        let result = match self.chunk.memory.map(device, segment) {
+            Ok(mut mapped) => {
+                let slice = unsafe { mapped.read::<u8>(device, hal::memory::Segment::ALL) }
+                    .expect("failed to get reader of mapped staging buffer");
+                Ok(pack_rows(
+                    slice,
+                    self.stage_bytes_per_row as usize,
+                    self.packed_bytes_per_row as usize,
+                ))
+            }
+            Err(hal::device::MapError::OutOfMemory(_)) => Err(DeviceError::OutOfMemory),
+            Err(err) => panic!("failed to map staging buffer: {}", err),
+        };
+        (self.chunk, (self.callback, result))
+    }
+
+    /// Fails the readback, returning the chunk to be recycled.
+    pub(crate) fn cancel(self) -> (StagingChunk<B>, QueueReadPendingCallback) {
+        (self.chunk, (self.callback, Err(DeviceError::Lost)))
+    }
+}
+
+/// Removes the padding at the end of each row of the staged data.
+fn pack_rows(slice: &[u8], stage_bytes_per_row: usize, packed_bytes_per_row: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(slice.len() / stage_bytes_per_row * packed_bytes_per_row);
+    for row in slice.chunks(stage_bytes_per_row) {
+        data.extend_from_slice(&row[..packed_bytes_per_row]);
+    }
+    data
+}
+
+#[derive(Debug)]
+enum ReadbackSource {
+    Buffer {
+        id: Stored<id::BufferId>,
+        region: hal::command::BufferCopy,
+    },
+    Texture {
+        id: Stored<id::TextureId>,
+        selector: TextureSelector,
+        region: hal::command::BufferImageCopy,
+    },
+}
+
+/// Readback requested by a `queue_read_*` operation.
+///
+/// The copy is only recorded when the next submission is made, after its
+/// command buffers, so that it sees their results.
+#[derive(Debug)]
+pub(crate) struct QueuedReadback<B: hal::Backend> {
+    source: ReadbackSource,
+    readback: PendingReadback<B>,
+}
+
+impl<B: hal::Backend> QueuedReadback<B> {
+    /// Records the copy into the staging chunk, transitioning the source
+    /// from the device state it's left in by the submitted command buffers.
+    fn encode(
+        &self,
+        raw: &mut B::CommandBuffer,
+        trackers: &mut TrackerSet,
+        buffer_guard: &Storage<Buffer<B>, id::BufferId>,
+        texture_guard: &Storage<Texture<B>, id::TextureId>,
+        submit_index: SubmissionIndex,
+        temp_suspected: &mut SuspectedResources,
+    ) {
+        let chunk_barrier = hal::memory::Barrier::Buffer {
+            states: hal::buffer::Access::empty()..hal::buffer::Access::TRANSFER_WRITE,
+            target: &self.readback.chunk.buffer,
+            range: self.readback.range(),
+            families: None,
+        };
+        match self.source {
+            ReadbackSource::Buffer { ref id, ref region } => {
+                let (src, transition) = trackers
+                    .buffers
+                    .use_replace(buffer_guard, id.value.0, (), BufferUse::COPY_SRC)
+                    .unwrap();
+                if !src.life_guard.use_at(submit_index) {
+                    temp_suspected.buffers.push(id.value);
+                }
+                unsafe {
+                    raw.pipeline_barrier(
+                        super::all_buffer_stages() | hal::pso::PipelineStage::HOST
+                            ..hal::pso::PipelineStage::TRANSFER,
+                        hal::memory::Dependencies::empty(),
+                        iter::once(chunk_barrier)
+                            .chain(transition.map(|pending| pending.into_hal(src))),
+                    );
+                    raw.copy_buffer(&src.raw, &self.readback.chunk.buffer, iter::once(region));
+                }
+            }
+            ReadbackSource::Texture {
+                ref id,
+                ref selector,
+                ref region,
+            } => {
+                let (src, transition) = trackers
+                    .textures
+                    .use_replace(
+                        texture_guard,
+                        id.value.0,
+                        selector.clone(),
+                        TextureUse::COPY_SRC,
+                    )
+                    .unwrap();
+                if !src.life_guard.use_at(submit_index) {
+                    temp_suspected.textures.push(id.value);
+                }
+                unsafe {
+                    raw.pipeline_barrier(
+                        super::all_image_stages() | hal::pso::PipelineStage::HOST
+                            ..hal::pso::PipelineStage::TRANSFER,
+                        hal::memory::Dependencies::empty(),
+                        iter::once(chunk_barrier)
+                            .chain(transition.map(|pending| pending.into_hal(src))),
+                    );
+                    raw.copy_image_to_buffer(
+                        &src.raw,
+                        hal::image::Layout::TransferSrcOptimal,
+                        &self.readback.chunk.buffer,
+                        iter::once(region),
+                    );
+                }
+            }
+        }
+        unsafe {
+            raw.pipeline_barrier(
+                hal::pso::PipelineStage::TRANSFER..hal::pso::PipelineStage::HOST,
+                hal::memory::Dependencies::empty(),
+                iter::once(hal::memory::Barrier::Buffer {
+                    states: hal::buffer::Access::TRANSFER_WRITE..hal::buffer::Access::HOST_READ,
+                    target: &self.readback.chunk.buffer,
+                    range: self.readback.range(),
+                    families: None,
+                }),
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
 pub(crate) struct PendingWrites<B: hal::Backend> {
     pub command_buffer: Option<B::CommandBuffer>,
     pub temp_buffers: Vec<(B::Buffer, MemoryBlock<B>)>,
+    pub staging: StagingRing<B>,
+    /// Only behind a mutex to keep the device `Sync`, since the callbacks aren't.
+    pub readbacks: Mutex<Vec<QueuedReadback<B>>>,
 }
 
 impl<B: hal::Backend> PendingWrites<B> {
@@ -40,6 +435,8 @@ impl<B: hal::Backend> PendingWrites<B> {
         PendingWrites {
             command_buffer: None,
             temp_buffers: Vec::new(),
+            staging: StagingRing::new(),
+            readbacks: Mutex::new(Vec::new()),
         }
     }
 
@@ -58,6 +455,19 @@ impl<B: hal::Backend> PendingWrites<B> {
                 device.destroy_buffer(buffer);
             }
         }
+        self.staging.dispose(device, mem_allocator);
+    }
+
+    /// Fails the readbacks that are not submitted yet.
+    pub fn cancel_readbacks(&mut self) -> Vec<QueueReadPendingCallback> {
+        let readbacks = self.readbacks.get_mut();
+        let mut callbacks = Vec::with_capacity(readbacks.len());
+        for queued in readbacks.drain(..) {
+            let (chunk, callback) = queued.readback.cancel();
+            self.staging.release(chunk);
+            callbacks.push(callback);
+        }
+        callbacks
     }
 
     pub fn consume_temp(&mut self, buffer: B::Buffer, memory: MemoryBlock<B>) {
@@ -65,7 +475,14 @@ impl<B: hal::Backend> PendingWrites<B> {
     }
 
     fn consume(&mut self, stage: StagingData<B>) {
-        self.temp_buffers.push((stage.buffer, stage.memory));
+        self.staging.active = Some(stage.chunk);
+        self.command_buffer = Some(stage.cmdbuf);
+    }
+
+    /// Returns the resources of a stage that nothing was recorded for,
+    /// after an error.
+    fn abandon(&mut self, stage: StagingData<B>) {
+        self.staging.give_back(stage.chunk, stage.offset);
         self.command_buffer = Some(stage.cmdbuf);
     }
 }
@@ -82,10 +499,16 @@ impl<B: hal::Backend> super::Device<B> {
         self.pending_writes.command_buffer.as_mut().unwrap()
     }
 
-    fn prepare_stage(&mut self, size: wgt::BufferAddress) -> Result<StagingData<B>, DeviceError> {
+    fn create_staging_chunk(
+        &mut self,
+        size: wgt::BufferAddress,
+    ) -> Result<StagingChunk<B>, DeviceError> {
         let mut buffer = unsafe {
             self.raw
-                .create_buffer(size, hal::buffer::Usage::TRANSFER_SRC)
+                .create_buffer(
+                    size,
+                    hal::buffer::Usage::TRANSFER_SRC | hal::buffer::Usage::TRANSFER_DST,
+                )
                 .map_err(|err| match err {
                     hal::buffer::CreationError::OutOfMemory(_) => DeviceError::OutOfMemory,
                     _ => panic!("failed to create staging buffer: {}", err),
@@ -105,13 +528,79 @@ impl<B: hal::Backend> super::Device<B> {
             )
             .map_err(DeviceError::from_heaps)?;
         unsafe {
-            self.raw.set_buffer_name(&mut buffer, "<write_buffer_temp>");
+            self.raw.set_buffer_name(&mut buffer, "<staging_chunk>");
             self.raw
                 .bind_buffer_memory(memory.memory(), memory.segment().offset, &mut buffer)
                 .map_err(DeviceError::from_bind)?;
         }
 
-        let cmdbuf = match self.pending_writes.command_buffer.take() {
+        Ok(StagingChunk {
+            buffer,
+            memory,
+            size,
+            offset: 0,
+        })
+    }
+
+    /// Gets a free chunk of at least `size` bytes from the staging ring.
+    fn acquire_staging_chunk(
+        &mut self,
+        size: wgt::BufferAddress,
+    ) -> Result<StagingChunk<B>, DeviceError> {
+        let life_tracker = self.life_tracker.get_mut();
+        self.pending_writes
+            .staging
+            .reclaim(life_tracker.take_free_staging_chunks());
+        // Submissions are done in order, so everything before
+        // the first pending one can be reused.
+        let first_pending_index = life_tracker
+            .first_pending_submission(&self.raw)
+            .unwrap_or(self.active_submission_index + 1);
+        self.pending_writes.staging.recycle(first_pending_index);
+        self.pending_writes
+            .staging
+            .trim(&self.raw, &mut self.mem_allocator.lock());
+        match self.pending_writes.staging.take_free(size) {
+            Some(chunk) => Ok(chunk),
+            None => self.create_staging_chunk(size.max(STAGING_CHUNK_SIZE)),
+        }
+    }
+
+    fn prepare_stage(
+        &mut self,
+        size: wgt::BufferAddress,
+        alignment: wgt::BufferAddress,
+    ) -> Result<StagingData<B>, DeviceError> {
+        let mut chunk = match self.pending_writes.staging.active.take() {
+            Some(chunk) if chunk.fits(size, alignment) => chunk,
+            full => {
+                self.pending_writes.staging.closed.extend(full);
+                self.acquire_staging_chunk(size)?
+            }
+        };
+        let offset = chunk.aligned_offset(alignment);
+        chunk.offset = offset + size;
+
+        Ok(StagingData {
+            chunk,
+            offset,
+            size,
+            cmdbuf: self.take_pending_command_buffer(),
+        })
+    }
+
+    /// Prepares a whole staging chunk to receive the data of a readback.
+    fn prepare_readback(
+        &mut self,
+        size: wgt::BufferAddress,
+    ) -> Result<StagingChunk<B>, DeviceError> {
+        let mut chunk = self.acquire_staging_chunk(size)?;
+        chunk.offset = size;
+        Ok(chunk)
+    }
+
+    fn take_pending_command_buffer(&mut self) -> B::CommandBuffer {
+        match self.pending_writes.command_buffer.take() {
             Some(cmdbuf) => cmdbuf,
             None => {
                 let mut cmdbuf = self.cmd_allocator.allocate_internal();
@@ -120,12 +609,7 @@ impl<B: hal::Backend> super::Device<B> {
                 }
                 cmdbuf
             }
-        };
-        Ok(StagingData {
-            buffer,
-            memory,
-            cmdbuf,
-        })
+        }
     }
 }
 
@@ -137,6 +621,14 @@ pub enum QueueWriteError {
     Transfer(#[from] TransferError),
 }
 
+#[derive(Clone, Debug, Error)]
+pub enum QueueReadError {
+    #[error(transparent)]
+    Queue(#[from] DeviceError),
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+}
+
 #[derive(Clone, Debug, Error)]
 pub enum QueueSubmitError {
     #[error(transparent)]
@@ -145,10 +637,26 @@ pub enum QueueSubmitError {
     InvalidCommandBuffer(id::CommandBufferId),
     #[error(transparent)]
     BufferAccess(#[from] BufferAccessError),
+    #[error("command buffer {0:?} was already submitted, and isn't reusable")]
+    CommandBufferNotReusable(id::CommandBufferId),
+    #[error("previous submission of command buffer {0:?} is not done yet")]
+    CommandBufferInUse(id::CommandBufferId),
     #[error("swap chain output was dropped before the command buffer got submitted")]
     SwapChainOutputDropped,
     #[error("GPU got stuck :(")]
     StuckGpu,
+    #[error(transparent)]
+    Wait(WaitIdleError),
+}
+
+impl From<WaitIdleError> for QueueSubmitError {
+    fn from(error: WaitIdleError) -> Self {
+        match error {
+            WaitIdleError::Device(err) => QueueSubmitError::Queue(err),
+            WaitIdleError::StuckGpu => QueueSubmitError::StuckGpu,
+            other => QueueSubmitError::Wait(other),
+        }
+    }
 }
 
 //TODO: move out common parts of write_xxx.
@@ -192,31 +700,12 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             return Ok(());
         }
 
-        let mut stage = device.prepare_stage(data_size)?;
-        {
-            let mut mapped = stage
-                .memory
-                .map(&device.raw, hal::memory::Segment::ALL)
-                .map_err(|err| match err {
-                    hal::device::MapError::OutOfMemory(_) => DeviceError::OutOfMemory,
-                    _ => panic!("failed to map buffer: {}", err),
-                })?;
-            unsafe { mapped.write(&device.raw, hal::memory::Segment::ALL) }
-                .expect("failed to get writer to mapped staging buffer")
-                .slice[..data.len()]
-                .copy_from_slice(data);
-        }
-
-        let mut trackers = device.trackers.lock();
-        let (dst, transition) = trackers
-            .buffers
-            .use_replace(&*buffer_guard, buffer_id, (), BufferUse::COPY_DST)
-            .map_err(TransferError::InvalidBuffer)?;
+        let dst = buffer_guard
+            .get(buffer_id)
+            .map_err(|_| TransferError::InvalidBuffer(buffer_id))?;
         if !dst.usage.contains(wgt::BufferUsage::COPY_DST) {
             Err(TransferError::MissingCopyDstUsageFlag)?;
         }
-        dst.life_guard.use_at(device.active_submission_index + 1);
-
         if data_size % wgt::COPY_BUFFER_ALIGNMENT != 0 {
             Err(TransferError::UnalignedCopySize(data_size))?
         }
@@ -232,8 +721,27 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             })?
         }
 
+        let alignment = get_lowest_common_denom(
+            device.hal_limits.optimal_buffer_copy_offset_alignment as u32,
+            wgt::COPY_BUFFER_ALIGNMENT as u32,
+        );
+        let mut stage = device.prepare_stage(data_size, alignment as wgt::BufferAddress)?;
+        if let Err(err) = stage.write(&device.raw, |slice| {
+            slice[..data.len()].copy_from_slice(data)
+        }) {
+            device.pending_writes.abandon(stage);
+            return Err(err.into());
+        }
+
+        let mut trackers = device.trackers.lock();
+        let (dst, transition) = trackers
+            .buffers
+            .use_replace(&*buffer_guard, buffer_id, (), BufferUse::COPY_DST)
+            .unwrap();
+        dst.life_guard.use_at(device.active_submission_index + 1);
+
         let region = hal::command::BufferCopy {
-            src: 0,
+            src: stage.offset,
             dst: buffer_offset,
             size: data.len() as _,
         };
@@ -243,15 +751,15 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 hal::memory::Dependencies::empty(),
                 iter::once(hal::memory::Barrier::Buffer {
                     states: hal::buffer::Access::HOST_WRITE..hal::buffer::Access::TRANSFER_READ,
-                    target: &stage.buffer,
-                    range: hal::buffer::SubRange::WHOLE,
+                    target: &stage.chunk.buffer,
+                    range: stage.range(),
                     families: None,
                 })
                 .chain(transition.map(|pending| pending.into_hal(dst))),
             );
             stage
                 .cmdbuf
-                .copy_buffer(&stage.buffer, &dst.raw, iter::once(region));
+                .copy_buffer(&stage.chunk.buffer, &dst.raw, iter::once(region));
         }
 
         device.pending_writes.consume(stage);
@@ -299,11 +807,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             return Ok(());
         }
 
-        let texture_format = texture_guard.get(destination.texture).unwrap().format;
-        let bytes_per_block = conv::map_texture_format(texture_format, device.private_features)
-            .surface_desc()
-            .bits as u32
-            / BITS_PER_BYTE;
+        let texture = texture_guard.get(destination.texture).unwrap();
+        let texture_format = texture.format;
+        let bytes_per_block =
+            aspect_bytes_per_block(texture_format, destination.aspect, device.private_features)?;
         validate_linear_texture_data(
             data_layout,
             texture_format,
@@ -327,44 +834,26 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let block_rows_in_copy = (size.depth - 1) * block_rows_per_image + height_blocks;
         let stage_size = stage_bytes_per_row as u64 * block_rows_in_copy as u64;
-        let mut stage = device.prepare_stage(stage_size)?;
-
-        let mut trackers = device.trackers.lock();
-        let (dst, transition) = trackers
-            .textures
-            .use_replace(
-                &*texture_guard,
-                destination.texture,
-                image_range,
-                TextureUse::COPY_DST,
-            )
-            .unwrap();
-
-        if !dst.usage.contains(wgt::TextureUsage::COPY_DST) {
+        let alignment = get_lowest_common_denom(
+            device.hal_limits.optimal_buffer_copy_offset_alignment as u32,
+            bytes_per_block,
+        );
+        if !texture.usage.contains(wgt::TextureUsage::COPY_DST) {
             Err(TransferError::MissingCopyDstUsageFlag)?
         }
         validate_texture_copy_range(
             destination,
-            dst.format,
-            dst.kind,
+            texture.format,
+            texture.kind,
             CopySide::Destination,
             size,
         )?;
-        dst.life_guard.use_at(device.active_submission_index + 1);
 
-        {
-            let mut mapped = stage
-                .memory
-                .map(&device.raw, hal::memory::Segment::ALL)
-                .map_err(|err| match err {
-                    hal::device::MapError::OutOfMemory(_) => DeviceError::OutOfMemory,
-                    _ => panic!("failed to map staging buffer: {}", err),
-                })?;
-            let mapping = unsafe { mapped.write(&device.raw, hal::memory::Segment::ALL) }
-                .expect("failed to get writer to mapped staging buffer");
+        let mut stage = device.prepare_stage(stage_size, alignment as wgt::BufferAddress)?;
+        let written = stage.write(&device.raw, |slice| {
             if stage_bytes_per_row == data_layout.bytes_per_row {
                 // Fast path if the data isalready being aligned optimally.
-                mapping.slice[..stage_size as usize].copy_from_slice(data);
+                slice[..stage_size as usize].copy_from_slice(data);
             } else {
                 // Copy row by row into the optimal alignment.
                 let copy_bytes_per_row =
@@ -376,15 +865,31 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             (rows_offset + row) as usize * data_layout.bytes_per_row as usize;
                         let stage_offset =
                             (rows_offset + row) as usize * stage_bytes_per_row as usize;
-                        mapping.slice[stage_offset..stage_offset + copy_bytes_per_row]
+                        slice[stage_offset..stage_offset + copy_bytes_per_row]
                             .copy_from_slice(&data[data_offset..data_offset + copy_bytes_per_row]);
                     }
                 }
             }
+        });
+        if let Err(err) = written {
+            device.pending_writes.abandon(stage);
+            return Err(err.into());
         }
 
+        let mut trackers = device.trackers.lock();
+        let (dst, transition) = trackers
+            .textures
+            .use_replace(
+                &*texture_guard,
+                destination.texture,
+                image_range,
+                TextureUse::COPY_DST,
+            )
+            .unwrap();
+        dst.life_guard.use_at(device.active_submission_index + 1);
+
         let region = hal::command::BufferImageCopy {
-            buffer_offset: 0,
+            buffer_offset: stage.offset,
             buffer_width: (stage_bytes_per_row / bytes_per_block) * block_width,
             buffer_height: texel_rows_per_image,
             image_layers,
@@ -398,14 +903,14 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 hal::memory::Dependencies::empty(),
                 iter::once(hal::memory::Barrier::Buffer {
                     states: hal::buffer::Access::HOST_WRITE..hal::buffer::Access::TRANSFER_READ,
-                    target: &stage.buffer,
-                    range: hal::buffer::SubRange::WHOLE,
+                    target: &stage.chunk.buffer,
+                    range: stage.range(),
                     families: None,
                 })
                 .chain(transition.map(|pending| pending.into_hal(dst))),
             );
             stage.cmdbuf.copy_buffer_to_image(
-                &stage.buffer,
+                &stage.chunk.buffer,
                 &dst.raw,
                 hal::image::Layout::TransferDstOptimal,
                 iter::once(region),
@@ -417,21 +922,261 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         Ok(())
     }
 
+    /// Reads the contents of a buffer region, delivering them to the callback
+    /// once the next submission is done.
+    ///
+    /// The contents are read after the command buffers of that submission.
+    pub fn queue_read_buffer<B: GfxBackend>(
+        &self,
+        queue_id: id::QueueId,
+        buffer_id: id::BufferId,
+        buffer_offset: wgt::BufferAddress,
+        size: wgt::BufferAddress,
+        callback: QueueReadCallback,
+    ) -> Result<(), QueueReadError> {
+        span!(_guard, INFO, "Queue::read_buffer");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (mut device_guard, mut token) = hub.devices.write(&mut token);
+        let device = device_guard
+            .get_mut(queue_id)
+            .map_err(|_| DeviceError::Invalid)?;
+        let (buffer_guard, _) = hub.buffers.read(&mut token);
+
+        if size % wgt::COPY_BUFFER_ALIGNMENT != 0 {
+            Err(TransferError::UnalignedCopySize(size))?
+        }
+        if buffer_offset % wgt::COPY_BUFFER_ALIGNMENT != 0 {
+            Err(TransferError::UnalignedBufferOffset(buffer_offset))?
+        }
+        let src = buffer_guard
+            .get(buffer_id)
+            .map_err(|_| TransferError::InvalidBuffer(buffer_id))?;
+        if !src.usage.contains(wgt::BufferUsage::COPY_SRC) {
+            Err(TransferError::MissingCopySrcUsageFlag)?
+        }
+        if buffer_offset + size > src.size {
+            Err(TransferError::BufferOverrun {
+                start_offset: buffer_offset,
+                end_offset: buffer_offset + size,
+                buffer_size: src.size,
+                side: CopySide::Source,
+            })?
+        }
+        if size == 0 {
+            tracing::trace!("Ignoring read_buffer of size 0");
+            drop(buffer_guard);
+            drop(device_guard);
+            callback(Ok(Vec::new()));
+            return Ok(());
+        }
+
+        let chunk = device.prepare_readback(size)?;
+        device
+            .pending_writes
+            .readbacks
+            .get_mut()
+            .push(QueuedReadback {
+                source: ReadbackSource::Buffer {
+                    id: Stored {
+                        value: id::Valid(buffer_id),
+                        ref_count: src.life_guard.add_ref(),
+                    },
+                    region: hal::command::BufferCopy {
+                        src: buffer_offset,
+                        dst: 0,
+                        size,
+                    },
+                },
+                readback: PendingReadback {
+                    chunk,
+                    stage_bytes_per_row: size,
+                    packed_bytes_per_row: size,
+                    rows: 1,
+                    callback,
+                },
+            });
+
+        Ok(())
+    }
+
+    /// Reads the contents of a texture region, delivering them tightly packed
+    /// to the callback once the next submission is done.
+    ///
+    /// The contents are read after the command buffers of that submission.
+    pub fn queue_read_texture<B: GfxBackend>(
+        &self,
+        queue_id: id::QueueId,
+        source: &TextureCopyView,
+        size: &wgt::Extent3d,
+        callback: QueueReadCallback,
+    ) -> Result<(), QueueReadError> {
+        span!(_guard, INFO, "Queue::read_texture");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (mut device_guard, mut token) = hub.devices.write(&mut token);
+        let device = device_guard
+            .get_mut(queue_id)
+            .map_err(|_| DeviceError::Invalid)?;
+        let (texture_guard, _) = hub.textures.read(&mut token);
+        let (image_layers, image_range, image_offset) =
+            texture_copy_view_to_hal(source, size, &*texture_guard)?;
+
+        let texture = texture_guard.get(source.texture).unwrap();
+        let texture_format = texture.format;
+        let bytes_per_block =
+            aspect_bytes_per_block(texture_format, source.aspect, device.private_features)?;
+        let (block_width, block_height) = conv::texture_block_size(texture_format);
+        let width_blocks = size.width / block_width;
+        let height_blocks = size.height / block_height;
+
+        // The staging data follows the same rules as user-provided copy buffers,
+        // so the layout is validated the same way.
+        let packed_bytes_per_row = bytes_per_block * width_blocks;
+        let stage_bytes_per_row = align_to(
+            packed_bytes_per_row,
+            get_lowest_common_denom(
+                device.hal_limits.optimal_buffer_copy_pitch_alignment as u32,
+                wgt::COPY_BYTES_PER_ROW_ALIGNMENT,
+            ),
+        );
+        let rows = height_blocks * size.depth;
+        let stage_size = stage_bytes_per_row as wgt::BufferAddress * rows as wgt::BufferAddress;
+        let stage_layout = wgt::TextureDataLayout {
+            offset: 0,
+            bytes_per_row: stage_bytes_per_row,
+            rows_per_image: size.height,
+        };
+        validate_linear_texture_data(
+            &stage_layout,
+            texture_format,
+            stage_size,
+            CopySide::Destination,
+            bytes_per_block as wgt::BufferAddress,
+            size,
+        )?;
+
+        if !texture.usage.contains(wgt::TextureUsage::COPY_SRC) {
+            Err(TransferError::MissingCopySrcUsageFlag)?
+        }
+        validate_texture_copy_range(source, texture.format, texture.kind, CopySide::Source, size)?;
+        if size.width == 0 || size.height == 0 || size.depth == 0 {
+            tracing::trace!("Ignoring read_texture of size 0");
+            drop(texture_guard);
+            drop(device_guard);
+            callback(Ok(Vec::new()));
+            return Ok(());
+        }
+
+        let region = hal::command::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_width: (stage_bytes_per_row / bytes_per_block) * block_width,
+            buffer_height: size.height,
+            image_layers,
+            image_offset,
+            image_extent: conv::map_extent(size, texture.dimension),
+        };
+        let ref_count = texture.life_guard.add_ref();
+        let chunk = device.prepare_readback(stage_size)?;
+        device
+            .pending_writes
+            .readbacks
+            .get_mut()
+            .push(QueuedReadback {
+                source: ReadbackSource::Texture {
+                    id: Stored {
+                        value: id::Valid(source.texture),
+                        ref_count,
+                    },
+                    selector: image_range,
+                    region,
+                },
+                readback: PendingReadback {
+                    chunk,
+                    stage_bytes_per_row: stage_bytes_per_row as wgt::BufferAddress,
+                    packed_bytes_per_row: packed_bytes_per_row as wgt::BufferAddress,
+                    rows,
+                    callback,
+                },
+            });
+
+        Ok(())
+    }
+
     pub fn queue_submit<B: GfxBackend>(
         &self,
         queue_id: id::QueueId,
         command_buffer_ids: &[id::CommandBufferId],
-    ) -> Result<(), QueueSubmitError> {
+    ) -> Result<super::SubmissionHandle, QueueSubmitError> {
         span!(_guard, INFO, "Queue::submit");
 
         let hub = B::hub(self);
 
-        let callbacks = {
-            let mut token = Token::root();
+        let mut token = Token::root();
+        // Wait for a free submission slot without holding the device locks,
+        // so that the device stays usable by other threads in the meantime.
+        loop {
+            let (raw, fence) = {
+                let (device_guard, mut token) = hub.devices.read(&mut token);
+                let device = device_guard
+                    .get(queue_id)
+                    .map_err(|_| DeviceError::Invalid)?;
+                let max = match device.max_submissions_in_flight {
+                    Some(max) => max.get() as usize,
+                    None => break,
+                };
+                let fence = device.lock_life(&mut token).free_slot_fence(max);
+                match fence {
+                    Some(fence) => (Arc::clone(&device.raw), fence),
+                    None => break,
+                }
+            };
+            super::life::wait_for_free_slot::<B>(&raw, &fence)?;
+            // The submission is done now, but it stays active until triaged.
+            let (device_guard, mut token) = hub.devices.read(&mut token);
+            let device = device_guard
+                .get(queue_id)
+                .map_err(|_| DeviceError::Invalid)?;
+            device
+                .lock_life(&mut token)
+                .triage_submissions(&device.raw, false)?;
+        }
+
+        let (callbacks, submit_index) = {
             let (mut device_guard, mut token) = hub.devices.write(&mut token);
             let device = device_guard
                 .get_mut(queue_id)
                 .map_err(|_| DeviceError::Invalid)?;
+
+            // Submissions complete in order, so everything before the first pending one is done.
+            let first_pending_index = device
+                .lock_life(&mut token)
+                .first_pending_submission(&device.raw);
+            // Validate all the command buffers before changing any state of the device.
+            {
+                let (command_buffer_guard, _) = hub.command_buffers.read(&mut token);
+                for (i, &cmb_id) in command_buffer_ids.iter().enumerate() {
+                    let cmdbuf = command_buffer_guard
+                        .get(cmb_id)
+                        .map_err(|_| QueueSubmitError::InvalidCommandBuffer(cmb_id))?;
+                    let submitted_before = command_buffer_ids[..i].contains(&cmb_id);
+                    if cmdbuf.submission_index.is_some() || submitted_before {
+                        if !cmdbuf.reusable {
+                            return Err(QueueSubmitError::CommandBufferNotReusable(cmb_id));
+                        }
+                        let in_use = match (cmdbuf.submission_index, first_pending_index) {
+                            (Some(index), Some(first_pending)) => index >= first_pending,
+                            _ => false,
+                        };
+                        if in_use || submitted_before {
+                            return Err(QueueSubmitError::CommandBufferInUse(cmb_id));
+                        }
+                    }
+                }
+            }
+
             let pending_write_command_buffer =
                 device
                     .pending_writes
@@ -444,13 +1189,15 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             device.temp_suspected.clear();
             device.active_submission_index += 1;
             let submit_index = device.active_submission_index;
+            device.pending_writes.staging.submit(submit_index);
+            let mut consumed_ids = SmallVec::<[_; 4]>::new();
 
-            let fence = {
+            let (fence, readbacks) = {
                 let mut signal_swapchain_semaphores = SmallVec::<[_; 1]>::new();
                 let (mut swap_chain_guard, mut token) = hub.swap_chains.write(&mut token);
                 let (mut command_buffer_guard, mut token) = hub.command_buffers.write(&mut token);
 
-                {
+                let (readback_command_buffer, readbacks) = {
                     let (bind_group_guard, mut token) = hub.bind_groups.read(&mut token);
                     let (compute_pipe_guard, mut token) = hub.compute_pipelines.read(&mut token);
                     let (render_pipe_guard, mut token) = hub.render_pipelines.read(&mut token);
@@ -471,11 +1218,18 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         let cmdbuf = command_buffer_guard
                             .get_mut(cmb_id)
                             .map_err(|_| QueueSubmitError::InvalidCommandBuffer(cmb_id))?;
+                        if !cmdbuf.reusable {
+                            consumed_ids.push(cmb_id);
+                        }
                         #[cfg(feature = "trace")]
                         match device.trace {
                             Some(ref trace) => trace.lock().add(Action::Submit(
                                 submit_index,
-                                cmdbuf.commands.take().unwrap(),
+                                if cmdbuf.reusable {
+                                    cmdbuf.commands.clone().unwrap()
+                                } else {
+                                    cmdbuf.commands.take().unwrap()
+                                },
                             )),
                             None => (),
                         };
@@ -502,14 +1256,19 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         for id in cmdbuf.trackers.buffers.used() {
                             let buffer = &mut buffer_guard[id];
                             if !buffer.life_guard.use_at(submit_index) {
-                                if let BufferMapState::Active { .. } = buffer.map_state {
-                                    tracing::warn!("Dropped buffer has a pending mapping.");
-                                    super::unmap_buffer(&device.raw, buffer)?;
+                                match buffer.map_state {
+                                    BufferMapState::Active { .. }
+                                    | BufferMapState::Persistent { .. } => {
+                                        tracing::warn!("Dropped buffer has a pending mapping.");
+                                        super::unmap_buffer(&*device.raw, buffer)?;
+                                    }
+                                    _ => {}
                                 }
                                 device.temp_suspected.buffers.push(id);
                             } else {
                                 match buffer.map_state {
-                                    BufferMapState::Idle => (),
+                                    // persistently mapped buffers are synchronized by the user
+                                    BufferMapState::Idle | BufferMapState::Persistent { .. } => (),
                                     _ => panic!("Buffer {:?} is still mapped", id),
                                 }
                             }
@@ -546,10 +1305,27 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         }
 
                         // execute resource transitions
-                        let mut transit = device.cmd_allocator.extend(cmdbuf);
+                        let mut transit = match cmdbuf.submission_index {
+                            // The previous submission is done, so the transitions
+                            // can be recorded again for the current device state.
+                            Some(_) => {
+                                let mut transit = cmdbuf.raw.remove(0);
+                                unsafe {
+                                    transit.reset(false);
+                                }
+                                transit
+                            }
+                            None => {
+                                unsafe {
+                                    // the last buffer was open, closing now
+                                    cmdbuf.raw.last_mut().unwrap().finish();
+                                }
+                                device.cmd_allocator.extend(cmdbuf)
+                            }
+                        };
+                        cmdbuf.submission_index = Some(submit_index);
                         unsafe {
-                            // the last buffer was open, closing now
-                            cmdbuf.raw.last_mut().unwrap().finish();
+                            // Transit buffers are recorded again for every submission.
                             transit
                                 .begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
                         }
@@ -567,8 +1343,34 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         cmdbuf.raw.insert(0, transit);
                     }
 
+                    // record the readbacks last, so that they see the results of the submission
+                    let readbacks = std::mem::take(device.pending_writes.readbacks.get_mut());
+                    let readback_command_buffer = if readbacks.is_empty() {
+                        None
+                    } else {
+                        let mut raw = device.cmd_allocator.allocate_internal();
+                        unsafe {
+                            raw.begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
+                        }
+                        for queued in readbacks.iter() {
+                            queued.encode(
+                                &mut raw,
+                                &mut trackers,
+                                &*buffer_guard,
+                                &*texture_guard,
+                                submit_index,
+                                &mut device.temp_suspected,
+                            );
+                        }
+                        unsafe {
+                            raw.finish();
+                        }
+                        Some(raw)
+                    };
+
                     tracing::trace!("Device after submission {}: {:#?}", submit_index, trackers);
-                }
+                    (readback_command_buffer, readbacks)
+                };
 
                 // now prepare the GPU submission
                 let fence = device
@@ -576,11 +1378,15 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     .create_fence(false)
                     .or(Err(DeviceError::OutOfMemory))?;
                 let submission = hal::queue::Submission {
-                    command_buffers: pending_write_command_buffer.as_ref().into_iter().chain(
-                        command_buffer_ids
-                            .iter()
-                            .flat_map(|&cmb_id| &command_buffer_guard.get(cmb_id).unwrap().raw),
-                    ),
+                    command_buffers: pending_write_command_buffer
+                        .as_ref()
+                        .into_iter()
+                        .chain(
+                            command_buffer_ids
+                                .iter()
+                                .flat_map(|&cmb_id| &command_buffer_guard.get(cmb_id).unwrap().raw),
+                        )
+                        .chain(readback_command_buffer.as_ref()),
                     wait_semaphores: Vec::new(),
                     signal_semaphores: signal_swapchain_semaphores
                         .into_iter()
@@ -590,7 +1396,12 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 unsafe {
                     device.queue_group.queues[0].submit(submission, Some(&fence));
                 }
-                fence
+                if let Some(comb_raw) = readback_command_buffer {
+                    device
+                        .cmd_allocator
+                        .after_submit_internal(comb_raw, submit_index);
+                }
+                (fence, readbacks)
             };
 
             if let Some(comb_raw) = pending_write_command_buffer {
@@ -599,29 +1410,52 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     .after_submit_internal(comb_raw, submit_index);
             }
 
-            let callbacks = match device.maintain(&hub, false, &mut token) {
-                Ok(callbacks) => callbacks,
-                Err(WaitIdleError::Device(err)) => return Err(QueueSubmitError::Queue(err)),
-                Err(WaitIdleError::StuckGpu) => return Err(QueueSubmitError::StuckGpu),
-            };
-            super::Device::lock_life_internal(&device.life_tracker, &mut token).track_submission(
+            let callbacks = device.maintain(&hub, false, &mut token)?;
+            let mut life_tracker =
+                super::Device::lock_life_internal(&device.life_tracker, &mut token);
+            for queued in readbacks {
+                life_tracker.add_readback(queued.readback);
+            }
+            life_tracker.track_submission(
                 submit_index,
                 fence,
                 &device.temp_suspected,
                 device.pending_writes.temp_buffers.drain(..),
             );
+            drop(life_tracker);
 
-            // finally, return the command buffers to the allocator
-            for &cmb_id in command_buffer_ids {
+            // finally, return the command buffers to the allocator,
+            // reusable ones stay alive until dropped
+            for cmb_id in consumed_ids {
                 let (cmd_buf, _) = hub.command_buffers.unregister(cmb_id, &mut token);
                 device.cmd_allocator.after_submit(cmd_buf, submit_index);
             }
 
-            callbacks
+            (callbacks, submit_index)
         };
 
-        super::fire_map_callbacks(callbacks);
+        callbacks.fire();
 
+        Ok(super::SubmissionHandle {
+            queue_id,
+            index: submit_index,
+        })
+    }
+
+    pub fn queue_on_submitted_work_done<B: GfxBackend>(
+        &self,
+        queue_id: id::QueueId,
+        closure: SubmittedWorkDoneClosure,
+    ) -> Result<(), DeviceError> {
+        span!(_guard, INFO, "Queue::on_submitted_work_done");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, mut token) = hub.devices.read(&mut token);
+        let device = device_guard
+            .get(queue_id)
+            .map_err(|_| DeviceError::Invalid)?;
+        device.lock_life(&mut token).add_work_done_closure(closure);
         Ok(())
     }
 }
@@ -669,3 +1503,152 @@ fn test_gcd() {
     assert_eq!(get_greatest_common_divisor(6, 4), 2);
     assert_eq!(get_greatest_common_divisor(7, 7), 7);
 }
+
+#[test]
+fn test_align_offset() {
+    assert_eq!(align_offset(0, 4), 0);
+    assert_eq!(align_offset(5, 4), 8);
+    assert_eq!(align_offset(8, 4), 8);
+    assert_eq!(align_offset(13, 256), 256);
+}
+
+#[test]
+fn test_keep_free_staging_chunk() {
+    assert!(keep_free_staging_chunk(STAGING_CHUNK_SIZE, 0));
+    assert!(keep_free_staging_chunk(
+        STAGING_CHUNK_SIZE,
+        MAX_FREE_STAGING_CHUNKS - 1
+    ));
+    // The free list is capped.
+    assert!(!keep_free_staging_chunk(
+        STAGING_CHUNK_SIZE,
+        MAX_FREE_STAGING_CHUNKS
+    ));
+    // Chunks of oversized writes are never kept.
+    assert!(!keep_free_staging_chunk(STAGING_CHUNK_SIZE + 1, 0));
+}
+
+#[cfg(test)]
+fn test_staging_chunk(
+    device: &gfx_backend_empty::Device,
+    heaps: &mut Heaps<crate::backend::Empty>,
+    data: &[u8],
+) -> StagingChunk<crate::backend::Empty> {
+    let size = data.len() as wgt::BufferAddress;
+    let buffer = unsafe { device.create_buffer(size, hal::buffer::Usage::TRANSFER_DST) }.unwrap();
+    let requirements = unsafe { device.get_buffer_requirements(&buffer) };
+    let mut memory = heaps
+        .allocate(
+            device,
+            &requirements,
+            gfx_memory::MemoryUsage::Staging { read_back: true },
+            gfx_memory::Kind::Dedicated,
+        )
+        .unwrap();
+    {
+        let mut mapped = memory.map(device, hal::memory::Segment::ALL).unwrap();
+        let writer = unsafe { mapped.write(device, hal::memory::Segment::ALL) }.unwrap();
+        writer.slice[..data.len()].copy_from_slice(data);
+    }
+    StagingChunk {
+        buffer,
+        memory,
+        size,
+        offset: size,
+    }
+}
+
+#[cfg(test)]
+fn test_heaps() -> Heaps<crate::backend::Empty> {
+    let properties = hal::adapter::MemoryProperties {
+        memory_types: vec![hal::adapter::MemoryType {
+            properties: hal::memory::Properties::DEVICE_LOCAL
+                | hal::memory::Properties::CPU_VISIBLE
+                | hal::memory::Properties::COHERENT
+                | hal::memory::Properties::CPU_CACHED,
+            heap_index: 0,
+        }],
+        memory_heaps: vec![!0],
+    };
+    unsafe {
+        Heaps::new(
+            &properties,
+            gfx_memory::GeneralConfig {
+                block_size_granularity: 0x100,
+                max_chunk_size: 0x100_0000,
+                min_device_allocation: 0x1_0000,
+            },
+            gfx_memory::LinearConfig {
+                line_size: 0x100_0000,
+            },
+            1,
+        )
+    }
+}
+
+#[test]
+fn test_pack_rows() {
+    let staged = [1, 2, 3, 0, 4, 5, 6, 0];
+    assert_eq!(pack_rows(&staged, 4, 3), vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(pack_rows(&staged, 8, 8), staged.to_vec());
+}
+
+#[test]
+fn test_readback_resolve() {
+    let device = gfx_backend_empty::Device;
+    let mut heaps = test_heaps();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let readback = PendingReadback {
+        chunk: test_staging_chunk(&device, &mut heaps, &[1, 2, 0, 0, 3, 4, 0, 0]),
+        stage_bytes_per_row: 4,
+        packed_bytes_per_row: 2,
+        rows: 2,
+        callback: Box::new(move |result| sender.send(result).unwrap()),
+    };
+    let (chunk, (callback, result)) = readback.resolve(&device);
+    callback(result);
+    assert_eq!(receiver.recv().unwrap().unwrap(), vec![1, 2, 3, 4]);
+    heaps.free(&device, chunk.memory);
+    heaps.clear(&device);
+}
+
+#[test]
+fn test_readbacks_fail_on_dispose() {
+    use super::life::{LifetimeTracker, SuspectedResources};
+
+    let device = gfx_backend_empty::Device;
+    let mut heaps = test_heaps();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut tracker = LifetimeTracker::<crate::backend::Empty>::new();
+    // One readback is in flight, another one waits for the next submission.
+    for index in 1..=2 {
+        let sender = sender.clone();
+        tracker.add_readback(PendingReadback {
+            chunk: test_staging_chunk(&device, &mut heaps, &[0; 4]),
+            stage_bytes_per_row: 4,
+            packed_bytes_per_row: 4,
+            rows: 1,
+            callback: Box::new(move |result| sender.send(result).unwrap()),
+        });
+        if index == 1 {
+            tracker.track_submission(index, (), &SuspectedResources::default(), iter::empty());
+        }
+    }
+
+    let mut callbacks = Vec::new();
+    let chunks = tracker
+        .into_staging_chunks(&device, &mut callbacks)
+        .collect::<Vec<_>>();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(callbacks.len(), 2);
+    for (callback, result) in callbacks {
+        callback(result);
+    }
+    for _ in 0..2 {
+        assert!(matches!(receiver.recv().unwrap(), Err(DeviceError::Lost)));
+    }
+    for chunk in chunks {
+        heaps.free(&device, chunk.memory);
+    }
+    heaps.clear(&device);
+}
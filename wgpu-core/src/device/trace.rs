@@ -5,7 +5,7 @@
 use crate::id;
 #[cfg(feature = "trace")]
 use std::io::Write as _;
-use std::{borrow::Cow, ops::Range};
+use std::{borrow::Cow, collections::BTreeMap, ops::Range};
 
 //TODO: consider a readable Id that doesn't include the backend
 
@@ -69,7 +69,11 @@ pub enum Action<'a> {
     DestroyBindGroup(id::BindGroupId),
     CreateShaderModule {
         id: id::ShaderModuleId,
+        /// File with the original code. Its extension tells the kind of source:
+        /// "spv", "wgsl", "glsl", or "ron" for a serialized Naga module.
         data: FileName,
+        #[cfg_attr(any(feature = "trace", feature = "replay"), serde(default))]
+        glsl: Option<GlslOptions>,
     },
     DestroyShaderModule(id::ShaderModuleId),
     CreateComputePipeline(
@@ -88,6 +92,12 @@ pub enum Action<'a> {
         base: crate::command::BasePass<crate::command::RenderCommand>,
     },
     DestroyRenderBundle(id::RenderBundleId),
+    CreateComputeBundle {
+        id: id::ComputeBundleId,
+        desc: crate::command::ComputeBundleEncoderDescriptor<'a>,
+        base: crate::command::BasePass<crate::command::ComputeCommand>,
+    },
+    DestroyComputeBundle(id::ComputeBundleId),
     WriteBuffer {
         id: id::BufferId,
         data: FileName,
@@ -103,7 +113,7 @@ pub enum Action<'a> {
     Submit(crate::SubmissionIndex, Vec<Command>),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub enum Command {
@@ -137,6 +147,23 @@ pub enum Command {
         target_colors: Vec<crate::command::ColorAttachmentDescriptor>,
         target_depth_stencil: Option<crate::command::DepthStencilAttachmentDescriptor>,
     },
+    RunParallelRenderPass {
+        bases: Vec<crate::command::BasePass<crate::command::RenderCommand>>,
+        target_colors: Vec<crate::command::ColorAttachmentDescriptor>,
+        target_depth_stencil: Option<crate::command::DepthStencilAttachmentDescriptor>,
+    },
+    PushDebugGroup(String),
+    PopDebugGroup,
+    InsertDebugMarker(String),
+}
+
+/// Parameters of a GLSL shader module, which aren't part of the code.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize))]
+#[cfg_attr(feature = "replay", derive(serde::Deserialize))]
+pub struct GlslOptions {
+    pub stage: naga::ShaderStage,
+    pub defines: BTreeMap<String, String>,
 }
 
 #[cfg(feature = "trace")]
@@ -169,6 +196,17 @@ impl Trace {
         name
     }
 
+    pub(crate) fn make_ron<T: serde::Serialize>(&mut self, value: &T) -> String {
+        let string = match ron::ser::to_string_pretty(value, self.config.clone()) {
+            Ok(string) => string,
+            Err(e) => {
+                tracing::warn!("RON serialization failure: {:?}", e);
+                String::new()
+            }
+        };
+        self.make_binary("ron", string.as_bytes())
+    }
+
     pub(crate) fn add(&mut self, action: Action) {
         match ron::ser::to_string_pretty(&action, self.config.clone()) {
             Ok(string) => {
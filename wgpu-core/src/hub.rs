@@ -5,12 +5,12 @@
 use crate::{
     backend,
     binding_model::{BindGroup, BindGroupLayout, PipelineLayout},
-    command::{CommandBuffer, RenderBundle},
+    command::{CommandBuffer, ComputeBundle, RenderBundle},
     device::Device,
     id::{
-        AdapterId, BindGroupId, BindGroupLayoutId, BufferId, CommandBufferId, ComputePipelineId,
-        DeviceId, PipelineLayoutId, RenderBundleId, RenderPipelineId, SamplerId, ShaderModuleId,
-        SurfaceId, SwapChainId, TextureId, TextureViewId, TypedId, Valid,
+        AdapterId, BindGroupId, BindGroupLayoutId, BufferId, CommandBufferId, ComputeBundleId,
+        ComputePipelineId, DeviceId, PipelineLayoutId, RenderBundleId, RenderPipelineId, SamplerId,
+        ShaderModuleId, SurfaceId, SwapChainId, TextureId, TextureViewId, TypedId, Valid,
     },
     instance::{Adapter, Instance, Surface},
     pipeline::{ComputePipeline, RenderPipeline, ShaderModule},
@@ -225,6 +225,7 @@ impl<B: hal::Backend> Access<SwapChain<B>> for Device<B> {}
 impl<B: hal::Backend> Access<PipelineLayout<B>> for Root {}
 impl<B: hal::Backend> Access<PipelineLayout<B>> for Device<B> {}
 impl<B: hal::Backend> Access<PipelineLayout<B>> for RenderBundle {}
+impl<B: hal::Backend> Access<PipelineLayout<B>> for ComputeBundle {}
 impl<B: hal::Backend> Access<BindGroupLayout<B>> for Root {}
 impl<B: hal::Backend> Access<BindGroupLayout<B>> for Device<B> {}
 impl<B: hal::Backend> Access<BindGroupLayout<B>> for PipelineLayout<B> {}
@@ -238,6 +239,8 @@ impl<B: hal::Backend> Access<CommandBuffer<B>> for Device<B> {}
 impl<B: hal::Backend> Access<CommandBuffer<B>> for SwapChain<B> {}
 impl<B: hal::Backend> Access<RenderBundle> for Device<B> {}
 impl<B: hal::Backend> Access<RenderBundle> for CommandBuffer<B> {}
+impl<B: hal::Backend> Access<ComputeBundle> for Device<B> {}
+impl<B: hal::Backend> Access<ComputeBundle> for CommandBuffer<B> {}
 impl<B: hal::Backend> Access<ComputePipeline<B>> for Device<B> {}
 impl<B: hal::Backend> Access<ComputePipeline<B>> for BindGroup<B> {}
 impl<B: hal::Backend> Access<RenderPipeline<B>> for Device<B> {}
@@ -351,6 +354,7 @@ pub trait GlobalIdentityHandlerFactory:
     + IdentityHandlerFactory<BindGroupId>
     + IdentityHandlerFactory<CommandBufferId>
     + IdentityHandlerFactory<RenderBundleId>
+    + IdentityHandlerFactory<ComputeBundleId>
     + IdentityHandlerFactory<RenderPipelineId>
     + IdentityHandlerFactory<ComputePipelineId>
     + IdentityHandlerFactory<BufferId>
@@ -489,6 +493,7 @@ pub struct Hub<B: hal::Backend, F: GlobalIdentityHandlerFactory> {
     pub bind_groups: Registry<BindGroup<B>, BindGroupId, F>,
     pub command_buffers: Registry<CommandBuffer<B>, CommandBufferId, F>,
     pub render_bundles: Registry<RenderBundle, RenderBundleId, F>,
+    pub compute_bundles: Registry<ComputeBundle, ComputeBundleId, F>,
     pub render_pipelines: Registry<RenderPipeline<B>, RenderPipelineId, F>,
     pub compute_pipelines: Registry<ComputePipeline<B>, ComputePipelineId, F>,
     pub buffers: Registry<Buffer<B>, BufferId, F>,
@@ -509,6 +514,7 @@ impl<B: GfxBackend, F: GlobalIdentityHandlerFactory> Hub<B, F> {
             bind_groups: Registry::new(B::VARIANT, factory, "BindGroup"),
             command_buffers: Registry::new(B::VARIANT, factory, "CommandBuffer"),
             render_bundles: Registry::new(B::VARIANT, factory, "RenderBundle"),
+            compute_bundles: Registry::new(B::VARIANT, factory, "ComputeBundle"),
             render_pipelines: Registry::new(B::VARIANT, factory, "RenderPipeline"),
             compute_pipelines: Registry::new(B::VARIANT, factory, "ComputePipeline"),
             buffers: Registry::new(B::VARIANT, factory, "Buffer"),
@@ -637,11 +643,16 @@ impl<B: GfxBackend, F: GlobalIdentityHandlerFactory> Hub<B, F> {
             }
         }
 
+        let mut readbacks = Vec::new();
         for element in devices.map.drain(..) {
             if let Element::Occupied(device, _) = element {
-                device.dispose();
+                readbacks.extend(device.dispose());
             }
         }
+        drop(devices);
+        for (callback, result) in readbacks {
+            callback(result);
+        }
     }
 }
 
@@ -164,6 +164,8 @@ pub type RenderPassEncoderId = *mut crate::command::RenderPass;
 pub type ComputePassEncoderId = *mut crate::command::ComputePass;
 pub type RenderBundleEncoderId = *mut crate::command::RenderBundleEncoder;
 pub type RenderBundleId = Id<crate::command::RenderBundle>;
+pub type ComputeBundleEncoderId = *mut crate::command::ComputeBundleEncoder;
+pub type ComputeBundleId = Id<crate::command::ComputeBundle>;
 // Swap chain
 pub type SwapChainId = Id<crate::swap_chain::SwapChain<Dummy>>;
 
@@ -4,13 +4,13 @@
 
 use crate::{
     backend,
-    device::Device,
+    device::{Device, MAX_VERTEX_BUFFERS},
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Input, Token},
     id::{AdapterId, DeviceId, SurfaceId, Valid},
     span, LifeGuard, PrivateFeatures, Stored, MAX_BIND_GROUPS,
 };
 
-use wgt::{Backend, BackendBit, DeviceDescriptor, PowerPreference, BIND_BUFFER_ALIGNMENT};
+use wgt::{Backend, BackendBit, DeviceDescriptor, PowerPreference};
 
 use hal::{
     adapter::{AdapterInfo as HalAdapterInfo, DeviceType as HalDeviceType, PhysicalDevice as _},
@@ -197,17 +197,140 @@ impl<B: hal::Backend> Adapter<B> {
                 .max(default_limits.max_uniform_buffer_binding_size),
             max_push_constant_size: (adapter_limits.max_push_constants_size as u32)
                 .max(MIN_PUSH_CONSTANT_SIZE), // As an extension, the default is always 0, so define a separate minimum.
+            max_compute_workgroup_size_x: adapter_limits.max_compute_work_group_size[0]
+                .max(default_limits.max_compute_workgroup_size_x),
+            max_compute_workgroup_size_y: adapter_limits.max_compute_work_group_size[1]
+                .max(default_limits.max_compute_workgroup_size_y),
+            max_compute_workgroup_size_z: adapter_limits.max_compute_work_group_size[2]
+                .max(default_limits.max_compute_workgroup_size_z),
+            max_compute_invocations_per_workgroup: (adapter_limits
+                .max_compute_work_group_invocations
+                as u32)
+                .max(default_limits.max_compute_invocations_per_workgroup),
+            max_compute_workgroups_per_dimension: adapter_limits
+                .max_compute_work_group_count
+                .iter()
+                .cloned()
+                .min()
+                .unwrap_or(0)
+                .max(default_limits.max_compute_workgroups_per_dimension),
+            max_compute_workgroup_storage_size: (adapter_limits.max_compute_shared_memory_size
+                as u32)
+                .max(default_limits.max_compute_workgroup_storage_size),
+            max_texture_dimension_1d: adapter_limits
+                .max_image_1d_size
+                .max(default_limits.max_texture_dimension_1d),
+            max_texture_dimension_2d: adapter_limits
+                .max_image_2d_size
+                .max(default_limits.max_texture_dimension_2d),
+            max_texture_dimension_3d: adapter_limits
+                .max_image_3d_size
+                .max(default_limits.max_texture_dimension_3d),
+            max_texture_array_layers: (adapter_limits.max_image_array_layers as u32)
+                .max(default_limits.max_texture_array_layers),
+            max_vertex_buffers: (adapter_limits.max_vertex_input_bindings as u32)
+                .min(MAX_VERTEX_BUFFERS as u32)
+                .max(default_limits.max_vertex_buffers),
+            max_vertex_attributes: (adapter_limits.max_vertex_input_attributes as u32)
+                .max(default_limits.max_vertex_attributes),
+            max_vertex_buffer_array_stride: (adapter_limits.max_vertex_input_binding_stride as u32)
+                .max(default_limits.max_vertex_buffer_array_stride),
+            max_storage_buffer_binding_size: (adapter_limits
+                .max_storage_buffer_range
+                .min(u32::MAX as u64) as u32)
+                .max(default_limits.max_storage_buffer_binding_size),
+            max_inter_stage_shader_components: (adapter_limits
+                .max_vertex_output_components
+                .min(adapter_limits.max_fragment_input_components)
+                as u32)
+                .max(default_limits.max_inter_stage_shader_components),
+            // Lower alignments are "better", so these are reported as they are,
+            // only kept to non-zero powers of two that the default limits can satisfy.
+            min_uniform_buffer_offset_alignment: adapter_limits
+                .min_uniform_buffer_offset_alignment
+                .clamp(1, 256)
+                .next_power_of_two() as u32,
+            min_storage_buffer_offset_alignment: adapter_limits
+                .min_storage_buffer_offset_alignment
+                .clamp(1, 256)
+                .next_power_of_two() as u32,
         };
 
         Adapter {
             raw,
             features,
             limits,
-            life_guard: LifeGuard::new(),
+            life_guard: LifeGuard::new("<Adapter>"),
         }
     }
 }
 
+/// Returns true if all the device-local memory is host-visible, like on unified
+/// memory architectures. There has to be some device-local memory to begin with.
+fn is_unified_memory(mem_props: &hal::adapter::MemoryProperties) -> bool {
+    let mut device_local = mem_props
+        .memory_types
+        .iter()
+        .filter(|ty| {
+            ty.properties
+                .contains(hal::memory::Properties::DEVICE_LOCAL)
+        })
+        .peekable();
+    device_local.peek().is_some()
+        && device_local.all(|ty| ty.properties.contains(hal::memory::Properties::CPU_VISIBLE))
+}
+
+/// Checks that the requested limits are at least as "good" as the allowed ones.
+fn check_limits(requested: &wgt::Limits, allowed: &wgt::Limits) -> Result<(), RequestDeviceError> {
+    macro_rules! check {
+        (higher: $($higher:ident),*; lower: $($lower:ident),*) => {
+            $(
+                if requested.$higher > allowed.$higher {
+                    return Err(RequestDeviceError::LimitsExceeded(stringify!($higher)));
+                }
+            )*
+            $(
+                if requested.$lower < allowed.$lower {
+                    return Err(RequestDeviceError::LimitsExceeded(stringify!($lower)));
+                }
+            )*
+        };
+    }
+
+    check!(
+        higher:
+            max_bind_groups,
+            max_dynamic_uniform_buffers_per_pipeline_layout,
+            max_dynamic_storage_buffers_per_pipeline_layout,
+            max_sampled_textures_per_shader_stage,
+            max_samplers_per_shader_stage,
+            max_storage_buffers_per_shader_stage,
+            max_storage_textures_per_shader_stage,
+            max_uniform_buffers_per_shader_stage,
+            max_uniform_buffer_binding_size,
+            max_push_constant_size,
+            max_compute_workgroup_size_x,
+            max_compute_workgroup_size_y,
+            max_compute_workgroup_size_z,
+            max_compute_invocations_per_workgroup,
+            max_compute_workgroups_per_dimension,
+            max_compute_workgroup_storage_size,
+            max_texture_dimension_1d,
+            max_texture_dimension_2d,
+            max_texture_dimension_3d,
+            max_texture_array_layers,
+            max_vertex_buffers,
+            max_vertex_attributes,
+            max_vertex_buffer_array_stride,
+            max_storage_buffer_binding_size,
+            max_inter_stage_shader_components;
+        lower:
+            min_uniform_buffer_offset_alignment,
+            min_storage_buffer_offset_alignment
+    );
+    Ok(())
+}
+
 /// Metadata about a backend adapter.
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
@@ -253,8 +376,8 @@ pub enum RequestDeviceError {
     DeviceLost,
     #[error("device initialization failed due to implementation specific errors")]
     Internal,
-    #[error("some of the requested device limits are not supported")]
-    LimitsExceeded,
+    #[error("requested device limit `{0}` is not supported")]
+    LimitsExceeded(&'static str),
     #[error("device has no queue supporting graphics")]
     NoGraphicsQueue,
     #[error("not enough memory left")]
@@ -724,22 +847,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 })?;
 
             let limits = phd.limits();
-            assert_eq!(
-                0,
-                BIND_BUFFER_ALIGNMENT % limits.min_storage_buffer_offset_alignment,
-                "Adapter storage buffer offset alignment not compatible with WGPU"
-            );
-            assert_eq!(
-                0,
-                BIND_BUFFER_ALIGNMENT % limits.min_uniform_buffer_offset_alignment,
-                "Adapter uniform buffer offset alignment not compatible with WGPU"
-            );
-            if adapter.limits < desc.limits {
-                return Err(RequestDeviceError::LimitsExceeded);
-            }
+            check_limits(&desc.limits, &adapter.limits)?;
 
             let mem_props = phd.memory_properties();
-            if !desc.shader_validation {
+            if desc.shader_validation == wgt::ShaderValidation::Off {
                 tracing::warn!("Shader validation is disabled");
             }
             let private_features = PrivateFeatures {
@@ -753,6 +864,9 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     .format_properties(Some(hal::format::Format::D24UnormS8Uint))
                     .optimal_tiling
                     .contains(hal::format::ImageFeature::DEPTH_STENCIL_ATTACHMENT),
+                // Other backends don't implement executing secondary command buffers.
+                secondary_command_buffers: matches!(B::VARIANT, Backend::Vulkan | Backend::Metal),
+                unified_memory: is_unified_memory(&mem_props),
             };
 
             Device::new(
@@ -775,3 +889,80 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         Ok(id.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{check_limits, is_unified_memory, RequestDeviceError};
+    use hal::{
+        adapter::{MemoryProperties, MemoryType},
+        memory::Properties,
+    };
+
+    fn memory_properties(types: &[Properties]) -> MemoryProperties {
+        MemoryProperties {
+            memory_types: types
+                .iter()
+                .map(|&properties| MemoryType {
+                    properties,
+                    heap_index: 0,
+                })
+                .collect(),
+            memory_heaps: vec![1 << 30],
+        }
+    }
+
+    #[test]
+    fn unified_memory() {
+        let unified = memory_properties(&[
+            Properties::DEVICE_LOCAL | Properties::CPU_VISIBLE | Properties::COHERENT,
+            Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+        ]);
+        assert!(is_unified_memory(&unified));
+
+        let discrete = memory_properties(&[
+            Properties::DEVICE_LOCAL,
+            Properties::DEVICE_LOCAL | Properties::CPU_VISIBLE,
+            Properties::CPU_VISIBLE | Properties::COHERENT,
+        ]);
+        assert!(!is_unified_memory(&discrete));
+
+        // Having no device-local memory doesn't make it unified.
+        let host_only = memory_properties(&[Properties::CPU_VISIBLE | Properties::COHERENT]);
+        assert!(!is_unified_memory(&host_only));
+        assert!(!is_unified_memory(&memory_properties(&[])));
+    }
+
+    #[test]
+    fn limits() {
+        let allowed = wgt::Limits::default();
+        assert_eq!(check_limits(&allowed, &allowed), Ok(()));
+
+        let requested = wgt::Limits {
+            max_texture_dimension_2d: allowed.max_texture_dimension_2d * 2,
+            ..allowed.clone()
+        };
+        assert_eq!(
+            check_limits(&requested, &allowed),
+            Err(RequestDeviceError::LimitsExceeded(
+                "max_texture_dimension_2d"
+            ))
+        );
+
+        // Alignments are "better" when lower.
+        let requested = wgt::Limits {
+            min_uniform_buffer_offset_alignment: 512,
+            ..allowed.clone()
+        };
+        assert_eq!(check_limits(&requested, &allowed), Ok(()));
+        let requested = wgt::Limits {
+            min_storage_buffer_offset_alignment: 64,
+            ..allowed.clone()
+        };
+        assert_eq!(
+            check_limits(&requested, &allowed),
+            Err(RequestDeviceError::LimitsExceeded(
+                "min_storage_buffer_offset_alignment"
+            ))
+        );
+    }
+}
@@ -167,14 +167,17 @@ impl Drop for MultiRefCount {
 struct LifeGuard {
     ref_count: Option<RefCount>,
     submission_index: AtomicUsize,
+    /// Label of the resource, reported when it gets destroyed.
+    label: String,
 }
 
 impl LifeGuard {
-    fn new() -> Self {
+    fn new(label: &str) -> Self {
         let bx = Box::new(AtomicUsize::new(1));
         LifeGuard {
             ref_count: ptr::NonNull::new(Box::into_raw(bx)).map(RefCount),
             submission_index: AtomicUsize::new(0),
+            label: label.to_string(),
         }
     }
 
@@ -197,10 +200,12 @@ struct Stored<T> {
 
 #[derive(Clone, Copy, Debug)]
 struct PrivateFeatures {
-    shader_validation: bool,
+    shader_validation: wgt::ShaderValidation,
     anisotropic_filtering: bool,
     texture_d24: bool,
     texture_d24_s8: bool,
+    secondary_command_buffers: bool,
+    unified_memory: bool,
 }
 
 #[macro_export]
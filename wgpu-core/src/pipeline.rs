@@ -6,10 +6,15 @@ use crate::{
     binding_model::{CreateBindGroupLayoutError, CreatePipelineLayoutError},
     device::{DeviceError, RenderPassContext},
     id::{DeviceId, PipelineLayoutId, ShaderModuleId},
-    validation::StageError,
+    validation::{BindingError, SpirvInfo, StageError},
     Label, LifeGuard, RefCount, Stored,
 };
-use std::borrow::{Borrow, Cow};
+use std::{
+    borrow::{Borrow, Cow},
+    collections::BTreeMap,
+    error::Error as StdError,
+    fmt,
+};
 use thiserror::Error;
 use wgt::{BufferAddress, IndexFormat, InputStepMode};
 
@@ -18,6 +23,13 @@ use wgt::{BufferAddress, IndexFormat, InputStepMode};
 pub enum ShaderModuleSource<'a> {
     SpirV(Cow<'a, [u32]>),
     Wgsl(Cow<'a, str>),
+    /// GLSL code with a single `main` entry point for the given stage.
+    Glsl {
+        source: Cow<'a, str>,
+        stage: naga::ShaderStage,
+        /// Object-like macros defined before the code, by name.
+        defines: BTreeMap<String, String>,
+    },
     Naga(naga::Module),
 }
 
@@ -25,7 +37,697 @@ pub enum ShaderModuleSource<'a> {
 pub struct ShaderModule<B: hal::Backend> {
     pub(crate) raw: B::ShaderModule,
     pub(crate) device_id: Stored<DeviceId>,
+    /// Naga representation of the code. It's missing if SPIR-V code isn't parsed.
     pub(crate) module: Option<naga::Module>,
+    /// Whether pipelines using this module are validated against `module`.
+    pub(crate) validate: bool,
+    /// Whether the SPIR-V code was generated from `module`.
+    pub(crate) generated: bool,
+    pub(crate) spirv_info: SpirvInfo,
+}
+
+pub(crate) fn spv_writer_flags() -> naga::back::spv::WriterFlags {
+    if cfg!(debug_assertions) {
+        naga::back::spv::WriterFlags::DEBUG
+    } else {
+        naga::back::spv::WriterFlags::empty()
+    }
+}
+
+fn copy_arena<T>(
+    arena: &naga::Arena<T>,
+    mut copy: impl FnMut(naga::Handle<T>, &T) -> T,
+) -> naga::Arena<T> {
+    let mut result = naga::Arena::new();
+    for (handle, value) in arena.iter() {
+        result.append(copy(handle, value));
+    }
+    result
+}
+
+/// Copies `module`, taking the values of constants from `constant_inner`.
+///
+/// Naga modules can't be cloned, but all of their parts can.
+fn copy_module(
+    module: &naga::Module,
+    mut constant_inner: impl FnMut(naga::Handle<naga::Constant>, &naga::Constant) -> naga::ConstantInner,
+) -> naga::Module {
+    naga::Module {
+        header: module.header.clone(),
+        types: copy_arena(&module.types, |_, ty| naga::Type {
+            name: ty.name.clone(),
+            inner: ty.inner.clone(),
+        }),
+        constants: copy_arena(&module.constants, |handle, constant| naga::Constant {
+            name: constant.name.clone(),
+            specialization: constant.specialization,
+            inner: constant_inner(handle, constant),
+            ty: constant.ty,
+        }),
+        global_variables: copy_arena(&module.global_variables, |_, var| var.clone()),
+        functions: copy_arena(&module.functions, |_, function| naga::Function {
+            name: function.name.clone(),
+            parameter_types: function.parameter_types.clone(),
+            return_type: function.return_type,
+            global_usage: function.global_usage.clone(),
+            local_variables: copy_arena(&function.local_variables, |_, var| var.clone()),
+            expressions: copy_arena(&function.expressions, |_, expr| expr.clone()),
+            body: function.body.clone(),
+        }),
+        entry_points: module
+            .entry_points
+            .iter()
+            .map(|entry_point| naga::EntryPoint {
+                stage: entry_point.stage,
+                name: entry_point.name.clone(),
+                function: entry_point.function,
+            })
+            .collect(),
+    }
+}
+
+/// Pipeline-overridable constants applied to a shader stage.
+#[derive(Debug)]
+pub(crate) struct StageSpecialization {
+    pub(crate) specialization: hal::pso::Specialization<'static>,
+    /// SPIR-V code with the constants substituted, which needs a shader module
+    /// of its own.
+    pub(crate) spv: Option<Vec<u32>>,
+}
+
+impl<B: hal::Backend> ShaderModule<B> {
+    pub(crate) fn validation_module(&self) -> Option<&naga::Module> {
+        if self.validate {
+            self.module.as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Applies the given pipeline-overridable constants.
+    ///
+    /// Specialization constants of SPIR-V code are set at pipeline creation.
+    /// Naga doesn't generate those, so constants of generated code are
+    /// substituted in a copy of `module` instead, which then produces new SPIR-V code.
+    pub(crate) fn specialize(
+        &self,
+        constants: &PipelineConstants,
+    ) -> Result<StageSpecialization, PipelineConstantError> {
+        if constants.is_empty() {
+            return Ok(StageSpecialization {
+                specialization: hal::pso::Specialization::EMPTY,
+                spv: None,
+            });
+        }
+
+        match self.module {
+            Some(ref module) if self.generated => {
+                let mut substitutes = Vec::with_capacity(constants.len());
+                for (id, &value) in constants.iter() {
+                    let (handle, constant) = module
+                        .constants
+                        .iter()
+                        .find(|(_, constant)| match *id {
+                            PipelineConstantId::Numeric(number) => {
+                                constant.specialization == Some(number)
+                            }
+                            PipelineConstantId::Named(ref name) => {
+                                constant.name.as_ref() == Some(name)
+                            }
+                        })
+                        .ok_or_else(|| PipelineConstantError::Missing(id.clone()))?;
+                    let kind = match module.types[constant.ty].inner {
+                        naga::TypeInner::Scalar { kind, .. } => Some(kind),
+                        _ => None,
+                    };
+                    let inner = match (kind, value) {
+                        (Some(naga::ScalarKind::Bool), PipelineConstantValue::Bool(v)) => {
+                            naga::ConstantInner::Bool(v)
+                        }
+                        (Some(naga::ScalarKind::Sint), PipelineConstantValue::Sint(v)) => {
+                            naga::ConstantInner::Sint(v as i64)
+                        }
+                        (Some(naga::ScalarKind::Uint), PipelineConstantValue::Uint(v)) => {
+                            naga::ConstantInner::Uint(v as u64)
+                        }
+                        (Some(naga::ScalarKind::Float), PipelineConstantValue::Float(v)) => {
+                            naga::ConstantInner::Float(v as f64)
+                        }
+                        _ => {
+                            return Err(PipelineConstantError::WrongType {
+                                id: id.clone(),
+                                value,
+                            })
+                        }
+                    };
+                    substitutes.push((handle, inner));
+                }
+
+                let specialized = copy_module(module, |handle, constant| {
+                    substitutes
+                        .iter()
+                        .find(|&&(substituted, _)| substituted == handle)
+                        .map_or_else(|| constant.inner.clone(), |(_, inner)| inner.clone())
+                });
+                let spv = naga::back::spv::Writer::new(&specialized.header, spv_writer_flags())
+                    .write(&specialized);
+
+                Ok(StageSpecialization {
+                    specialization: hal::pso::Specialization::EMPTY,
+                    spv: Some(spv),
+                })
+            }
+            _ => {
+                let mut spec_constants = Vec::with_capacity(constants.len());
+                let mut data = Vec::new();
+                for (id, &value) in constants.iter() {
+                    let constant = self
+                        .spirv_info
+                        .spec_constants
+                        .iter()
+                        .find(|constant| match *id {
+                            PipelineConstantId::Numeric(number) => constant.id == number,
+                            PipelineConstantId::Named(ref name) => {
+                                constant.name.as_ref() == Some(name)
+                            }
+                        })
+                        .ok_or_else(|| PipelineConstantError::Missing(id.clone()))?;
+                    // Specialization data is laid out like the constant in memory,
+                    // and booleans take 32 bits.
+                    let bits = match (constant.kind, constant.width, value) {
+                        (naga::ScalarKind::Bool, _, PipelineConstantValue::Bool(v)) => v as u32,
+                        (naga::ScalarKind::Sint, 4, PipelineConstantValue::Sint(v)) => v as u32,
+                        (naga::ScalarKind::Uint, 4, PipelineConstantValue::Uint(v)) => v,
+                        (naga::ScalarKind::Float, 4, PipelineConstantValue::Float(v)) => {
+                            v.to_bits()
+                        }
+                        _ => {
+                            return Err(PipelineConstantError::WrongType {
+                                id: id.clone(),
+                                value,
+                            })
+                        }
+                    };
+                    let offset = data.len() as u16;
+                    data.extend_from_slice(&bits.to_ne_bytes());
+                    spec_constants.push(hal::pso::SpecializationConstant {
+                        id: constant.id,
+                        range: offset..offset + 4,
+                    });
+                }
+
+                Ok(StageSpecialization {
+                    specialization: hal::pso::Specialization {
+                        constants: Cow::Owned(spec_constants),
+                        data: Cow::Owned(data),
+                    },
+                    spv: None,
+                })
+            }
+        }
+    }
+}
+
+/// Reflection information about a shader module.
+#[derive(Clone, Debug)]
+pub struct ShaderModuleReflection {
+    pub entry_points: Vec<EntryPointReflection>,
+}
+
+/// Reflection information about an entry point of a shader module.
+#[derive(Clone, Debug)]
+pub struct EntryPointReflection {
+    pub name: String,
+    pub stage: wgt::ShaderStage,
+    /// Size of the workgroup of compute entry points, if it's known, with the
+    /// default values of specialization constants.
+    pub workgroup_size: Option<[u32; 3]>,
+    /// Resource bindings used by the entry point.
+    pub bindings: Vec<BindingReflection>,
+    /// Inputs with a location, e.g. vertex attributes.
+    pub inputs: Vec<InterfaceReflection>,
+    /// Outputs with a location, e.g. fragment outputs.
+    pub outputs: Vec<InterfaceReflection>,
+}
+
+/// Resource binding used by an entry point.
+#[derive(Clone, Debug)]
+pub struct BindingReflection {
+    pub set: u32,
+    pub binding: u32,
+    pub name: Option<String>,
+    /// Binding type derived from the shader, as used for implicit pipeline layouts.
+    pub ty: wgt::BindingType,
+    pub usage: naga::GlobalUse,
+}
+
+/// Input or output of an entry point.
+#[derive(Clone, Debug)]
+pub struct InterfaceReflection {
+    pub location: wgt::ShaderLocation,
+    pub name: Option<String>,
+    pub ty: naga::TypeInner,
+    /// Vertex format matching the type, for vertex inputs.
+    pub vertex_format: Option<wgt::VertexFormat>,
+}
+
+#[derive(Clone, Debug, Error)]
+pub enum ShaderReflectionError {
+    #[error("shader module is invalid")]
+    InvalidModule,
+    #[error("shader module has no reflection information, because its SPIR-V code wasn't parsed")]
+    NotParsed,
+    #[error(
+        "unable to derive the type of global binding at index {binding} in set {set}: {error}"
+    )]
+    Binding {
+        set: u32,
+        binding: u32,
+        error: BindingError,
+    },
+}
+
+/// Position in the source code of a shader, both 1-based.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// Error produced by a shader frontend.
+#[derive(Clone, Debug)]
+pub struct ShaderParseError {
+    /// Name of the source language, e.g. "WGSL".
+    pub language: &'static str,
+    pub message: String,
+    /// Where the error was found, if the language is textual.
+    pub location: Option<SourceLocation>,
+    /// The source line at `location`.
+    pub snippet: Option<String>,
+}
+
+impl ShaderParseError {
+    pub(crate) fn from_wgsl(error: naga::front::wgsl::ParseError, code: &str) -> Self {
+        // Naga reports the number of lines preceding the error, and the
+        // 0-based column in the last of them.
+        let (line, column) = error.pos;
+        let line = line.max(1);
+        ShaderParseError {
+            language: "WGSL",
+            message: error.error.to_string(),
+            location: Some(SourceLocation {
+                line: line as u32,
+                column: column as u32 + 1,
+            }),
+            snippet: code.lines().nth(line - 1).map(String::from),
+        }
+    }
+
+    /// Creates an error from the message of a GLSL frontend error.
+    ///
+    /// Naga's GLSL errors can't be inspected, but their messages include the
+    /// `TokenMetadata` of the offending token: a 0-based line, and the range
+    /// of its bytes in that line.
+    pub(crate) fn from_glsl(message: String, code: &str) -> Self {
+        const METADATA: &str = "TokenMetadata { line: ";
+        let position = message.find(METADATA).and_then(|start| {
+            let rest = &message[start + METADATA.len()..];
+            let (line, rest) = rest.split_at(rest.find(',')?);
+            let rest = rest.strip_prefix(", chars: ")?;
+            let column = &rest[..rest.find("..")?];
+            Some((
+                start,
+                line.parse::<usize>().ok()?,
+                column.parse::<u32>().ok()?,
+            ))
+        });
+        match position {
+            Some((start, line, column)) => {
+                let message = message[..start].trim_end_matches(&['(', ' '][..]);
+                ShaderParseError {
+                    language: "GLSL",
+                    message: message.strip_suffix(" at").unwrap_or(message).to_string(),
+                    location: Some(SourceLocation {
+                        line: line as u32 + 1,
+                        column: column + 1,
+                    }),
+                    snippet: code.lines().nth(line).map(String::from),
+                }
+            }
+            None => ShaderParseError {
+                language: "GLSL",
+                message,
+                location: None,
+                snippet: None,
+            },
+        }
+    }
+
+    pub(crate) fn from_spirv(error: naga::front::spv::Error) -> Self {
+        ShaderParseError {
+            language: "SPIR-V",
+            message: match error {
+                naga::front::spv::Error::UnsupportedCapability(cap) => {
+                    format!("unsupported capability {:?}", cap)
+                }
+                naga::front::spv::Error::UnknownCapability(word) => {
+                    format!("unknown capability {}", word)
+                }
+                naga::front::spv::Error::UnsupportedExtension(ref name) => {
+                    format!("unsupported extension {}", name)
+                }
+                _ => format!("{:?}", error),
+            },
+            location: None,
+            snippet: None,
+        }
+    }
+}
+
+impl fmt::Display for ShaderParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to parse {}: {}", self.language, self.message)?;
+        if let Some(loc) = self.location {
+            write!(f, "\n  --> {}:{}", loc.line, loc.column)?;
+            if let Some(ref snippet) = self.snippet {
+                let gutter = loc.line.to_string().len();
+                write!(f, "\n{:w$} |", "", w = gutter)?;
+                write!(f, "\n{} | {}", loc.line, snippet)?;
+                write!(
+                    f,
+                    "\n{:w$} | {:c$}^",
+                    "",
+                    "",
+                    w = gutter,
+                    c = loc.column as usize - 1
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StdError for ShaderParseError {}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Splits the identifier at the start of `text` from the rest.
+fn split_identifier(text: &str) -> (&str, &str) {
+    text.split_at(text.find(|c| !is_identifier_char(c)).unwrap_or(text.len()))
+}
+
+/// Evaluates the expression of an `#if` or `#elif` directive.
+///
+/// Integer literals, `defined`, and the logical, equality and relational
+/// operators are supported. Macros have to expand to an integer literal,
+/// and other identifiers are 0.
+fn eval_glsl_condition(
+    expression: &str,
+    macros: &BTreeMap<&str, &str>,
+) -> Result<bool, &'static str> {
+    const OPERATORS: &[&str] = &["&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "(", ")"];
+
+    fn parse_integer(text: &str) -> Option<i64> {
+        text.trim_end_matches(&['u', 'U'][..]).parse().ok()
+    }
+
+    struct Parser<'a, 'm> {
+        tokens: Vec<&'a str>,
+        position: usize,
+        macros: &'m BTreeMap<&'a str, &'a str>,
+    }
+
+    impl<'a> Parser<'a, '_> {
+        fn next_if(&mut self, token: &str) -> bool {
+            let found = self.tokens.get(self.position) == Some(&token);
+            if found {
+                self.position += 1;
+            }
+            found
+        }
+
+        fn next(&mut self) -> Result<&'a str, &'static str> {
+            let token = self
+                .tokens
+                .get(self.position)
+                .ok_or("unexpected end of the condition")?;
+            self.position += 1;
+            Ok(token)
+        }
+
+        fn or(&mut self) -> Result<i64, &'static str> {
+            let mut value = self.and()?;
+            while self.next_if("||") {
+                let right = self.and()?;
+                value = (value != 0 || right != 0) as i64;
+            }
+            Ok(value)
+        }
+
+        fn and(&mut self) -> Result<i64, &'static str> {
+            let mut value = self.comparison()?;
+            while self.next_if("&&") {
+                let right = self.comparison()?;
+                value = (value != 0 && right != 0) as i64;
+            }
+            Ok(value)
+        }
+
+        fn comparison(&mut self) -> Result<i64, &'static str> {
+            let mut value = self.unary()?;
+            loop {
+                let compare: fn(&i64, &i64) -> bool = match self.tokens.get(self.position) {
+                    Some(&"==") => i64::eq,
+                    Some(&"!=") => i64::ne,
+                    Some(&"<=") => i64::le,
+                    Some(&">=") => i64::ge,
+                    Some(&"<") => i64::lt,
+                    Some(&">") => i64::gt,
+                    _ => return Ok(value),
+                };
+                self.position += 1;
+                let right = self.unary()?;
+                value = compare(&value, &right) as i64;
+            }
+        }
+
+        fn unary(&mut self) -> Result<i64, &'static str> {
+            if self.next_if("!") {
+                return Ok((self.unary()? == 0) as i64);
+            }
+            match self.next()? {
+                "(" => {
+                    let value = self.or()?;
+                    if !self.next_if(")") {
+                        return Err("expected `)`");
+                    }
+                    Ok(value)
+                }
+                "defined" => {
+                    let parenthesized = self.next_if("(");
+                    let name = self.next()?;
+                    if parenthesized && !self.next_if(")") {
+                        return Err("expected `)`");
+                    }
+                    Ok(self.macros.contains_key(name) as i64)
+                }
+                token if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                    parse_integer(token).ok_or("invalid integer in the condition")
+                }
+                token if token.starts_with(is_identifier_char) => match self.macros.get(token) {
+                    Some(value) => {
+                        parse_integer(value).ok_or("macro in the condition isn't an integer")
+                    }
+                    None => Ok(0),
+                },
+                _ => Err("unexpected token in the condition"),
+            }
+        }
+    }
+
+    let mut tokens = Vec::new();
+    let mut rest = expression
+        .split("//")
+        .next()
+        .unwrap_or_default()
+        .trim_start();
+    while !rest.is_empty() {
+        let (token, tail) = if rest.starts_with(is_identifier_char) {
+            split_identifier(rest)
+        } else {
+            let operator = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or("unsupported operator in the condition")?;
+            rest.split_at(operator.len())
+        };
+        tokens.push(token);
+        rest = tail.trim_start();
+    }
+
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        macros,
+    };
+    let value = parser.or()?;
+    if parser.position != parser.tokens.len() {
+        return Err("unexpected token in the condition");
+    }
+    Ok(value != 0)
+}
+
+/// Conditional section of GLSL code, started by `#if`, `#ifdef` or `#ifndef`.
+struct GlslConditional {
+    /// Whether the code around the section is kept.
+    outer_active: bool,
+    /// Whether the current branch is kept.
+    active: bool,
+    /// Whether one of the branches was kept already.
+    taken: bool,
+    has_else: bool,
+    location: SourceLocation,
+    snippet: String,
+}
+
+/// Handles the preprocessor directives that Naga's GLSL frontend doesn't know.
+///
+/// Object-like macros from `defines` and `#define` directives are expanded once,
+/// without rescanning, and `#undef` removes them. Conditional sections are kept or
+/// blanked according to `#if`, `#ifdef`, `#ifndef`, `#elif`, `#else` and `#endif`.
+/// `#version` is left to the frontend, `#extension`, `#pragma` and `#line` are blanked,
+/// and any other directive is an error.
+/// Line numbers are preserved.
+pub(crate) fn preprocess_glsl(
+    code: &str,
+    defines: &BTreeMap<String, String>,
+) -> Result<String, ShaderParseError> {
+    let mut macros = defines
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect::<BTreeMap<_, _>>();
+    let mut conditionals = Vec::<GlslConditional>::new();
+    let mut output = String::with_capacity(code.len());
+
+    for (index, line) in code.lines().enumerate() {
+        let active = conditionals.last().map_or(true, |cond| cond.active);
+        let trimmed = line.trim_start();
+        if let Some(directive) = trimmed.strip_prefix('#') {
+            let location = SourceLocation {
+                line: index as u32 + 1,
+                column: (line.len() - trimmed.len()) as u32 + 1,
+            };
+            let error = |message: &str| ShaderParseError {
+                language: "GLSL",
+                message: message.to_string(),
+                location: Some(location),
+                snippet: Some(line.to_string()),
+            };
+            let (keyword, argument) = split_identifier(directive.trim_start());
+            let argument = argument.trim();
+            let condition_name = || {
+                let (name, rest) = split_identifier(argument);
+                let rest = rest.trim_start();
+                if name.is_empty() || !(rest.is_empty() || rest.starts_with("//")) {
+                    Err(error("expected a macro name"))
+                } else {
+                    Ok(name)
+                }
+            };
+            match keyword {
+                "if" | "ifdef" | "ifndef" => {
+                    let condition = if !active {
+                        false
+                    } else if keyword == "if" {
+                        eval_glsl_condition(argument, &macros).map_err(error)?
+                    } else {
+                        macros.contains_key(condition_name()?) == (keyword == "ifdef")
+                    };
+                    conditionals.push(GlslConditional {
+                        outer_active: active,
+                        active: condition,
+                        taken: condition,
+                        has_else: false,
+                        location,
+                        snippet: line.to_string(),
+                    });
+                }
+                "elif" | "else" => {
+                    let cond = match conditionals.last_mut() {
+                        Some(cond) if !cond.has_else => cond,
+                        _ => return Err(error("unexpected conditional branch")),
+                    };
+                    let condition = if !cond.outer_active || cond.taken {
+                        false
+                    } else if keyword == "elif" {
+                        eval_glsl_condition(argument, &macros).map_err(error)?
+                    } else {
+                        true
+                    };
+                    cond.has_else = keyword == "else";
+                    cond.active = condition;
+                    cond.taken |= condition;
+                }
+                "endif" => {
+                    if conditionals.pop().is_none() {
+                        return Err(error("unexpected `#endif`"));
+                    }
+                }
+                // Null directive.
+                "" => {}
+                // Naga doesn't handle these, and they don't affect the generated module,
+                // so they are blanked out, keeping the line numbers intact.
+                "extension" | "pragma" | "line" => {}
+                _ if !active => {}
+                "version" => output.push_str(line),
+                "define" => {
+                    let (name, value) = split_identifier(argument);
+                    if name.is_empty()
+                        || name.starts_with(|c: char| c.is_ascii_digit())
+                        || value.starts_with('(')
+                    {
+                        return Err(error("only object-like macros are supported"));
+                    }
+                    let value = value.split("//").next().unwrap_or_default().trim();
+                    macros.insert(name, value);
+                }
+                "undef" => {
+                    macros.remove(condition_name()?);
+                }
+                _ => return Err(error("unsupported preprocessor directive")),
+            }
+        } else if active {
+            let mut rest = line;
+            while let Some(start) = rest.find(is_identifier_char) {
+                output.push_str(&rest[..start]);
+                rest = &rest[start..];
+                let end = rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len());
+                let word = &rest[..end];
+                // Words starting with a digit are numbers.
+                match macros.get(word) {
+                    Some(value) if !word.starts_with(|c: char| c.is_ascii_digit()) => {
+                        output.push_str(value)
+                    }
+                    _ => output.push_str(word),
+                }
+                rest = &rest[end..];
+            }
+            output.push_str(rest);
+        }
+        output.push('\n');
+    }
+
+    match conditionals.pop() {
+        Some(cond) => Err(ShaderParseError {
+            language: "GLSL",
+            message: "conditional section isn't terminated by `#endif`".to_string(),
+            location: Some(cond.location),
+            snippet: Some(cond.snippet),
+        }),
+        None => Ok(output),
+    }
 }
 
 #[derive(Clone, Debug, Error)]
@@ -33,9 +735,48 @@ pub enum CreateShaderModuleError {
     #[error(transparent)]
     Device(#[from] DeviceError),
     #[error(transparent)]
+    Parsing(#[from] ShaderParseError),
+    #[error(transparent)]
     Validation(#[from] naga::proc::ValidationError),
 }
 
+/// Identifies a pipeline-overridable constant of a shader.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize))]
+#[cfg_attr(feature = "replay", derive(serde::Deserialize))]
+pub enum PipelineConstantId {
+    /// The `SpecId` of a SPIR-V specialization constant, or the
+    /// specialization ID of a Naga constant.
+    Numeric(u32),
+    /// The name of the constant.
+    Named(String),
+}
+
+/// Value of a pipeline-overridable constant. It has to match the type of the constant.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize))]
+#[cfg_attr(feature = "replay", derive(serde::Deserialize))]
+pub enum PipelineConstantValue {
+    Bool(bool),
+    Sint(i32),
+    Uint(u32),
+    Float(f32),
+}
+
+/// Values of the pipeline-overridable constants of a stage.
+pub type PipelineConstants = BTreeMap<PipelineConstantId, PipelineConstantValue>;
+
+#[derive(Clone, Debug, Error)]
+pub enum PipelineConstantError {
+    #[error("shader has no overridable constant {0:?}")]
+    Missing(PipelineConstantId),
+    #[error("value {value:?} doesn't match the type of constant {id:?}")]
+    WrongType {
+        id: PipelineConstantId,
+        value: PipelineConstantValue,
+    },
+}
+
 /// Describes a programmable pipeline stage.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
@@ -46,6 +787,9 @@ pub struct ProgrammableStageDescriptor<'a> {
     /// The name of the entry point in the compiled shader. There must be a function that returns
     /// void with this name in the shader.
     pub entry_point: Cow<'a, str>,
+    /// Values overriding the constants of the shader, for this pipeline only.
+    #[cfg_attr(any(feature = "trace", feature = "replay"), serde(default))]
+    pub constants: Cow<'a, PipelineConstants>,
 }
 
 /// Number of implicit bind groups derived at pipeline creation.
@@ -70,6 +814,10 @@ pub enum ImplicitLayoutError {
 pub struct ComputePipelineDescriptor<'a> {
     pub label: Label<'a>,
     /// The layout of bind groups for this pipeline.
+    ///
+    /// If it's `None`, the layout is derived from the shaders, which have to
+    /// declare the format of their storage textures. Naga doesn't write
+    /// these formats to SPIR-V, so the modules it generates need an explicit layout.
     pub layout: Option<PipelineLayoutId>,
     /// The compiled compute stage and its entry point.
     pub compute_stage: ProgrammableStageDescriptor<'a>,
@@ -132,6 +880,10 @@ pub struct VertexStateDescriptor<'a> {
 pub struct RenderPipelineDescriptor<'a> {
     pub label: Label<'a>,
     /// The layout of bind groups for this pipeline.
+    ///
+    /// If it's `None`, the layout is derived from the shaders, which have to
+    /// declare the format of their storage textures. Naga doesn't write
+    /// these formats to SPIR-V, so the modules it generates need an explicit layout.
     pub layout: Option<PipelineLayoutId>,
     /// The compiled vertex stage and its entry point.
     pub vertex_stage: ProgrammableStageDescriptor<'a>,
@@ -181,6 +933,18 @@ pub enum CreateRenderPipelineError {
         location: wgt::ShaderLocation,
         offset: BufferAddress,
     },
+    #[error("pipeline uses {given} vertex buffers, exceeding the limit of {limit}")]
+    TooManyVertexBuffers { given: u32, limit: u32 },
+    #[error("pipeline uses {given} vertex attributes, exceeding the limit of {limit}")]
+    TooManyVertexAttributes { given: u32, limit: u32 },
+    #[error("vertex buffer {index} stride {given} exceeds the limit of {limit}")]
+    VertexStrideTooLarge {
+        index: u32,
+        given: BufferAddress,
+        limit: u32,
+    },
+    #[error("vertex stage outputs {given} components, exceeding the limit of {limit}")]
+    TooManyInterStageComponents { given: u32, limit: u32 },
     #[error("missing required device features {0:?}")]
     MissingFeature(wgt::Features),
     #[error("error in stage {flag:?}: {error}")]
@@ -216,3 +980,134 @@ impl<B: hal::Backend> Borrow<RefCount> for RenderPipeline<B> {
         self.life_guard.ref_count.as_ref().unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{copy_module, preprocess_glsl, ShaderParseError};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn specialized_copy() {
+        let module =
+            naga::front::wgsl::parse_str("const scale : f32 = 1.0;\nconst count : i32 = 4;\n")
+                .unwrap();
+        let copy = copy_module(&module, |_, constant| match constant.inner {
+            naga::ConstantInner::Float(_) => naga::ConstantInner::Float(2.0),
+            ref other => other.clone(),
+        });
+        let values = |module: &naga::Module| {
+            module
+                .constants
+                .iter()
+                .map(|(_, constant)| constant.inner.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values(&copy),
+            [
+                naga::ConstantInner::Float(2.0),
+                naga::ConstantInner::Sint(4)
+            ]
+        );
+        // The original module is left alone.
+        assert_eq!(
+            values(&module),
+            [
+                naga::ConstantInner::Float(1.0),
+                naga::ConstantInner::Sint(4)
+            ]
+        );
+        assert_eq!(copy.types.len(), module.types.len());
+    }
+
+    #[test]
+    fn wgsl_parse_error() {
+        let code = "const a : i32 = 2;\nconst b : i32 = ;\n";
+        let err = naga::front::wgsl::parse_str(code).unwrap_err();
+        let err = ShaderParseError::from_wgsl(err, code);
+        assert_eq!(err.location.map(|loc| loc.line), Some(2));
+        assert_eq!(err.snippet.as_deref(), Some("const b : i32 = ;"));
+    }
+
+    #[test]
+    fn glsl_parse_error() {
+        let code = "#version 450\nvoid main() {\n    o_color = 1.0;\n}\n";
+        let err =
+            naga::front::glsl_new::parse_str(code, "main".to_string(), naga::ShaderStage::Fragment)
+                .unwrap_err();
+        let err = ShaderParseError::from_glsl(err.kind.to_string(), code);
+        assert_eq!(err.message, "Unknown variable o_color");
+        assert_eq!(
+            err.location,
+            Some(super::SourceLocation { line: 3, column: 5 })
+        );
+        assert_eq!(err.snippet.as_deref(), Some("    o_color = 1.0;"));
+    }
+
+    #[test]
+    fn glsl_conditionals() {
+        let mut defines = BTreeMap::new();
+        defines.insert("LEVEL".to_string(), "2".to_string());
+        let code = "#ifdef LEVEL\na\n#ifndef LEVEL\nb\n#else\nc\n#endif\n#elif 1\nd\n#endif\n#if LEVEL > 1 && !defined(NONE) || NONE\ne\n#elif LEVEL\nf\n#else\ng\n#endif\n#undef LEVEL\n#if defined LEVEL\nh\n#endif\n";
+        let output = preprocess_glsl(code, &defines).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            [
+                "", "a", "", "", "", "c", "", "", "", "", "", "e", "", "", "", "", "", "", "", "",
+                ""
+            ]
+        );
+
+        // Directives in skipped sections don't matter.
+        assert_eq!(
+            preprocess_glsl("#if 0\n#pragma x\n#define A 1\n#endif\nA\n", &defines).unwrap(),
+            "\n\n\n\nA\n"
+        );
+
+        let err = preprocess_glsl("#if 1\n#else\n#elif 1\n#endif\n", &defines).unwrap_err();
+        assert_eq!(err.location.map(|loc| loc.line), Some(3));
+        let err = preprocess_glsl("#endif\n", &defines).unwrap_err();
+        assert_eq!(err.location.map(|loc| loc.line), Some(1));
+        let err = preprocess_glsl("#if LEVEL + 1\n#endif\n", &defines).unwrap_err();
+        assert_eq!(err.message, "unsupported operator in the condition");
+    }
+
+    #[test]
+    fn glsl_defines() {
+        let code = "#version 450\n#define SCALE 2.0 // factor\nlayout(location = 0) out vec4 o_color;\nvoid main() { o_color = vec4(COUNT, SCALE, 0.0, 1.0); }\n";
+        let mut defines = BTreeMap::new();
+        defines.insert("COUNT".to_string(), "4.0".to_string());
+        let output = preprocess_glsl(code, &defines).unwrap();
+        assert_eq!(
+            output,
+            "#version 450\n\nlayout(location = 0) out vec4 o_color;\nvoid main() { o_color = vec4(4.0, 2.0, 0.0, 1.0); }\n"
+        );
+        naga::front::glsl_new::parse_str(&output, "main".to_string(), naga::ShaderStage::Fragment)
+            .unwrap();
+
+        // Parts of numbers are not expanded.
+        defines.insert("e1".to_string(), "x".to_string());
+        assert_eq!(preprocess_glsl("1e1 e1", &defines).unwrap(), "1e1 x\n");
+
+        let err = preprocess_glsl("void main() {}\n  #ifdef A\n", &defines).unwrap_err();
+        assert_eq!(
+            err.location,
+            Some(super::SourceLocation { line: 2, column: 3 })
+        );
+    }
+
+    #[test]
+    fn glsl_ignored_directives() {
+        let code = "#version 450\n#extension GL_ARB_separate_shader_objects : enable\n#pragma optimize(on)\n#line 10\nlayout(location = 0) out vec4 o_color;\nvoid main() { o_color = vec4(1.0); }\n";
+        let output = preprocess_glsl(code, &BTreeMap::new()).unwrap();
+        assert_eq!(
+            output,
+            "#version 450\n\n\n\nlayout(location = 0) out vec4 o_color;\nvoid main() { o_color = vec4(1.0); }\n"
+        );
+        naga::front::glsl_new::parse_str(&output, "main".to_string(), naga::ShaderStage::Fragment)
+            .unwrap();
+
+        let err = preprocess_glsl("#include \"common.glsl\"\n", &BTreeMap::new()).unwrap_err();
+        assert_eq!(err.message, "unsupported preprocessor directive");
+    }
+}
@@ -15,7 +15,9 @@ use thiserror::Error;
 
 use std::{
     borrow::Borrow,
+    fmt,
     num::{NonZeroU32, NonZeroU8},
+    ops::Range,
     ptr::NonNull,
 };
 
@@ -83,16 +85,26 @@ pub(crate) enum BufferMapState<B: hal::Backend> {
     /// Mapped at creation.
     Init {
         ptr: NonNull<u8>,
+        /// Sub-ranges handed out by `buffer_get_mapped_range`.
+        mapped_ranges: Vec<Range<wgt::BufferAddress>>,
         stage_buffer: B::Buffer,
         stage_memory: MemoryBlock<B>,
     },
     /// Waiting for GPU to be done before mapping
     Waiting(BufferPendingMapping),
+    /// Mapped for the whole lifetime of the buffer, while the GPU is using it.
+    /// The host accesses are synchronized by the user, with explicit flushes.
+    Persistent {
+        ptr: NonNull<u8>,
+        host: crate::device::HostMap,
+    },
     /// Mapped
     Active {
         ptr: NonNull<u8>,
         sub_range: hal::buffer::SubRange,
         host: crate::device::HostMap,
+        /// Sub-ranges handed out by `buffer_get_mapped_range`.
+        mapped_ranges: Vec<Range<wgt::BufferAddress>>,
     },
     /// Not mapped
     Idle,
@@ -103,11 +115,53 @@ unsafe impl<B: hal::Backend> Sync for BufferMapState<B> {}
 
 pub type BufferMapCallback = unsafe extern "C" fn(status: BufferMapAsyncStatus, userdata: *mut u8);
 
+/// Callback fired once a buffer mapping is resolved.
+pub enum BufferMapClosure {
+    /// C callback, receiving a status code and the user data.
+    C {
+        callback: BufferMapCallback,
+        user_data: *mut u8,
+    },
+    /// Rust closure, receiving the error if the mapping failed.
+    Rust(Box<dyn FnOnce(Result<(), BufferAccessError>) + Send + 'static>),
+}
+
+impl BufferMapClosure {
+    pub(crate) fn call(self, result: Result<(), BufferAccessError>) {
+        match self {
+            BufferMapClosure::C {
+                callback,
+                user_data,
+            } => {
+                let status = match result {
+                    Ok(()) => BufferMapAsyncStatus::Success,
+                    Err(BufferAccessError::Device(DeviceError::Lost)) => {
+                        BufferMapAsyncStatus::ContextLost
+                    }
+                    Err(_) => BufferMapAsyncStatus::Error,
+                };
+                unsafe { callback(status, user_data) }
+            }
+            BufferMapClosure::Rust(closure) => closure(result),
+        }
+    }
+}
+
+impl fmt::Debug for BufferMapClosure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BufferMapClosure::C { user_data, .. } => {
+                write!(f, "BufferMapClosure::C {{ user_data: {:?} }}", user_data)
+            }
+            BufferMapClosure::Rust(_) => write!(f, "BufferMapClosure::Rust"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct BufferMapOperation {
     pub host: crate::device::HostMap,
-    pub callback: BufferMapCallback,
-    pub user_data: *mut u8,
+    pub callback: BufferMapClosure,
 }
 
 //TODO: clarify if/why this is needed here
@@ -115,11 +169,9 @@ unsafe impl Send for BufferMapOperation {}
 unsafe impl Sync for BufferMapOperation {}
 
 impl BufferMapOperation {
-    pub(crate) fn call_error(self) {
-        tracing::error!("wgpu_buffer_map_async failed: buffer mapping is pending");
-        unsafe {
-            (self.callback)(BufferMapAsyncStatus::Error, self.user_data);
-        }
+    pub(crate) fn call_error(self, error: BufferAccessError) {
+        tracing::error!("wgpu_buffer_map_async failed: {}", error);
+        self.callback.call(Err(error));
     }
 }
 
@@ -131,12 +183,28 @@ pub enum BufferAccessError {
     InvalidBuffer,
     #[error("buffer is already mapped")]
     AlreadyMapped,
+    #[error("buffer map is pending")]
+    MapAlreadyPending,
     #[error(transparent)]
     MissingBufferUsage(#[from] MissingBufferUsageError),
     #[error("buffer is not mapped")]
     NotMapped,
+    #[error("persistent mapping requires unified memory and `Features::MAPPABLE_PRIMARY_BUFFERS`")]
+    PersistentMappingUnsupported,
     #[error("buffer map range does not respect `COPY_BUFFER_ALIGNMENT`")]
     UnalignedRange,
+    #[error("mapped range offset {0} does not respect `MAP_ALIGNMENT`")]
+    UnalignedOffset(wgt::BufferAddress),
+    #[error("mapped range {requested:?} is outside of the mapped region {mapped:?}")]
+    OutOfBoundsRange {
+        requested: Range<wgt::BufferAddress>,
+        mapped: Range<wgt::BufferAddress>,
+    },
+    #[error("mapped range {requested:?} overlaps with the already obtained range {existing:?}")]
+    OverlappingRange {
+        requested: Range<wgt::BufferAddress>,
+        existing: Range<wgt::BufferAddress>,
+    },
 }
 
 impl From<hal::device::MapError> for BufferAccessError {
@@ -213,10 +281,23 @@ pub struct Texture<B: hal::Backend> {
     pub(crate) life_guard: LifeGuard,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureErrorDimension {
+    X,
+    Y,
+    Z,
+}
+
 #[derive(Clone, Debug, Error)]
 pub enum TextureDimensionError {
     #[error("too many layers ({0}) for texture array")]
     TooManyLayers(u32),
+    #[error("dimension {dim:?} of size {given} exceeds the limit of {limit}")]
+    LimitExceeded {
+        dim: TextureErrorDimension,
+        given: u32,
+        limit: u32,
+    },
     #[error("1D textures must have height set to 1")]
     InvalidHeight,
     #[error("sample count {0} is invalid")]
@@ -227,8 +308,6 @@ pub enum TextureDimensionError {
 pub enum CreateTextureError {
     #[error(transparent)]
     Device(#[from] DeviceError),
-    #[error("D24Plus textures cannot be copied")]
-    CannotCopyD24Plus,
     #[error(transparent)]
     InvalidDimension(#[from] TextureDimensionError),
     #[error("texture descriptor mip level count ({0}) must be less than `MAX_MIP_LEVELS`")]
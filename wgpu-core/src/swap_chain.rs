@@ -183,7 +183,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         layers: 0..1,
                         levels: 0..1,
                     },
-                    life_guard: LifeGuard::new(),
+                    life_guard: LifeGuard::new("<SwapChainView>"),
                 };
 
                 let ref_count = view.life_guard.add_ref();
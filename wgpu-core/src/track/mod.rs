@@ -7,6 +7,7 @@ mod range;
 mod texture;
 
 use crate::{
+    command::UsageConflict,
     conv,
     hub::Storage,
     id::{self, TypedId, Valid},
@@ -151,6 +152,9 @@ impl PendingTransition<TextureState> {
         tex: &'a resource::Texture<B>,
     ) -> hal::memory::Barrier<'a, B> {
         tracing::trace!("\ttexture -> {:?}", self);
+        // Transitions always cover all the aspects, even for copies of a single aspect:
+        // the tracker keeps one state per subresource, and Vulkan requires both aspects
+        // of a depth-stencil image in a barrier unless separate layouts are enabled.
         let aspects = tex.aspects;
         hal::memory::Barrier::Image {
             states: conv::map_texture_state(self.usage.start, aspects)
@@ -491,6 +495,7 @@ pub(crate) struct TrackerSet {
     pub compute_pipes: ResourceTracker<PhantomData<id::ComputePipelineId>>,
     pub render_pipes: ResourceTracker<PhantomData<id::RenderPipelineId>>,
     pub bundles: ResourceTracker<PhantomData<id::RenderBundleId>>,
+    pub compute_bundles: ResourceTracker<PhantomData<id::ComputeBundleId>>,
 }
 
 impl TrackerSet {
@@ -505,6 +510,7 @@ impl TrackerSet {
             compute_pipes: ResourceTracker::new(backend),
             render_pipes: ResourceTracker::new(backend),
             bundles: ResourceTracker::new(backend),
+            compute_bundles: ResourceTracker::new(backend),
         }
     }
 
@@ -518,6 +524,7 @@ impl TrackerSet {
         self.compute_pipes.clear();
         self.render_pipes.clear();
         self.bundles.clear();
+        self.compute_bundles.clear();
     }
 
     /// Try to optimize the tracking representation.
@@ -530,13 +537,25 @@ impl TrackerSet {
         self.compute_pipes.optimize();
         self.render_pipes.optimize();
         self.bundles.optimize();
+        self.compute_bundles.optimize();
     }
 
     /// Merge all the trackers of another instance by extending
     /// the usage. Panics on a conflict.
     pub fn merge_extend(&mut self, other: &Self) {
-        self.buffers.merge_extend(&other.buffers).unwrap();
-        self.textures.merge_extend(&other.textures).unwrap();
+        self.try_merge_extend(other).unwrap();
+    }
+
+    /// Merge all the trackers of another instance by extending
+    /// the usage. Returns the first conflicting buffer or texture usage.
+    pub fn try_merge_extend(&mut self, other: &Self) -> Result<(), UsageConflict> {
+        self.buffers
+            .merge_extend(&other.buffers)
+            .map_err(|pending| UsageConflict::Buffer(pending.id.0, pending.usage))?;
+        self.textures
+            .merge_extend(&other.textures)
+            .map_err(|pending| UsageConflict::Texture(pending.id.0, pending.usage))?;
+        // The other resources are stateless, so they never conflict.
         self.views.merge_extend(&other.views).unwrap();
         self.bind_groups.merge_extend(&other.bind_groups).unwrap();
         self.samplers.merge_extend(&other.samplers).unwrap();
@@ -545,6 +564,10 @@ impl TrackerSet {
             .unwrap();
         self.render_pipes.merge_extend(&other.render_pipes).unwrap();
         self.bundles.merge_extend(&other.bundles).unwrap();
+        self.compute_bundles
+            .merge_extend(&other.compute_bundles)
+            .unwrap();
+        Ok(())
     }
 
     pub fn backend(&self) -> wgt::Backend {
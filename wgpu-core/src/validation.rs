@@ -2,7 +2,15 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::{binding_model::BindEntryMap, FastHashMap, MAX_BIND_GROUPS};
+use crate::{
+    binding_model::BindEntryMap,
+    pipeline::{
+        BindingReflection, EntryPointReflection, InterfaceReflection, PipelineConstantError,
+        PipelineConstantId, PipelineConstantValue, PipelineConstants, ShaderModuleReflection,
+        ShaderReflectionError,
+    },
+    FastHashMap, MAX_BIND_GROUPS,
+};
 use arrayvec::ArrayVec;
 use std::collections::hash_map::Entry;
 use thiserror::Error;
@@ -48,6 +56,32 @@ pub fn check_texture_usage(
     }
 }
 
+#[derive(Clone, Debug, Error)]
+pub enum LayoutError {
+    #[error("type {0:?} can't be stored in a buffer")]
+    UnsupportedType(naga::Handle<naga::Type>),
+    #[error("runtime-sized array {0:?} is not the last member of the buffer")]
+    UnboundArray(naga::Handle<naga::Type>),
+    #[error("member {index} of structure {ty:?} has no offset")]
+    MissingMemberOffset {
+        ty: naga::Handle<naga::Type>,
+        index: usize,
+    },
+    #[error("member {index} of structure {ty:?} at offset {offset} overlaps the previous member, which ends at {end}")]
+    OverlappingMember {
+        ty: naga::Handle<naga::Type>,
+        index: usize,
+        offset: wgt::BufferAddress,
+        end: wgt::BufferAddress,
+    },
+    #[error("stride {stride} of array {ty:?} is smaller than its element size {size}")]
+    InvalidArrayStride {
+        ty: naga::Handle<naga::Type>,
+        stride: wgt::BufferAddress,
+        size: wgt::BufferAddress,
+    },
+}
+
 #[derive(Clone, Debug, Error)]
 pub enum BindingError {
     #[error("binding is missing from the pipeline layout")]
@@ -60,6 +94,8 @@ pub enum BindingError {
     WrongType,
     #[error("buffer structure size {0}, added to one element of an unbound array, if it's the last field, ended up greater than the given `min_binding_size`")]
     WrongBufferSize(wgt::BufferAddress),
+    #[error("buffer structure layout is invalid: {0}")]
+    InvalidLayout(LayoutError),
     #[error("view dimension {dim:?} (is array: {is_array}) doesn't match the shader")]
     WrongTextureViewDimension {
         dim: naga::ImageDimension,
@@ -73,6 +109,12 @@ pub enum BindingError {
     WrongTextureMultisampled,
     #[error("comparison flag doesn't match the shader")]
     WrongSamplerComparison,
+    #[error("storage texture format doesn't match the format {0:?} declared by the shader")]
+    WrongStorageTextureFormat(wgt::TextureFormat),
+    #[error("storage texture access doesn't match the shader, which is {0:?}")]
+    WrongStorageTextureAccess(StorageImageAccess),
+    #[error("storage texture format isn't declared by the shader, so the pipeline needs an explicit layout")]
+    UnknownStorageTextureFormat,
     #[error("derived bind group layout type is not consistent between stages")]
     InconsistentlyDerivedType,
 }
@@ -105,71 +147,230 @@ pub enum StageError {
         location: wgt::ShaderLocation,
         error: InputError,
     },
+    #[error(transparent)]
+    Constant(#[from] PipelineConstantError),
+    #[error("workgroup size {size:?} exceeds the limit of {limit:?}")]
+    WorkgroupSizeExceeded { size: [u32; 3], limit: [u32; 3] },
+    #[error("workgroup has {invocations} invocations, exceeding the limit of {limit}")]
+    TooManyInvocations { invocations: u64, limit: u32 },
+    #[error("workgroup storage takes {size} bytes, exceeding the limit of {limit}")]
+    WorkgroupStorageExceeded {
+        size: wgt::BufferAddress,
+        limit: u32,
+    },
+}
+
+/// Rules for laying out data in buffers, depending on the kind of buffer.
+///
+/// They only fill in what the shader leaves out: explicit member offsets and
+/// array strides are taken as they are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LayoutRules {
+    /// Used by uniform buffers: arrays and structures are aligned to 16 bytes.
+    Std140,
+    /// Used by storage buffers.
+    Std430,
+}
+
+impl LayoutRules {
+    fn for_binding(ty: &BindingType) -> Option<Self> {
+        match *ty {
+            BindingType::UniformBuffer { .. } => Some(LayoutRules::Std140),
+            BindingType::StorageBuffer { .. } => Some(LayoutRules::Std430),
+            _ => None,
+        }
+    }
+
+    /// Guesses the rules from the storage class, when there is no binding to look at.
+    ///
+    /// SPIR-V storage buffers may be declared in the `Uniform` class, so this is
+    /// only an estimate for the implicit pipeline layouts.
+    fn for_class(class: naga::StorageClass) -> Option<Self> {
+        match class {
+            naga::StorageClass::Uniform => Some(LayoutRules::Std140),
+            naga::StorageClass::StorageBuffer => Some(LayoutRules::Std430),
+            _ => None,
+        }
+    }
+
+    fn aggregate_alignment(self, alignment: wgt::BufferAddress) -> wgt::BufferAddress {
+        match self {
+            LayoutRules::Std140 => round_up(alignment, 16),
+            LayoutRules::Std430 => alignment,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TypeLayout {
+    size: wgt::BufferAddress,
+    alignment: wgt::BufferAddress,
 }
 
-fn get_aligned_type_size(
+fn round_up(value: wgt::BufferAddress, alignment: wgt::BufferAddress) -> wgt::BufferAddress {
+    match value % alignment {
+        0 => value,
+        rem => value - rem + alignment,
+    }
+}
+
+/// Computes the layout of a type stored in a buffer.
+///
+/// If `allow_unbound` is set, the type may be (or end with) a runtime-sized
+/// array, which is then counted as having a single element.
+fn get_type_layout(
     module: &naga::Module,
     handle: naga::Handle<naga::Type>,
+    rules: LayoutRules,
     allow_unbound: bool,
-) -> wgt::BufferAddress {
+) -> Result<TypeLayout, LayoutError> {
     use naga::TypeInner as Ti;
-    //TODO: take alignment into account!
-    match module.types[handle].inner {
-        Ti::Scalar { kind: _, width } => width as wgt::BufferAddress,
+
+    fn vector_alignment(size: naga::VectorSize, width: naga::Bytes) -> wgt::BufferAddress {
+        let width = width as wgt::BufferAddress;
+        match size {
+            naga::VectorSize::Bi => 2 * width,
+            naga::VectorSize::Tri | naga::VectorSize::Quad => 4 * width,
+        }
+    }
+
+    Ok(match module.types[handle].inner {
+        Ti::Scalar { kind: _, width } => TypeLayout {
+            size: width as wgt::BufferAddress,
+            alignment: width as wgt::BufferAddress,
+        },
         Ti::Vector {
             size,
             kind: _,
             width,
-        } => size as wgt::BufferAddress * width as wgt::BufferAddress,
+        } => TypeLayout {
+            size: size as wgt::BufferAddress * width as wgt::BufferAddress,
+            alignment: vector_alignment(size, width),
+        },
         Ti::Matrix {
-            rows,
             columns,
+            rows,
             kind: _,
             width,
         } => {
-            rows as wgt::BufferAddress * columns as wgt::BufferAddress * width as wgt::BufferAddress
+            // Matrices are stored as arrays of column vectors.
+            let alignment = rules.aggregate_alignment(vector_alignment(rows, width));
+            TypeLayout {
+                size: columns as wgt::BufferAddress * alignment,
+                alignment,
+            }
         }
-        Ti::Pointer { .. } => 4,
-        Ti::Array {
-            base,
-            size: naga::ArraySize::Static(count),
-            stride,
-        } => {
-            let base_size = match stride {
-                Some(stride) => stride.get() as wgt::BufferAddress,
-                None => get_aligned_type_size(module, base, false),
-            };
-            base_size * count as wgt::BufferAddress
-        }
-        Ti::Array {
-            base,
-            size: naga::ArraySize::Dynamic,
-            stride,
-        } if allow_unbound => match stride {
-            Some(stride) => stride.get() as wgt::BufferAddress,
-            None => get_aligned_type_size(module, base, false),
-        },
-        Ti::Struct { ref members } => members.last().map_or(0, |member| {
-            let offset = match member.origin {
-                naga::MemberOrigin::BuiltIn(_) => {
-                    tracing::error!("Missing offset on a struct member");
-                    0 // TODO: make it a proper error
+        Ti::Array { base, size, stride } => {
+            let base_layout = get_type_layout(module, base, rules, false)?;
+            let alignment = rules.aggregate_alignment(base_layout.alignment);
+            let stride = match stride {
+                Some(stride) => {
+                    let stride = stride.get() as wgt::BufferAddress;
+                    if stride < base_layout.size {
+                        return Err(LayoutError::InvalidArrayStride {
+                            ty: handle,
+                            stride,
+                            size: base_layout.size,
+                        });
+                    }
+                    stride
                 }
-                naga::MemberOrigin::Offset(offset) => offset as wgt::BufferAddress,
+                None => round_up(base_layout.size, alignment),
+            };
+            let count = match size {
+                naga::ArraySize::Static(count) => count as wgt::BufferAddress,
+                naga::ArraySize::Dynamic if allow_unbound => 1,
+                naga::ArraySize::Dynamic => return Err(LayoutError::UnboundArray(handle)),
             };
-            offset + get_aligned_type_size(module, member.ty, false)
-        }),
-        _ => panic!("Unexpected struct field"),
+            TypeLayout {
+                size: count * stride,
+                alignment,
+            }
+        }
+        Ti::Struct { ref members } => {
+            let mut end = 0;
+            let mut alignment = 1;
+            for (index, member) in members.iter().enumerate() {
+                let offset = match member.origin {
+                    naga::MemberOrigin::Offset(offset) => offset as wgt::BufferAddress,
+                    naga::MemberOrigin::BuiltIn(_) => {
+                        return Err(LayoutError::MissingMemberOffset { ty: handle, index })
+                    }
+                };
+                let is_last = index + 1 == members.len();
+                let layout = get_type_layout(module, member.ty, rules, allow_unbound && is_last)?;
+                if offset < end {
+                    return Err(LayoutError::OverlappingMember {
+                        ty: handle,
+                        index,
+                        offset,
+                        end,
+                    });
+                }
+                end = offset + layout.size;
+                alignment = alignment.max(layout.alignment);
+            }
+            let alignment = rules.aggregate_alignment(alignment);
+            TypeLayout {
+                size: round_up(end, alignment),
+                alignment,
+            }
+        }
+        _ => return Err(LayoutError::UnsupportedType(handle)),
+    })
+}
+
+/// Returns the minimum size of a buffer bound to the given variable.
+fn get_buffer_binding_size(
+    module: &naga::Module,
+    var: &naga::GlobalVariable,
+    rules: LayoutRules,
+) -> Result<wgt::BufferAddress, BindingError> {
+    get_type_layout(module, var.ty, rules, true)
+        .map(|layout| layout.size)
+        .map_err(BindingError::InvalidLayout)
+}
+
+/// How a storage image is accessed by a shader.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StorageImageAccess {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+fn get_storage_image_access(
+    flags: naga::ImageFlags,
+    image: Option<&StorageImage>,
+) -> StorageImageAccess {
+    let (can_load, can_store) = match image {
+        Some(image) if image.non_readable || image.non_writable => {
+            (!image.non_readable, !image.non_writable)
+        }
+        // Without decorations, fall back to the access qualifier known to Naga.
+        // It isn't used by Vulkan shaders, so images without it are assumed
+        // to be read-only.
+        _ if flags.intersects(naga::ImageFlags::CAN_LOAD | naga::ImageFlags::CAN_STORE) => (
+            flags.contains(naga::ImageFlags::CAN_LOAD),
+            flags.contains(naga::ImageFlags::CAN_STORE),
+        ),
+        _ => (true, false),
+    };
+    match (can_load, can_store) {
+        (_, false) => StorageImageAccess::ReadOnly,
+        (false, true) => StorageImageAccess::WriteOnly,
+        (true, true) => StorageImageAccess::ReadWrite,
     }
 }
 
 fn check_binding_use(
     module: &naga::Module,
+    storage_image: Option<&StorageImage>,
     var: &naga::GlobalVariable,
     entry: &BindGroupLayoutEntry,
 ) -> Result<naga::GlobalUse, BindingError> {
     match module.types[var.ty].inner {
-        naga::TypeInner::Struct { ref members } => {
+        naga::TypeInner::Struct { .. } => {
             let (allowed_usage, min_size) = match entry.ty {
                 BindingType::UniformBuffer {
                     dynamic: _,
@@ -189,10 +390,8 @@ fn check_binding_use(
                 }
                 _ => return Err(BindingError::WrongType),
             };
-            let mut actual_size = 0;
-            for (i, member) in members.iter().enumerate() {
-                actual_size += get_aligned_type_size(module, member.ty, i + 1 == members.len());
-            }
+            let rules = LayoutRules::for_binding(&entry.ty).ok_or(BindingError::WrongType)?;
+            let actual_size = get_buffer_binding_size(module, var, rules)?;
             match min_size {
                 Some(non_zero) if non_zero.get() < actual_size => {
                     return Err(BindingError::WrongBufferSize(actual_size))
@@ -274,12 +473,20 @@ fn check_binding_use(
                     };
                     (naga::GlobalUse::LOAD, true)
                 }
-                BindingType::StorageTexture { readonly, .. } => {
-                    if readonly {
-                        //TODO: check entry.storage_texture_format
-                        (naga::GlobalUse::LOAD, false)
-                    } else {
-                        (naga::GlobalUse::STORE, false)
+                BindingType::StorageTexture {
+                    format, readonly, ..
+                } => {
+                    if let Some(shader_format) = storage_image.and_then(|image| image.format) {
+                        if shader_format != format {
+                            return Err(BindingError::WrongStorageTextureFormat(shader_format));
+                        }
+                    }
+                    let access = get_storage_image_access(flags, storage_image);
+                    match (access, readonly) {
+                        (StorageImageAccess::ReadOnly, true) => (naga::GlobalUse::LOAD, false),
+                        // Naga records any access to an image as a load.
+                        (StorageImageAccess::WriteOnly, false) => (naga::GlobalUse::all(), false),
+                        _ => return Err(BindingError::WrongStorageTextureAccess(access)),
                     }
                 }
                 _ => return Err(BindingError::WrongType),
@@ -683,6 +890,30 @@ pub fn check_texture_format(format: wgt::TextureFormat, output: &naga::TypeInner
 
 pub type StageInterface<'a> = FastHashMap<wgt::ShaderLocation, MaybeOwned<'a, naga::TypeInner>>;
 
+/// Returns the number of scalar components passed through a stage interface.
+///
+/// 64-bit scalars count as 2 components, and types without a known number
+/// of components count as a whole vector of 4.
+pub fn interface_component_count(interface: &StageInterface) -> u32 {
+    fn scalar_components(width: naga::Bytes) -> u32 {
+        (width as u32 / 4).max(1)
+    }
+    interface
+        .values()
+        .map(|ty| match **ty {
+            naga::TypeInner::Scalar { width, .. } => scalar_components(width),
+            naga::TypeInner::Vector { size, width, .. } => size as u32 * scalar_components(width),
+            naga::TypeInner::Matrix {
+                columns,
+                rows,
+                width,
+                ..
+            } => columns as u32 * rows as u32 * scalar_components(width),
+            _ => 4,
+        })
+        .sum()
+}
+
 pub enum IntrospectionBindGroupLayouts<'a> {
     Given(ArrayVec<[&'a BindEntryMap; MAX_BIND_GROUPS]>),
     Derived(&'a mut [BindEntryMap]),
@@ -690,17 +921,18 @@ pub enum IntrospectionBindGroupLayouts<'a> {
 
 fn derive_binding_type(
     module: &naga::Module,
+    spirv_info: &SpirvInfo,
     var: &naga::GlobalVariable,
+    set: u32,
+    binding: u32,
     usage: naga::GlobalUse,
 ) -> Result<BindingType, BindingError> {
     let ty = &module.types[var.ty];
     Ok(match ty.inner {
-        naga::TypeInner::Struct { ref members } => {
+        naga::TypeInner::Struct { .. } => {
             let dynamic = false;
-            let mut actual_size = 0;
-            for (i, member) in members.iter().enumerate() {
-                actual_size += get_aligned_type_size(module, member.ty, i + 1 == members.len());
-            }
+            let rules = LayoutRules::for_class(var.class).ok_or(BindingError::WrongType)?;
+            let actual_size = get_buffer_binding_size(module, var, rules)?;
             match var.class {
                 naga::StorageClass::Uniform => BindingType::UniformBuffer {
                     dynamic,
@@ -743,10 +975,18 @@ fn derive_binding_type(
                     multisampled: flags.contains(naga::ImageFlags::MULTISAMPLED),
                 }
             } else {
+                let storage_image = spirv_info.storage_images.get(&(set, binding));
+                let readonly = match get_storage_image_access(flags, storage_image) {
+                    StorageImageAccess::ReadOnly => true,
+                    StorageImageAccess::WriteOnly => false,
+                    access => return Err(BindingError::WrongStorageTextureAccess(access)),
+                };
                 BindingType::StorageTexture {
                     dimension,
-                    format: wgt::TextureFormat::Rgba8Unorm, //TODO
-                    readonly: !flags.contains(naga::ImageFlags::CAN_STORE),
+                    format: storage_image
+                        .and_then(|image| image.format)
+                        .ok_or(BindingError::UnknownStorageTextureFormat)?,
+                    readonly,
                 }
             }
         }
@@ -754,8 +994,72 @@ fn derive_binding_type(
     })
 }
 
+/// Checks the workgroup of a compute entry point against the device limits.
+///
+/// The workgroup size is taken from the SPIR-V code, with the specialization
+/// constants given by `constants`, and the storage used from `module`, if it's given.
+/// Naga doesn't keep workgroup sizes, so the size isn't checked for the code
+/// it generates.
+pub fn check_workgroup_limits(
+    module: Option<&naga::Module>,
+    spirv_info: &SpirvInfo,
+    entry_point_name: &str,
+    constants: &PipelineConstants,
+    limits: &wgt::Limits,
+) -> Result<(), StageError> {
+    if let Some(workgroup_size) = spirv_info.workgroup_sizes.get(entry_point_name) {
+        let size = spirv_info.specialize_workgroup_size(workgroup_size, constants);
+        let limit = [
+            limits.max_compute_workgroup_size_x,
+            limits.max_compute_workgroup_size_y,
+            limits.max_compute_workgroup_size_z,
+        ];
+        if size.iter().zip(&limit).any(|(size, limit)| size > limit) {
+            return Err(StageError::WorkgroupSizeExceeded { size, limit });
+        }
+        let invocations = size.iter().map(|&size| size as u64).product::<u64>();
+        if invocations > limits.max_compute_invocations_per_workgroup as u64 {
+            return Err(StageError::TooManyInvocations {
+                invocations,
+                limit: limits.max_compute_invocations_per_workgroup,
+            });
+        }
+    }
+
+    let module = match module {
+        Some(module) => module,
+        None => return Ok(()),
+    };
+    let entry_point = match module.entry_points.iter().find(|entry_point| {
+        entry_point.name == entry_point_name && entry_point.stage == naga::ShaderStage::Compute
+    }) {
+        Some(entry_point) => entry_point,
+        None => return Ok(()),
+    };
+    let function = &module.functions[entry_point.function];
+    let mut size = 0;
+    for ((_, var), &usage) in module.global_variables.iter().zip(&function.global_usage) {
+        if usage.is_empty() || var.class != naga::StorageClass::WorkGroup {
+            continue;
+        }
+        // The layout of workgroup storage is up to the implementation,
+        // std430 is a close estimate.
+        if let Ok(layout) = get_type_layout(module, var.ty, LayoutRules::Std430, false) {
+            size = round_up(size, layout.alignment) + layout.size;
+        }
+    }
+    if size > limits.max_compute_workgroup_storage_size as wgt::BufferAddress {
+        return Err(StageError::WorkgroupStorageExceeded {
+            size,
+            limit: limits.max_compute_workgroup_storage_size,
+        });
+    }
+    Ok(())
+}
+
 pub fn check_stage<'a>(
     module: &'a naga::Module,
+    spirv_info: &SpirvInfo,
     mut group_layouts: IntrospectionBindGroupLayouts,
     entry_point_name: &str,
     stage: naga::ShaderStage,
@@ -794,7 +1098,10 @@ pub fn check_stage<'a>(
                                 Err(BindingError::Invisible)
                             }
                         })
-                        .and_then(|entry| check_binding_use(module, var, entry))
+                        .and_then(|entry| {
+                            let storage_image = spirv_info.storage_images.get(&(set, binding));
+                            check_binding_use(module, storage_image, var, entry)
+                        })
                         .and_then(|allowed_usage| {
                             if allowed_usage.contains(usage) {
                                 Ok(())
@@ -805,9 +1112,10 @@ pub fn check_stage<'a>(
                     IntrospectionBindGroupLayouts::Derived(ref mut layouts) => layouts
                         .get_mut(set as usize)
                         .ok_or(BindingError::Missing)
-                        .and_then(|set| {
-                            let ty = derive_binding_type(module, var, usage)?;
-                            Ok(match set.entry(binding) {
+                        .and_then(|entries| {
+                            let ty =
+                                derive_binding_type(module, spirv_info, var, set, binding, usage)?;
+                            Ok(match entries.entry(binding) {
                                 Entry::Occupied(e) if e.get().ty != ty => {
                                     return Err(BindingError::InconsistentlyDerivedType)
                                 }
@@ -859,3 +1167,931 @@ pub fn check_stage<'a>(
     }
     Ok(outputs)
 }
+
+/// Storage image declared in SPIR-V code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StorageImage {
+    /// Texel format, if it's specified and has an equivalent texture format.
+    pub format: Option<wgt::TextureFormat>,
+    /// Decorated with `NonWritable`.
+    pub non_writable: bool,
+    /// Decorated with `NonReadable`.
+    pub non_readable: bool,
+}
+
+/// Scalar specialization constant declared in SPIR-V code.
+#[derive(Clone, Debug)]
+pub struct SpecConstant {
+    /// Value of the `SpecId` decoration.
+    pub id: u32,
+    /// Debug name given by `OpName`.
+    pub name: Option<String>,
+    pub kind: naga::ScalarKind,
+    /// Width in bytes.
+    pub width: naga::Bytes,
+}
+
+/// Workgroup size of a compute entry point declared in SPIR-V code.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WorkgroupSize {
+    /// Size with the default values of specialization constants.
+    pub size: [u32; 3],
+    /// `SpecId` of the specialization constant giving each dimension, if any.
+    pub spec_ids: [Option<u32>; 3],
+}
+
+/// Information extracted from SPIR-V code, which Naga doesn't keep yet.
+#[derive(Clone, Debug, Default)]
+pub struct SpirvInfo {
+    /// Workgroup sizes, per compute entry point. They are declared by the
+    /// `LocalSize` or `LocalSizeId` execution modes, or by a constant
+    /// decorated as the `WorkgroupSize` built-in, which applies to all of them.
+    pub workgroup_sizes: FastHashMap<String, WorkgroupSize>,
+    /// Storage images, per set and binding.
+    pub storage_images: FastHashMap<(u32, u32), StorageImage>,
+    /// Specialization constants that have a `SpecId`.
+    pub spec_constants: Vec<SpecConstant>,
+}
+
+impl SpirvInfo {
+    /// Returns the workgroup size with the given specialization constants applied.
+    pub(crate) fn specialize_workgroup_size(
+        &self,
+        workgroup_size: &WorkgroupSize,
+        constants: &PipelineConstants,
+    ) -> [u32; 3] {
+        let mut size = workgroup_size.size;
+        for (size, &spec_id) in size.iter_mut().zip(&workgroup_size.spec_ids) {
+            let spec_id = match spec_id {
+                Some(spec_id) => spec_id,
+                None => continue,
+            };
+            let name = self
+                .spec_constants
+                .iter()
+                .find(|constant| constant.id == spec_id)
+                .and_then(|constant| constant.name.clone());
+            let value = constants
+                .get(&PipelineConstantId::Numeric(spec_id))
+                .or_else(|| name.and_then(|name| constants.get(&PipelineConstantId::Named(name))));
+            match value {
+                Some(&PipelineConstantValue::Uint(value)) => *size = value,
+                Some(&PipelineConstantValue::Sint(value)) => *size = value as u32,
+                _ => {}
+            }
+        }
+        size
+    }
+}
+
+fn map_spirv_image_format(format: u32) -> Option<wgt::TextureFormat> {
+    use wgt::TextureFormat as Tf;
+    // Values of the SPIR-V `Image Format` enumeration.
+    Some(match format {
+        1 => Tf::Rgba32Float,
+        2 => Tf::Rgba16Float,
+        3 => Tf::R32Float,
+        4 => Tf::Rgba8Unorm,
+        5 => Tf::Rgba8Snorm,
+        6 => Tf::Rg32Float,
+        7 => Tf::Rg16Float,
+        8 => Tf::Rg11b10Float,
+        9 => Tf::R16Float,
+        11 => Tf::Rgb10a2Unorm,
+        13 => Tf::Rg8Unorm,
+        15 => Tf::R8Unorm,
+        18 => Tf::Rg8Snorm,
+        20 => Tf::R8Snorm,
+        21 => Tf::Rgba32Sint,
+        22 => Tf::Rgba16Sint,
+        23 => Tf::Rgba8Sint,
+        24 => Tf::R32Sint,
+        25 => Tf::Rg32Sint,
+        26 => Tf::Rg16Sint,
+        27 => Tf::Rg8Sint,
+        28 => Tf::R16Sint,
+        29 => Tf::R8Sint,
+        30 => Tf::Rgba32Uint,
+        31 => Tf::Rgba16Uint,
+        32 => Tf::Rgba8Uint,
+        33 => Tf::R32Uint,
+        35 => Tf::Rg32Uint,
+        36 => Tf::Rg16Uint,
+        37 => Tf::Rg8Uint,
+        38 => Tf::R16Uint,
+        39 => Tf::R8Uint,
+        _ => return None,
+    })
+}
+
+/// Scan the declarations of a SPIR-V module for the information Naga drops.
+pub fn scan_spirv(words: &[u32]) -> SpirvInfo {
+    const HEADER_WORDS: usize = 5;
+    const OP_NAME: u32 = 5;
+    const OP_ENTRY_POINT: u32 = 15;
+    const OP_EXECUTION_MODE: u32 = 16;
+    const OP_TYPE_BOOL: u32 = 20;
+    const OP_TYPE_INT: u32 = 21;
+    const OP_TYPE_FLOAT: u32 = 22;
+    const OP_TYPE_IMAGE: u32 = 25;
+    const OP_TYPE_POINTER: u32 = 32;
+    const OP_CONSTANT: u32 = 43;
+    const OP_CONSTANT_COMPOSITE: u32 = 44;
+    const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+    const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+    const OP_SPEC_CONSTANT: u32 = 50;
+    const OP_SPEC_CONSTANT_COMPOSITE: u32 = 51;
+    const OP_FUNCTION: u32 = 54;
+    const OP_VARIABLE: u32 = 59;
+    const OP_DECORATE: u32 = 71;
+    const OP_EXECUTION_MODE_ID: u32 = 331;
+    const EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+    const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+    const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+    const IMAGE_SAMPLED_STORAGE: u32 = 2;
+    const DECORATION_SPEC_ID: u32 = 1;
+    const DECORATION_BUILT_IN: u32 = 11;
+    const BUILT_IN_WORKGROUP_SIZE: u32 = 25;
+    const DECORATION_NON_WRITABLE: u32 = 24;
+    const DECORATION_NON_READABLE: u32 = 25;
+    const DECORATION_BINDING: u32 = 33;
+    const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+    #[derive(Default)]
+    struct Decorations {
+        set: Option<u32>,
+        binding: Option<u32>,
+        spec_id: Option<u32>,
+        non_writable: bool,
+        non_readable: bool,
+        workgroup_size: bool,
+    }
+
+    // Decodes a nul-terminated literal string.
+    fn read_string(words: &[u32]) -> Option<String> {
+        let bytes = words
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .take_while(|&byte| byte != 0)
+            .collect::<Vec<_>>();
+        String::from_utf8(bytes).ok()
+    }
+
+    let mut info = SpirvInfo::default();
+    let mut names = FastHashMap::default();
+    let mut debug_names = FastHashMap::default();
+    let mut scalars = FastHashMap::default();
+    let mut spec_constants = Vec::new();
+    let mut decorations = FastHashMap::<u32, Decorations>::default();
+    let mut image_formats = FastHashMap::default();
+    let mut pointers = FastHashMap::default();
+    let mut variables = Vec::new();
+    // Types and values of 32-bit integer constants.
+    let mut integers = FastHashMap::default();
+    let mut composites = FastHashMap::default();
+    let mut local_size_ids = Vec::new();
+    let mut offset = HEADER_WORDS;
+    while offset < words.len() {
+        let word_count = (words[offset] >> 16) as usize;
+        let op = words[offset] & 0xFFFF;
+        if word_count == 0 || offset + word_count > words.len() || op == OP_FUNCTION {
+            break;
+        }
+        let operands = &words[offset + 1..offset + word_count];
+        match op {
+            OP_NAME if operands.len() > 1 => {
+                if let Some(name) = read_string(&operands[1..]) {
+                    debug_names.insert(operands[0], name);
+                }
+            }
+            OP_ENTRY_POINT if operands.len() > 2 && operands[0] == EXECUTION_MODEL_GL_COMPUTE => {
+                if let Some(name) = read_string(&operands[2..]) {
+                    names.insert(operands[1], name);
+                }
+            }
+            OP_EXECUTION_MODE
+                if operands.len() == 5 && operands[1] == EXECUTION_MODE_LOCAL_SIZE =>
+            {
+                if let Some(name) = names.get(&operands[0]) {
+                    info.workgroup_sizes.insert(
+                        name.clone(),
+                        WorkgroupSize {
+                            size: [operands[2], operands[3], operands[4]],
+                            spec_ids: [None; 3],
+                        },
+                    );
+                }
+            }
+            OP_EXECUTION_MODE_ID
+                if operands.len() == 5 && operands[1] == EXECUTION_MODE_LOCAL_SIZE_ID =>
+            {
+                if let Some(name) = names.get(&operands[0]) {
+                    local_size_ids.push((name.clone(), [operands[2], operands[3], operands[4]]));
+                }
+            }
+            OP_DECORATE if operands.len() >= 2 => {
+                let decor = decorations.entry(operands[0]).or_default();
+                match (operands[1], operands.get(2)) {
+                    (DECORATION_DESCRIPTOR_SET, Some(&set)) => decor.set = Some(set),
+                    (DECORATION_BINDING, Some(&binding)) => decor.binding = Some(binding),
+                    (DECORATION_SPEC_ID, Some(&id)) => decor.spec_id = Some(id),
+                    (DECORATION_NON_WRITABLE, _) => decor.non_writable = true,
+                    (DECORATION_NON_READABLE, _) => decor.non_readable = true,
+                    (DECORATION_BUILT_IN, Some(&BUILT_IN_WORKGROUP_SIZE)) => {
+                        decor.workgroup_size = true
+                    }
+                    _ => {}
+                }
+            }
+            OP_TYPE_BOOL if operands.len() == 1 => {
+                scalars.insert(operands[0], (naga::ScalarKind::Bool, 4));
+            }
+            OP_TYPE_INT if operands.len() == 3 => {
+                let kind = if operands[2] != 0 {
+                    naga::ScalarKind::Sint
+                } else {
+                    naga::ScalarKind::Uint
+                };
+                scalars.insert(operands[0], (kind, operands[1] / 8));
+            }
+            OP_TYPE_FLOAT if operands.len() == 2 => {
+                scalars.insert(operands[0], (naga::ScalarKind::Float, operands[1] / 8));
+            }
+            OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE | OP_SPEC_CONSTANT
+                if operands.len() >= 2 =>
+            {
+                spec_constants.push((operands[1], operands[0]));
+                if op == OP_SPEC_CONSTANT && operands.len() == 3 {
+                    integers.insert(operands[1], (operands[0], operands[2]));
+                }
+            }
+            OP_CONSTANT if operands.len() == 3 => {
+                integers.insert(operands[1], (operands[0], operands[2]));
+            }
+            OP_CONSTANT_COMPOSITE | OP_SPEC_CONSTANT_COMPOSITE if operands.len() == 5 => {
+                composites.insert(operands[1], [operands[2], operands[3], operands[4]]);
+            }
+            OP_TYPE_IMAGE if operands.len() >= 8 && operands[6] == IMAGE_SAMPLED_STORAGE => {
+                image_formats.insert(operands[0], map_spirv_image_format(operands[7]));
+            }
+            OP_TYPE_POINTER if operands.len() == 3 => {
+                pointers.insert(operands[0], operands[2]);
+            }
+            OP_VARIABLE if operands.len() >= 3 => {
+                variables.push((operands[1], operands[0]));
+            }
+            _ => {}
+        }
+        offset += word_count;
+    }
+
+    for (id, pointer_type) in variables {
+        let format = match pointers
+            .get(&pointer_type)
+            .and_then(|ty| image_formats.get(ty))
+        {
+            Some(&format) => format,
+            None => continue,
+        };
+        if let Some(&Decorations {
+            set: Some(set),
+            binding: Some(binding),
+            non_writable,
+            non_readable,
+            ..
+        }) = decorations.get(&id)
+        {
+            info.storage_images.insert(
+                (set, binding),
+                StorageImage {
+                    format,
+                    non_writable,
+                    non_readable,
+                },
+            );
+        }
+    }
+
+    // Resolves the size given by the IDs of integer constants.
+    let workgroup_size = |ids: [u32; 3]| {
+        let mut workgroup_size = WorkgroupSize::default();
+        for (i, id) in ids.iter().enumerate() {
+            let &(ty, value) = integers.get(id)?;
+            match scalars.get(&ty) {
+                Some(&(naga::ScalarKind::Uint, 4)) | Some(&(naga::ScalarKind::Sint, 4)) => {}
+                _ => return None,
+            }
+            workgroup_size.size[i] = value;
+            workgroup_size.spec_ids[i] = decorations.get(id).and_then(|decor| decor.spec_id);
+        }
+        Some(workgroup_size)
+    };
+    for (name, ids) in local_size_ids {
+        if let Some(size) = workgroup_size(ids) {
+            info.workgroup_sizes.insert(name, size);
+        }
+    }
+    // The built-in takes precedence over the execution modes.
+    let built_in = composites
+        .iter()
+        .find(|&(id, _)| matches!(decorations.get(id), Some(decor) if decor.workgroup_size));
+    if let Some(size) = built_in.and_then(|(_, &ids)| workgroup_size(ids)) {
+        for name in names.values() {
+            info.workgroup_sizes.insert(name.clone(), size);
+        }
+    }
+
+    for (id, ty) in spec_constants {
+        let spec_id = match decorations.get(&id).and_then(|decor| decor.spec_id) {
+            Some(spec_id) => spec_id,
+            None => continue,
+        };
+        if let Some(&(kind, width)) = scalars.get(&ty) {
+            info.spec_constants.push(SpecConstant {
+                id: spec_id,
+                name: debug_names.get(&id).cloned(),
+                kind,
+                width: width as naga::Bytes,
+            });
+        }
+    }
+    info
+}
+
+fn map_vertex_type(ty: &naga::TypeInner) -> Option<wgt::VertexFormat> {
+    use naga::{ScalarKind as Sk, TypeInner as Ti, VectorSize as Vs};
+    use wgt::VertexFormat as Vf;
+    Some(match *ty {
+        Ti::Scalar { kind, width: 4 } => match kind {
+            Sk::Float => Vf::Float,
+            Sk::Uint => Vf::Uint,
+            Sk::Sint => Vf::Int,
+            Sk::Bool => return None,
+        },
+        Ti::Vector {
+            size,
+            kind,
+            width: 4,
+        } => match (kind, size) {
+            (Sk::Float, Vs::Bi) => Vf::Float2,
+            (Sk::Float, Vs::Tri) => Vf::Float3,
+            (Sk::Float, Vs::Quad) => Vf::Float4,
+            (Sk::Uint, Vs::Bi) => Vf::Uint2,
+            (Sk::Uint, Vs::Tri) => Vf::Uint3,
+            (Sk::Uint, Vs::Quad) => Vf::Uint4,
+            (Sk::Sint, Vs::Bi) => Vf::Int2,
+            (Sk::Sint, Vs::Tri) => Vf::Int3,
+            (Sk::Sint, Vs::Quad) => Vf::Int4,
+            (Sk::Bool, _) => return None,
+        },
+        _ => return None,
+    })
+}
+
+pub fn reflect_module(
+    module: &naga::Module,
+    spirv_info: &SpirvInfo,
+) -> Result<ShaderModuleReflection, ShaderReflectionError> {
+    let mut entry_points = Vec::with_capacity(module.entry_points.len());
+    for entry_point in module.entry_points.iter() {
+        let stage = match entry_point.stage {
+            naga::ShaderStage::Vertex => wgt::ShaderStage::VERTEX,
+            naga::ShaderStage::Fragment => wgt::ShaderStage::FRAGMENT,
+            naga::ShaderStage::Compute => wgt::ShaderStage::COMPUTE,
+        };
+        let workgroup_size = match entry_point.stage {
+            naga::ShaderStage::Compute => spirv_info
+                .workgroup_sizes
+                .get(&entry_point.name)
+                .map(|size| size.size),
+            _ => None,
+        };
+        let mut reflection = EntryPointReflection {
+            name: entry_point.name.clone(),
+            stage,
+            workgroup_size,
+            bindings: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+
+        let function = &module.functions[entry_point.function];
+        for ((_, var), &usage) in module.global_variables.iter().zip(&function.global_usage) {
+            if usage.is_empty() {
+                continue;
+            }
+            match var.binding {
+                Some(naga::Binding::Descriptor { set, binding }) => {
+                    let ty = derive_binding_type(module, spirv_info, var, set, binding, usage)
+                        .map_err(|error| ShaderReflectionError::Binding {
+                            set,
+                            binding,
+                            error,
+                        })?;
+                    reflection.bindings.push(BindingReflection {
+                        set,
+                        binding,
+                        name: var.name.clone(),
+                        ty,
+                        usage,
+                    });
+                }
+                Some(naga::Binding::Location(location)) => {
+                    let ty = module.types[var.ty].inner.clone();
+                    let is_output = usage.contains(naga::GlobalUse::STORE);
+                    let vertex_format = match entry_point.stage {
+                        naga::ShaderStage::Vertex if !is_output => map_vertex_type(&ty),
+                        _ => None,
+                    };
+                    let interface = InterfaceReflection {
+                        location,
+                        name: var.name.clone(),
+                        ty,
+                        vertex_format,
+                    };
+                    if is_output {
+                        reflection.outputs.push(interface);
+                    } else {
+                        reflection.inputs.push(interface);
+                    }
+                }
+                _ => {}
+            }
+        }
+        reflection.bindings.sort_by_key(|b| (b.set, b.binding));
+        reflection.inputs.sort_by_key(|i| i.location);
+        reflection.outputs.sort_by_key(|o| o.location);
+        entry_points.push(reflection);
+    }
+    Ok(ShaderModuleReflection { entry_points })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_type_layout, LayoutError, LayoutRules};
+
+    fn add_type(module: &mut naga::Module, inner: naga::TypeInner) -> naga::Handle<naga::Type> {
+        module.types.append(naga::Type { name: None, inner })
+    }
+
+    fn add_struct(
+        module: &mut naga::Module,
+        members: &[(u32, naga::Handle<naga::Type>)],
+    ) -> naga::Handle<naga::Type> {
+        let members = members
+            .iter()
+            .map(|&(offset, ty)| naga::StructMember {
+                name: None,
+                origin: naga::MemberOrigin::Offset(offset),
+                ty,
+            })
+            .collect();
+        add_type(module, naga::TypeInner::Struct { members })
+    }
+
+    fn empty_module() -> naga::Module {
+        naga::Module {
+            header: naga::Header {
+                version: (1, 0, 0),
+                generator: 0,
+            },
+            types: naga::Arena::new(),
+            constants: naga::Arena::new(),
+            global_variables: naga::Arena::new(),
+            functions: naga::Arena::new(),
+            entry_points: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn buffer_layouts() {
+        let mut module = empty_module();
+        let float = add_type(
+            &mut module,
+            naga::TypeInner::Scalar {
+                kind: naga::ScalarKind::Float,
+                width: 4,
+            },
+        );
+        let vec3 = add_type(
+            &mut module,
+            naga::TypeInner::Vector {
+                size: naga::VectorSize::Tri,
+                kind: naga::ScalarKind::Float,
+                width: 4,
+            },
+        );
+        let mat3 = add_type(
+            &mut module,
+            naga::TypeInner::Matrix {
+                columns: naga::VectorSize::Tri,
+                rows: naga::VectorSize::Tri,
+                kind: naga::ScalarKind::Float,
+                width: 4,
+            },
+        );
+        let floats = add_type(
+            &mut module,
+            naga::TypeInner::Array {
+                base: float,
+                size: naga::ArraySize::Static(4),
+                stride: None,
+            },
+        );
+        let unbound = add_type(
+            &mut module,
+            naga::TypeInner::Array {
+                base: vec3,
+                size: naga::ArraySize::Dynamic,
+                stride: None,
+            },
+        );
+
+        // vec3 is padded by the following scalar, matrix columns are vec4-aligned.
+        let uniforms = add_struct(&mut module, &[(0, vec3), (12, float), (16, mat3)]);
+        let layout = get_type_layout(&module, uniforms, LayoutRules::Std140, true).unwrap();
+        assert_eq!((layout.size, layout.alignment), (64, 16));
+
+        // Array elements are rounded up to 16 bytes in uniform buffers only.
+        let layout = get_type_layout(&module, floats, LayoutRules::Std140, false).unwrap();
+        assert_eq!(layout.size, 64);
+        let layout = get_type_layout(&module, floats, LayoutRules::Std430, false).unwrap();
+        assert_eq!(layout.size, 16);
+
+        let storage = add_struct(&mut module, &[(0, float), (16, unbound)]);
+        let layout = get_type_layout(&module, storage, LayoutRules::Std430, true).unwrap();
+        assert_eq!(layout.size, 32);
+        match get_type_layout(&module, storage, LayoutRules::Std430, false) {
+            Err(LayoutError::UnboundArray(ty)) => assert_eq!(ty, unbound),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // Explicit offsets are taken as they are, even if the rules would align them.
+        let packed = add_struct(&mut module, &[(0, float), (4, vec3)]);
+        let layout = get_type_layout(&module, packed, LayoutRules::Std430, true).unwrap();
+        assert_eq!(layout.size, 16);
+
+        let overlapping = add_struct(&mut module, &[(0, vec3), (8, float)]);
+        match get_type_layout(&module, overlapping, LayoutRules::Std430, true) {
+            Err(LayoutError::OverlappingMember {
+                index: 1, end: 12, ..
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn spirv_storage_array() {
+        // `buffer B { float data[]; }`, declared by SPIR-V in the `Uniform` class
+        // with `ArrayStride 4`.
+        let mut module = empty_module();
+        let float = add_type(
+            &mut module,
+            naga::TypeInner::Scalar {
+                kind: naga::ScalarKind::Float,
+                width: 4,
+            },
+        );
+        let data = add_type(
+            &mut module,
+            naga::TypeInner::Array {
+                base: float,
+                size: naga::ArraySize::Dynamic,
+                stride: std::num::NonZeroU32::new(4),
+            },
+        );
+        let block = add_struct(&mut module, &[(0, data)]);
+        let var = naga::GlobalVariable {
+            name: None,
+            class: naga::StorageClass::Uniform,
+            binding: Some(naga::Binding::Descriptor { set: 0, binding: 0 }),
+            ty: block,
+        };
+        let entry = wgt::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgt::ShaderStage::COMPUTE,
+            ty: wgt::BindingType::StorageBuffer {
+                dynamic: false,
+                min_binding_size: wgt::BufferSize::new(4),
+                readonly: false,
+            },
+            count: None,
+        };
+        let usage = super::check_binding_use(&module, None, &var, &entry).unwrap();
+        assert_eq!(usage, naga::GlobalUse::all());
+    }
+
+    #[test]
+    fn scan_spirv() {
+        let name = u32::from_le_bytes(*b"main");
+        let words = [
+            0x0723_0203,
+            0x0001_0000,
+            0,
+            13,
+            0,
+            // OpEntryPoint GLCompute %4 "main"
+            (5 << 16) | 15,
+            5,
+            4,
+            name,
+            0,
+            // OpExecutionMode %4 LocalSize 8 4 1
+            (6 << 16) | 16,
+            4,
+            17,
+            8,
+            4,
+            1,
+            // OpName %12 "scale"
+            (4 << 16) | 5,
+            12,
+            u32::from_le_bytes(*b"scal"),
+            u32::from_le_bytes(*b"e\0\0\0"),
+            // OpDecorate %10 DescriptorSet 0
+            (4 << 16) | 71,
+            10,
+            34,
+            0,
+            // OpDecorate %10 Binding 2
+            (4 << 16) | 71,
+            10,
+            33,
+            2,
+            // OpDecorate %10 NonReadable
+            (3 << 16) | 71,
+            10,
+            25,
+            // OpDecorate %12 SpecId 3
+            (4 << 16) | 71,
+            12,
+            1,
+            3,
+            // %6 = OpTypeImage %5 2D 0 0 0 2 Rgba8
+            (9 << 16) | 25,
+            6,
+            5,
+            1,
+            0,
+            0,
+            0,
+            2,
+            4,
+            // %7 = OpTypePointer UniformConstant %6
+            (4 << 16) | 32,
+            7,
+            0,
+            6,
+            // %10 = OpVariable %7 UniformConstant
+            (4 << 16) | 59,
+            7,
+            10,
+            0,
+            // %11 = OpTypeFloat 32
+            (3 << 16) | 22,
+            11,
+            32,
+            // %12 = OpSpecConstant %11 1.0
+            (4 << 16) | 50,
+            11,
+            12,
+            1.0f32.to_bits(),
+        ];
+        let info = super::scan_spirv(&words);
+        assert_eq!(
+            info.workgroup_sizes.get("main").map(|size| size.size),
+            Some([8, 4, 1])
+        );
+        assert_eq!(
+            info.storage_images.get(&(0, 2)),
+            Some(&super::StorageImage {
+                format: Some(wgt::TextureFormat::Rgba8Unorm),
+                non_writable: false,
+                non_readable: true,
+            })
+        );
+        let constant = &info.spec_constants[0];
+        assert_eq!(constant.id, 3);
+        assert_eq!(constant.name.as_deref(), Some("scale"));
+        assert_eq!(constant.kind, naga::ScalarKind::Float);
+        assert_eq!(constant.width, 4);
+    }
+
+    #[test]
+    fn storage_image_access() {
+        use super::{get_storage_image_access, StorageImage, StorageImageAccess};
+        use naga::ImageFlags;
+
+        let undecorated = StorageImage {
+            format: None,
+            non_writable: false,
+            non_readable: false,
+        };
+        let write_only = StorageImage {
+            non_readable: true,
+            ..undecorated
+        };
+        assert_eq!(
+            get_storage_image_access(ImageFlags::CAN_LOAD, Some(&write_only)),
+            StorageImageAccess::WriteOnly
+        );
+        // Undecorated images use the access qualifier, like the ones written by Naga.
+        assert_eq!(
+            get_storage_image_access(ImageFlags::CAN_STORE, Some(&undecorated)),
+            StorageImageAccess::WriteOnly
+        );
+        assert_eq!(
+            get_storage_image_access(
+                ImageFlags::CAN_LOAD | ImageFlags::CAN_STORE,
+                Some(&undecorated)
+            ),
+            StorageImageAccess::ReadWrite
+        );
+        assert_eq!(
+            get_storage_image_access(ImageFlags::empty(), Some(&undecorated)),
+            StorageImageAccess::ReadOnly
+        );
+        assert_eq!(
+            get_storage_image_access(ImageFlags::CAN_STORE, None),
+            StorageImageAccess::WriteOnly
+        );
+    }
+
+    #[test]
+    fn scan_spirv_workgroup_size() {
+        let name = u32::from_le_bytes(*b"main");
+        let mut words = vec![
+            0x0723_0203,
+            0x0001_0200,
+            0,
+            10,
+            0,
+            // OpEntryPoint GLCompute %1 "main"
+            (5 << 16) | 15,
+            5,
+            1,
+            name,
+            0,
+            // OpExecutionModeId %1 LocalSizeId %5 %6 %6
+            (6 << 16) | 331,
+            1,
+            38,
+            5,
+            6,
+            6,
+            // OpDecorate %6 SpecId 7
+            (4 << 16) | 71,
+            6,
+            1,
+            7,
+            // %2 = OpTypeInt 32 0
+            (4 << 16) | 21,
+            2,
+            32,
+            0,
+            // %5 = OpConstant %2 64
+            (4 << 16) | 43,
+            2,
+            5,
+            64,
+            // %6 = OpSpecConstant %2 1
+            (4 << 16) | 50,
+            2,
+            6,
+            1,
+        ];
+        let info = super::scan_spirv(&words);
+        let workgroup_size = info.workgroup_sizes["main"];
+        assert_eq!(
+            workgroup_size,
+            super::WorkgroupSize {
+                size: [64, 1, 1],
+                spec_ids: [None, Some(7), Some(7)],
+            }
+        );
+
+        let limits = wgt::Limits::default();
+        let mut constants = crate::pipeline::PipelineConstants::new();
+        constants.insert(
+            crate::pipeline::PipelineConstantId::Numeric(7),
+            crate::pipeline::PipelineConstantValue::Uint(4),
+        );
+        assert_eq!(
+            info.specialize_workgroup_size(&workgroup_size, &constants),
+            [64, 4, 4]
+        );
+        assert!(super::check_workgroup_limits(
+            None,
+            &info,
+            "main",
+            &crate::pipeline::PipelineConstants::new(),
+            &limits
+        )
+        .is_ok());
+        match super::check_workgroup_limits(None, &info, "main", &constants, &limits) {
+            Err(super::StageError::TooManyInvocations { invocations, .. }) => {
+                assert_eq!(invocations, 1024)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        words.extend_from_slice(&[
+            // OpDecorate %9 BuiltIn WorkgroupSize
+            (4 << 16) | 71,
+            9,
+            11,
+            25,
+            // %9 = OpSpecConstantComposite %3 %5 %5 %6
+            (6 << 16) | 51,
+            3,
+            9,
+            5,
+            5,
+            6,
+        ]);
+        // The built-in takes precedence over the execution mode.
+        let info = super::scan_spirv(&words);
+        assert_eq!(
+            info.workgroup_sizes["main"],
+            super::WorkgroupSize {
+                size: [64, 64, 1],
+                spec_ids: [None, None, Some(7)],
+            }
+        );
+    }
+
+    #[test]
+    fn interface_components() {
+        use super::MaybeOwned;
+        use naga::{ScalarKind, TypeInner, VectorSize};
+
+        let mut interface = super::StageInterface::default();
+        interface.insert(
+            0,
+            MaybeOwned::Owned(TypeInner::Scalar {
+                kind: ScalarKind::Float,
+                width: 4,
+            }),
+        );
+        interface.insert(
+            1,
+            MaybeOwned::Owned(TypeInner::Vector {
+                size: VectorSize::Tri,
+                kind: ScalarKind::Float,
+                width: 4,
+            }),
+        );
+        interface.insert(
+            2,
+            MaybeOwned::Owned(TypeInner::Vector {
+                size: VectorSize::Bi,
+                kind: ScalarKind::Float,
+                width: 8,
+            }),
+        );
+        interface.insert(
+            3,
+            MaybeOwned::Owned(TypeInner::Matrix {
+                columns: VectorSize::Bi,
+                rows: VectorSize::Tri,
+                kind: ScalarKind::Float,
+                width: 4,
+            }),
+        );
+        assert_eq!(super::interface_component_count(&interface), 1 + 3 + 4 + 6);
+    }
+
+    #[test]
+    fn workgroup_limits() {
+        let limits = wgt::Limits::default();
+        let constants = crate::pipeline::PipelineConstants::new();
+        let mut info = super::SpirvInfo::default();
+        for &(name, size) in &[
+            ("main", [16, 16, 1]),
+            ("wide", [512, 1, 1]),
+            ("large", [32, 16, 1]),
+        ] {
+            info.workgroup_sizes.insert(
+                name.to_string(),
+                super::WorkgroupSize {
+                    size,
+                    spec_ids: [None; 3],
+                },
+            );
+        }
+
+        assert!(super::check_workgroup_limits(None, &info, "main", &constants, &limits).is_ok());
+        match super::check_workgroup_limits(None, &info, "wide", &constants, &limits) {
+            Err(super::StageError::WorkgroupSizeExceeded { size, .. }) => {
+                assert_eq!(size, [512, 1, 1])
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        match super::check_workgroup_limits(None, &info, "large", &constants, &limits) {
+            Err(super::StageError::TooManyInvocations { invocations, .. }) => {
+                assert_eq!(invocations, 512)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
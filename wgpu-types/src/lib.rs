@@ -27,10 +27,14 @@ pub type DynamicOffset = u32;
 ///
 /// [`bytes_per_row`]: TextureDataLayout::bytes_per_row
 pub const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
-/// Bound uniform/storage buffer offsets must be aligned to this number.
+/// Bound uniform/storage buffer offsets must be aligned to this number, unless
+/// a device is requested with lower [`Limits::min_uniform_buffer_offset_alignment`]
+/// and [`Limits::min_storage_buffer_offset_alignment`].
 pub const BIND_BUFFER_ALIGNMENT: BufferAddress = 256;
 /// Buffer to buffer copy offsets and sizes must be aligned to this number.
 pub const COPY_BUFFER_ALIGNMENT: BufferAddress = 4;
+/// Offsets of mapped ranges obtained from a mapped buffer must be aligned to this number.
+pub const MAP_ALIGNMENT: BufferAddress = 8;
 /// Vertex buffer strides have to be aligned to this number.
 pub const VERTEX_STRIDE_ALIGNMENT: BufferAddress = 4;
 /// Alignment all push constants need
@@ -176,6 +180,9 @@ bitflags::bitflags! {
         /// on a system that doesn't, this can severely hinder performance. Only use if you understand
         /// the consequences.
         ///
+        /// On unified memory systems, this also allows buffers to be mapped persistently,
+        /// and buffers mapped at creation to be written to directly, without a staging copy.
+        ///
         /// Supported platforms:
         /// - All
         ///
@@ -289,7 +296,7 @@ bitflags::bitflags! {
 /// Represents the sets of limits an adapter/device supports.
 ///
 /// Limits "better" than the default must be supported by the adapter and requested when requesting
-/// a device. If limits "better" than the adapter supports are requested, requesting a device will panic.
+/// a device. If limits "better" than the adapter supports are requested, requesting a device will fail.
 /// Once a device is requested, you may only use resources up to the limits requested _even_ if the
 /// adapter supports "better" limits.
 ///
@@ -302,6 +309,7 @@ bitflags::bitflags! {
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "trace", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
+#[cfg_attr(any(feature = "trace", feature = "replay"), serde(default))]
 pub struct Limits {
     /// Amount of bind groups that can be attached to a pipeline at the same time. Defaults to 4. Higher is "better".
     pub max_bind_groups: u32,
@@ -331,6 +339,47 @@ pub struct Limits {
     /// - DX11 & OpenGL don't natively support push constants, and are emulated with uniforms,
     ///   so this number is less useful.
     pub max_push_constant_size: u32,
+    /// Maximum size of the X dimension of a compute workgroup. Defaults to 256. Higher is "better".
+    pub max_compute_workgroup_size_x: u32,
+    /// Maximum size of the Y dimension of a compute workgroup. Defaults to 256. Higher is "better".
+    pub max_compute_workgroup_size_y: u32,
+    /// Maximum size of the Z dimension of a compute workgroup. Defaults to 64. Higher is "better".
+    pub max_compute_workgroup_size_z: u32,
+    /// Maximum number of invocations in a compute workgroup, i.e. the product of its
+    /// dimensions. Defaults to 256. Higher is "better".
+    pub max_compute_invocations_per_workgroup: u32,
+    /// Maximum number of workgroups dispatched in any dimension. Defaults to 65535. Higher is "better".
+    pub max_compute_workgroups_per_dimension: u32,
+    /// Maximum size in bytes of the workgroup storage used by a compute shader.
+    /// Defaults to 16384. Higher is "better".
+    pub max_compute_workgroup_storage_size: u32,
+    /// Maximum width of a 1D texture. Defaults to 8192. Higher is "better".
+    pub max_texture_dimension_1d: u32,
+    /// Maximum width and height of a 2D texture. Defaults to 8192. Higher is "better".
+    pub max_texture_dimension_2d: u32,
+    /// Maximum width, height and depth of a 3D texture. Defaults to 2048. Higher is "better".
+    pub max_texture_dimension_3d: u32,
+    /// Maximum number of layers of a 1D or 2D texture. Defaults to 2048. Higher is "better".
+    pub max_texture_array_layers: u32,
+    /// Amount of vertex buffers used by a render pipeline. Defaults to 8. Higher is "better".
+    pub max_vertex_buffers: u32,
+    /// Amount of vertex attributes used by a render pipeline, across all vertex buffers.
+    /// Defaults to 16. Higher is "better".
+    pub max_vertex_attributes: u32,
+    /// Maximum stride of a vertex buffer in bytes. Defaults to 2048. Higher is "better".
+    pub max_vertex_buffer_array_stride: u32,
+    /// Maximum size in bytes of a binding to a storage buffer. Defaults to 134217728 (128 MiB).
+    /// Higher is "better".
+    pub max_storage_buffer_binding_size: u32,
+    /// Amount of scalar components passed from the vertex to the fragment stage.
+    /// Defaults to 60. Higher is "better".
+    pub max_inter_stage_shader_components: u32,
+    /// Required alignment in bytes of the offsets of uniform buffer bindings, including
+    /// dynamic offsets. Defaults to [`BIND_BUFFER_ALIGNMENT`]. Lower is "better".
+    pub min_uniform_buffer_offset_alignment: u32,
+    /// Required alignment in bytes of the offsets of storage buffer bindings, including
+    /// dynamic offsets. Defaults to [`BIND_BUFFER_ALIGNMENT`]. Lower is "better".
+    pub min_storage_buffer_offset_alignment: u32,
 }
 
 impl Default for Limits {
@@ -346,10 +395,48 @@ impl Default for Limits {
             max_uniform_buffers_per_shader_stage: 12,
             max_uniform_buffer_binding_size: 16384,
             max_push_constant_size: 0,
+            max_compute_workgroup_size_x: 256,
+            max_compute_workgroup_size_y: 256,
+            max_compute_workgroup_size_z: 64,
+            max_compute_invocations_per_workgroup: 256,
+            max_compute_workgroups_per_dimension: 65535,
+            max_compute_workgroup_storage_size: 16384,
+            max_texture_dimension_1d: 8192,
+            max_texture_dimension_2d: 8192,
+            max_texture_dimension_3d: 2048,
+            max_texture_array_layers: 2048,
+            max_vertex_buffers: 8,
+            max_vertex_attributes: 16,
+            max_vertex_buffer_array_stride: 2048,
+            max_storage_buffer_binding_size: 128 << 20,
+            max_inter_stage_shader_components: 60,
+            min_uniform_buffer_offset_alignment: BIND_BUFFER_ALIGNMENT as u32,
+            min_storage_buffer_offset_alignment: BIND_BUFFER_ALIGNMENT as u32,
         }
     }
 }
 
+/// Level of validation applied to shader modules and to the pipelines using them.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "trace", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum ShaderValidation {
+    /// Shaders are not validated. Pipeline interfaces are not checked either.
+    Off,
+    /// Shaders are validated if they can be parsed. Shaders using features
+    /// the parser doesn't support yet are used without validation.
+    BestEffort,
+    /// Shaders that can't be parsed are rejected.
+    Strict,
+}
+
+impl Default for ShaderValidation {
+    fn default() -> Self {
+        ShaderValidation::Off
+    }
+}
+
 /// Describes a [`Device`].
 #[repr(C)]
 #[derive(Clone, Debug, Default)]
@@ -357,14 +444,18 @@ impl Default for Limits {
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 pub struct DeviceDescriptor {
     /// Features that the device should support. If any feature is not supported by
-    /// the adapter, creating a device will panic.
+    /// the adapter, creating a device fails with `UnsupportedFeature`.
     pub features: Features,
     /// Limits that the device should support. If any limit is "better" than the limit exposed by
-    /// the adapter, creating a device will panic.
+    /// the adapter, creating a device fails with `LimitsExceeded`.
     pub limits: Limits,
-    /// Switch shader validation on/off. This is a temporary field
-    /// that will be removed once our validation logic is complete.
-    pub shader_validation: bool,
+    /// How strictly shader modules are validated.
+    pub shader_validation: ShaderValidation,
+    /// Maximum number of submissions that can be in flight on the GPU.
+    /// `Queue::submit` blocks until an older submission is done when the limit is reached.
+    /// `None` means there is no limit.
+    #[cfg_attr(any(feature = "trace", feature = "replay"), serde(default))]
+    pub max_submissions_in_flight: Option<NonZeroU32>,
 }
 
 bitflags::bitflags! {
@@ -1197,19 +1288,26 @@ impl<L> BufferDescriptor<L> {
 pub struct CommandEncoderDescriptor<L> {
     /// Debug label for the command encoder. This will show up in graphics debuggers for easy identification.
     pub label: L,
+    /// If true, the command buffer can be marked as reusable when finished.
+    /// Otherwise, it's recorded for a single submission.
+    pub reusable: bool,
 }
 
 impl<L> CommandEncoderDescriptor<L> {
     pub fn map_label<K>(&self, fun: impl FnOnce(&L) -> K) -> CommandEncoderDescriptor<K> {
         CommandEncoderDescriptor {
             label: fun(&self.label),
+            reusable: self.reusable,
         }
     }
 }
 
 impl<T> Default for CommandEncoderDescriptor<Option<T>> {
     fn default() -> Self {
-        Self { label: None }
+        Self {
+            label: None,
+            reusable: false,
+        }
     }
 }
 
@@ -1526,12 +1624,18 @@ pub struct PushConstantRange {
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 pub struct CommandBufferDescriptor<L> {
     pub label: L,
+    /// If true, the command buffer is not consumed by a submission, and can be
+    /// submitted again once the previous submission of it is done.
+    ///
+    /// Requires the encoder to be created with `CommandEncoderDescriptor::reusable`.
+    pub reusable: bool,
 }
 
 impl<L> CommandBufferDescriptor<L> {
     pub fn map_label<K>(&self, fun: impl FnOnce(&L) -> K) -> CommandBufferDescriptor<K> {
         CommandBufferDescriptor {
             label: fun(&self.label),
+            reusable: self.reusable,
         }
     }
 }
@@ -1560,6 +1664,30 @@ impl<T> Default for RenderBundleDescriptor<Option<T>> {
     }
 }
 
+/// Describes a [`ComputeBundle`].
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "trace", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct ComputeBundleDescriptor<L> {
+    /// Debug label of the compute bundle encoder. This will show up in graphics debuggers for easy identification.
+    pub label: L,
+}
+
+impl<L> ComputeBundleDescriptor<L> {
+    pub fn map_label<K>(&self, fun: impl FnOnce(&L) -> K) -> ComputeBundleDescriptor<K> {
+        ComputeBundleDescriptor {
+            label: fun(&self.label),
+        }
+    }
+}
+
+impl<T> Default for ComputeBundleDescriptor<Option<T>> {
+    fn default() -> Self {
+        Self { label: None }
+    }
+}
+
 /// Type of data shaders will read from a texture.
 ///
 /// Only relevant for [`BindingType::SampledTexture`] bindings. See [`TextureFormat`] for more information.
@@ -1820,4 +1948,14 @@ pub struct TextureCopyView<T> {
     pub mip_level: u32,
     /// The base texel of the texture in the selected `mip_level`.
     pub origin: Origin3d,
+    /// The aspect of the texture to be copied to/from.
+    ///
+    /// Copies between a buffer and a `Depth24PlusStencil8` texture have to select
+    /// [`TextureAspect::StencilOnly`], with stencil texels taking 1 byte in the buffer.
+    /// The depth of `Depth24Plus` and `Depth24PlusStencil8` textures can't be copied
+    /// to or from a buffer, since its representation is backend-specific.
+    ///
+    /// Only the selected aspect is copied, but the texture is transitioned as a whole.
+    #[cfg_attr(any(feature = "trace", feature = "replay"), serde(default))]
+    pub aspect: TextureAspect,
 }